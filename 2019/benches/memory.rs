@@ -0,0 +1,97 @@
+use std::hint::black_box;
+use std::time::{Duration, Instant};
+use aoc2019::{IntcodeMachine, MachineState, Memory, ParamMode, Program, SparseMemory, Value, VecMemory};
+use num::BigInt;
+use itertools::Itertools;
+
+// Compiled versions of the example programs in the compile module's tests.
+include!("../src/compile/examples.rs");
+
+// Counts mem[100] up to 100000 and outputs it.
+const COUNTING_LOOP: &str = "1101,0,0,100,1001,100,1,100,1007,100,100000,101,1005,101,4,4,100,99";
+const DAY2_EXAMPLE: &str = "1,9,10,3,2,3,11,0,99,30,40,50,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0";
+const DAY7_FEEDBACK: &str = "3,26,1001,26,-4,26,3,27,1002,27,2,27,1,27,26,27,4,27,1001,28,-1,28,1005,28,6,99,0,0,5";
+
+fn counting_loop<M: Memory>(program: &Program) {
+    let mut machine = IntcodeMachine::<M>::with_program(program);
+    machine.compute().unwrap();
+    black_box(machine.get_output().unwrap());
+}
+
+fn compiled_counting_loop() {
+    let mut machine = counting_loop::machine();
+    machine.compute().unwrap();
+    black_box(machine.get_outputs());
+}
+
+fn noun_verb_search<M: Memory<Value = Value>>(program: &Program) {
+    let template = IntcodeMachine::with_memory(M::from_values(program));
+    for noun in 0..100 {
+        for verb in 0..100 {
+            let mut machine = template.clone();
+            machine.set_memory(ParamMode::Position, 1, noun).unwrap();
+            machine.set_memory(ParamMode::Position, 2, verb).unwrap();
+            // Some noun/verb pairs make the program crash, we only care about the work done.
+            let _ = machine.compute();
+            black_box(machine.get_memory(0));
+        }
+    }
+}
+
+fn phase_search<M: Memory<Value = Value>>(program: &Program) {
+    let template = IntcodeMachine::with_memory(M::from_values(program));
+    let mut max = 0;
+    for perm in (5..10).permutations(5) {
+        let mut amplifiers: Vec<IntcodeMachine<M>> = perm.iter().map(|phase| {
+            let mut amplifier = template.clone();
+            amplifier.push_input(*phase);
+            amplifier
+        }).collect();
+        amplifiers[0].push_input(0);
+        let signal = 'outer: loop {
+            for pos in 0..amplifiers.len() {
+                let state = amplifiers[pos].compute().unwrap();
+                let signal = amplifiers[pos].get_outputs_and_clear()[0];
+                amplifiers[(pos + 1) % perm.len()].push_input(signal);
+                if pos == perm.len() - 1 && state == MachineState::Done {
+                    break 'outer signal;
+                }
+            }
+        };
+        max = max.max(signal);
+    }
+    black_box(max);
+}
+
+fn bench(name: &str, iterations: u32, f: impl Fn()) -> Duration {
+    f();
+    let start = Instant::now();
+    for _ in 0..iterations {
+        f();
+    }
+    let per_iteration = start.elapsed() / iterations;
+    println!("{:<30} {:>12.3?} per iteration", name, per_iteration);
+    per_iteration
+}
+
+fn compare(name: &str, iterations: u32, vec: impl Fn(), sparse: impl Fn()) {
+    let vec = bench(&format!("{} (vec)", name), iterations, vec);
+    let sparse = bench(&format!("{} (sparse)", name), iterations, sparse);
+    println!("{:<30} {:>12.2}x\n", "speedup", sparse.as_secs_f64() / vec.as_secs_f64());
+}
+
+fn main() {
+    let counting: Program = COUNTING_LOOP.parse().unwrap();
+    let day2: Program = DAY2_EXAMPLE.parse().unwrap();
+    let day7: Program = DAY7_FEEDBACK.parse().unwrap();
+
+    compare("counting loop", 10, || counting_loop::<VecMemory>(&counting), || counting_loop::<SparseMemory>(&counting));
+    let interpreted = bench("counting loop (interpreted)", 10, || counting_loop::<VecMemory>(&counting));
+    let compiled = bench("counting loop (compiled)", 10, compiled_counting_loop);
+    println!("{:<30} {:>12.2}x\n", "speedup", interpreted.as_secs_f64() / compiled.as_secs_f64());
+    bench("counting loop (i128)", 10, || counting_loop::<VecMemory<i128>>(&counting));
+    bench("counting loop (bignum)", 10, || counting_loop::<VecMemory<BigInt>>(&counting));
+    println!();
+    compare("noun/verb search", 10, || noun_verb_search::<VecMemory>(&day2), || noun_verb_search::<SparseMemory>(&day2));
+    compare("phase setting search", 10, || phase_search::<VecMemory>(&day7), || phase_search::<SparseMemory>(&day7));
+}
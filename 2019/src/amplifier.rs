@@ -0,0 +1,173 @@
+use std::error::Error;
+use std::fmt;
+use std::sync::Mutex;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::thread;
+use itertools::Itertools;
+use crate::{IntcodeMachine, Value};
+use crate::network::{IntcodeNetwork, MachineId, NetworkError, NetworkState};
+
+/// How many permutations a worker takes at once.
+const BATCH_SIZE: usize = 64;
+
+/// An output with the index of the permutation that produced it, and the permutation itself.
+type Ranked = (Value, usize, Vec<Value>);
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Topology {
+    /// Each amplifier feeds the next one, the last one produces the result.
+    Linear,
+    /// Like `Linear`, but the last amplifier also feeds the first one, until all of them halt.
+    Feedback,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum AmplifierError {
+    Machine(NetworkError),
+    Stuck { waiting: Vec<MachineId> },
+    NoOutput,
+}
+
+impl fmt::Display for AmplifierError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Self::Machine(error) => write!(f, "amplifier failed: {}", error),
+            Self::Stuck { waiting } => write!(f, "amplifiers {:?} are stuck waiting for input", waiting),
+            Self::NoOutput => write!(f, "the last amplifier produced no output"),
+        }
+    }
+}
+
+impl Error for AmplifierError {}
+
+impl From<NetworkError> for AmplifierError {
+    fn from(error: NetworkError) -> Self {
+        Self::Machine(error)
+    }
+}
+
+/// Runs one amplifier per phase setting, each a copy of `template`, and returns the last amplifier's final output.
+pub fn run_amplifiers(template: &IntcodeMachine, phase_settings: &[Value], topology: Topology) -> Result<Value, AmplifierError> {
+    let amplifiers = phase_settings.iter().map(|phase| {
+        let mut amplifier = template.clone();
+        amplifier.push_input(*phase);
+        amplifier
+    }).collect();
+    let mut network = match topology {
+        Topology::Linear => IntcodeNetwork::pipeline(amplifiers),
+        Topology::Feedback => IntcodeNetwork::ring(amplifiers),
+    };
+    if network.is_empty() {
+        return Err(AmplifierError::NoOutput);
+    }
+    network.push_input(0, 0);
+    if let NetworkState::Idle { waiting } = network.run()? {
+        return Err(AmplifierError::Stuck { waiting });
+    }
+    match network.last_output() {
+        Some((id, value)) if id == network.len() - 1 => Ok(value),
+        _ => Err(AmplifierError::NoOutput),
+    }
+}
+
+/// Tries every assignment of distinct phases to the amplifiers, in parallel.
+#[derive(Clone, Debug)]
+pub struct PhaseSearch {
+    phases: Vec<Value>,
+    amplifiers: usize,
+    topology: Topology,
+    threads: usize,
+}
+
+impl PhaseSearch {
+    pub fn new(phases: impl IntoIterator<Item = Value>, amplifiers: usize, topology: Topology) -> Self {
+        let threads = thread::available_parallelism().map_or(1, |threads| threads.get());
+        Self { phases: phases.into_iter().collect(), amplifiers, topology, threads }
+    }
+
+    pub fn set_threads(&mut self, threads: usize) {
+        self.threads = threads.max(1);
+    }
+
+    /// The `count` phase settings with the highest outputs, highest first. Settings with the same output are
+    /// ordered like the permutations of the phases.
+    pub fn best(&self, template: &IntcodeMachine, count: usize) -> Result<Vec<(Value, Vec<Value>)>, AmplifierError> {
+        // Itertools' permutations don't stay exhausted once they have returned `None`, so they need fusing.
+        let permutations = Mutex::new(self.phases.iter().copied().permutations(self.amplifiers).fuse().enumerate());
+        let failed = AtomicBool::new(false);
+        let results: Vec<Result<Vec<Ranked>, AmplifierError>> = thread::scope(|scope| {
+            let workers: Vec<_> = (0..self.threads).map(|_| scope.spawn(|| {
+                let mut best = vec![];
+                while !failed.load(Ordering::Relaxed) {
+                    let batch: Vec<(usize, Vec<Value>)> = permutations.lock().unwrap().by_ref().take(BATCH_SIZE).collect();
+                    if batch.is_empty() {
+                        break;
+                    }
+                    for (index, phase_settings) in batch {
+                        let output = run_amplifiers(template, &phase_settings, self.topology).inspect_err(|_| failed.store(true, Ordering::Relaxed))?;
+                        best.push((output, index, phase_settings));
+                    }
+                    keep_best(&mut best, count);
+                }
+                Ok(best)
+            })).collect();
+            workers.into_iter().map(|worker| worker.join().unwrap()).collect()
+        });
+        let mut best = vec![];
+        for result in results {
+            best.extend(result?);
+        }
+        keep_best(&mut best, count);
+        Ok(best.into_iter().map(|(output, _, phase_settings)| (output, phase_settings)).collect())
+    }
+}
+
+fn keep_best(results: &mut Vec<Ranked>, count: usize) {
+    results.sort_by_key(|&(output, index, _)| (std::cmp::Reverse(output), index));
+    results.truncate(count);
+}
+
+#[cfg(test)]
+const FEEDBACK_EXAMPLE: &str = "3,26,1001,26,-4,26,3,27,1002,27,2,27,1,27,26,27,4,27,1001,28,-1,28,1005,28,6,99,0,0,5";
+
+#[test]
+fn test_run_amplifiers() {
+    let template = IntcodeMachine::from_string("3,15,3,16,1002,16,10,16,1,16,15,15,4,15,99,0,0").unwrap();
+    assert_eq!(run_amplifiers(&template, &[4,3,2,1,0], Topology::Linear), Ok(43210));
+    let template = IntcodeMachine::from_string(FEEDBACK_EXAMPLE).unwrap();
+    assert_eq!(run_amplifiers(&template, &[9,8,7,6,5], Topology::Feedback), Ok(139629729));
+}
+
+#[test]
+fn test_top_n() {
+    // Outputs 10 * input + phase, so the best settings put the highest phases first.
+    let template = IntcodeMachine::from_string("3,15,3,16,1002,16,10,16,1,16,15,15,4,15,99,0,0").unwrap();
+    let mut search = PhaseSearch::new(0..4, 3, Topology::Linear);
+    search.set_threads(3);
+    assert_eq!(search.best(&template, 3), Ok(vec![
+        (321, vec![3,2,1]),
+        (320, vec![3,2,0]),
+        (312, vec![3,1,2]),
+    ]));
+}
+
+#[test]
+fn test_thread_count_does_not_matter() {
+    let template = IntcodeMachine::from_string(FEEDBACK_EXAMPLE).unwrap();
+    let mut search = PhaseSearch::new(5..10, 5, Topology::Feedback);
+    search.set_threads(1);
+    let serial = search.best(&template, 10).unwrap();
+    search.set_threads(4);
+    assert_eq!(search.best(&template, 10).unwrap(), serial);
+    assert_eq!(serial[0], (139629729, vec![9,8,7,6,5]));
+}
+
+#[test]
+fn test_search_errors() {
+    let template = IntcodeMachine::from_string("3,0,42").unwrap();
+    assert_eq!(PhaseSearch::new(0..3, 2, Topology::Linear).best(&template, 1), Err(AmplifierError::Machine(NetworkError {
+        machine: 0, error: crate::IntcodeError::UnknownOpcode { opcode: 42, ip: 2 },
+    })));
+    let template = IntcodeMachine::from_string("3,0,3,0,99").unwrap();
+    assert_eq!(run_amplifiers(&template, &[1,2], Topology::Linear), Err(AmplifierError::Stuck { waiting: vec![1] }));
+}
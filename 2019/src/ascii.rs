@@ -0,0 +1,87 @@
+use std::fmt;
+use crate::{IntcodeMachine, Memory, Value};
+
+/// A run of output values, either ASCII text or a single value outside of the ASCII range, like a puzzle answer.
+#[derive(Clone, Debug, PartialEq)]
+pub enum AsciiOutput {
+    Text(String),
+    Value(Value),
+}
+
+impl fmt::Display for AsciiOutput {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            AsciiOutput::Text(text) => write!(f, "{}", text),
+            AsciiOutput::Value(value) => writeln!(f, "{}", value),
+        }
+    }
+}
+
+pub fn is_ascii(value: Value) -> bool {
+    (0..=127).contains(&value)
+}
+
+/// The character codes of a line of text, ending in a single `\n` whether the line ends in `\n`, `\r\n` or
+/// neither.
+pub fn encode_line(line: &str) -> Vec<Value> {
+    let line = line.strip_suffix('\n').unwrap_or(line);
+    let line = line.strip_suffix('\r').unwrap_or(line);
+    let mut values: Vec<Value> = line.bytes().map(Value::from).collect();
+    values.push(Value::from(b'\n'));
+    values
+}
+
+/// Groups consecutive ASCII values into text, other values are passed through on their own.
+pub fn decode(values: &[Value]) -> Vec<AsciiOutput> {
+    let mut outputs = vec![];
+    for &value in values {
+        match (outputs.last_mut(), is_ascii(value)) {
+            (Some(AsciiOutput::Text(text)), true) => text.push(value as u8 as char),
+            (_, true) => outputs.push(AsciiOutput::Text((value as u8 as char).to_string())),
+            (_, false) => outputs.push(AsciiOutput::Value(value)),
+        }
+    }
+    outputs
+}
+
+impl<M: Memory<Value = Value>> IntcodeMachine<M> {
+    /// Queues a line of text as input, see `encode_line`.
+    pub fn push_line(&mut self, line: &str) {
+        self.input.extend(encode_line(line));
+    }
+
+    pub fn get_ascii_outputs_and_clear(&mut self) -> Vec<AsciiOutput> {
+        decode(&self.get_outputs_and_clear())
+    }
+}
+
+#[test]
+fn test_encode_line() {
+    assert_eq!(encode_line("NOT A J"), vec![78, 79, 84, 32, 65, 32, 74, 10]);
+    assert_eq!(encode_line("WALK\n"), vec![87, 65, 76, 75, 10]);
+    assert_eq!(encode_line(""), vec![10]);
+    assert_eq!(encode_line("WALK\r\n"), vec![87, 65, 76, 75, 10]);
+}
+
+#[test]
+fn test_decode() {
+    assert_eq!(decode(&[]), vec![]);
+    assert_eq!(decode(&[72, 105, 10, 1234567, 33, -1]), vec![
+        AsciiOutput::Text("Hi\n".to_string()),
+        AsciiOutput::Value(1234567),
+        AsciiOutput::Text("!".to_string()),
+        AsciiOutput::Value(-1),
+    ]);
+    assert_eq!(decode(&[79, 75, 128]).iter().map(|output| output.to_string()).collect::<String>(), "OK128\n");
+}
+
+#[test]
+fn test_machine_lines() {
+    // in [7]; out [7]; jt #1, #0
+    let mut machine = IntcodeMachine::from_program(&"3,7,4,7,1105,1,0,0".parse().unwrap());
+    machine.push_line("hello");
+    machine.push_line("world\n");
+    assert_eq!(machine.compute(), Ok(crate::MachineState::Waiting));
+    assert_eq!(machine.get_ascii_outputs_and_clear(), vec![AsciiOutput::Text("hello\nworld\n".to_string())]);
+    assert!(machine.get_outputs().is_empty());
+}
@@ -0,0 +1,369 @@
+use std::collections::HashMap;
+use std::error::Error;
+use std::fmt;
+use crate::{Opcode, ParamMode, Program, Value};
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum AsmErrorKind {
+    UnknownMnemonic(String),
+    OperandCount { mnemonic: String, expected: usize, found: usize },
+    InvalidOperand(String),
+    InvalidExpression(String),
+    ImmediateWrite,
+    InvalidLabel(String),
+    DuplicateLabel(String),
+    UndefinedLabel(String),
+    AddressMismatch { expected: Value, actual: Value },
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct AsmError {
+    pub line: usize,
+    pub kind: AsmErrorKind,
+}
+
+impl fmt::Display for AsmError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "line {}: ", self.line)?;
+        match &self.kind {
+            AsmErrorKind::UnknownMnemonic(mnemonic) => write!(f, "unknown mnemonic {:?}", mnemonic),
+            AsmErrorKind::OperandCount { mnemonic, expected, found } =>
+                write!(f, "{} takes {} operands, but {} were given", mnemonic, expected, found),
+            AsmErrorKind::InvalidOperand(operand) => write!(f, "invalid operand {:?}", operand),
+            AsmErrorKind::InvalidExpression(expr) => write!(f, "invalid expression {:?}", expr),
+            AsmErrorKind::ImmediateWrite => write!(f, "cannot write to an immediate operand"),
+            AsmErrorKind::InvalidLabel(label) => write!(f, "invalid label name {:?}", label),
+            AsmErrorKind::DuplicateLabel(label) => write!(f, "label {:?} is defined more than once", label),
+            AsmErrorKind::UndefinedLabel(label) => write!(f, "label {:?} is not defined", label),
+            AsmErrorKind::AddressMismatch { expected, actual } =>
+                write!(f, "expected to be at address {}, but this is address {}", expected, actual),
+        }
+    }
+}
+
+impl Error for AsmError {}
+
+#[derive(Clone, Debug, PartialEq)]
+enum Term {
+    Number(Value),
+    Label(String),
+}
+
+/// A sum of terms, each with its sign. Numbers are parsed together with their sign, which is then always 1, so
+/// that `i64::MIN` can be written.
+#[derive(Clone, Debug, PartialEq)]
+struct Expr(Vec<(Value, Term)>);
+
+impl Expr {
+    fn parse(input: &str) -> Option<Self> {
+        let mut terms = vec![];
+        let mut sign = None;
+        let mut chars = input.chars().filter(|c| !c.is_whitespace()).peekable();
+        while let Some(&c) = chars.peek() {
+            if c == '+' || c == '-' {
+                if sign.is_some() {
+                    return None;
+                }
+                sign = Some(if c == '-' { -1 } else { 1 });
+                chars.next();
+                continue;
+            }
+            // Every term but the first one needs an operator in front of it.
+            if sign.is_none() && !terms.is_empty() {
+                return None;
+            }
+            let mut word = String::new();
+            while let Some(&c) = chars.peek() {
+                if !is_label_char(c) {
+                    break;
+                }
+                word.push(c);
+                chars.next();
+            }
+            let sign = sign.take().unwrap_or(1);
+            if word.starts_with(|c: char| c.is_ascii_digit()) {
+                let number = if sign < 0 { format!("-{}", word) } else { word };
+                terms.push((1, Term::Number(number.parse().ok()?)));
+            } else if is_label(&word) {
+                terms.push((sign, Term::Label(word)));
+            } else {
+                return None;
+            }
+        }
+        if terms.is_empty() || sign.is_some() {
+            return None;
+        }
+        Some(Expr(terms))
+    }
+
+    fn evaluate(&self, labels: &HashMap<String, Value>) -> Result<Value, AsmErrorKind> {
+        let mut result: Value = 0;
+        for (sign, term) in &self.0 {
+            let value = match term {
+                Term::Number(number) => *number,
+                Term::Label(label) => *labels.get(label).ok_or_else(|| AsmErrorKind::UndefinedLabel(label.clone()))?,
+            };
+            result = value.checked_mul(*sign).and_then(|value| result.checked_add(value))
+                .ok_or_else(|| AsmErrorKind::InvalidExpression(self.to_string()))?;
+        }
+        Ok(result)
+    }
+}
+
+impl fmt::Display for Expr {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        for (idx, (sign, term)) in self.0.iter().enumerate() {
+            let negative = match term {
+                Term::Number(number) => *number < 0,
+                Term::Label(_) => *sign < 0,
+            };
+            match (idx, negative) {
+                (_, true) => write!(f, "-")?,
+                (0, false) => (),
+                (_, false) => write!(f, "+")?,
+            }
+            match term {
+                Term::Number(number) => write!(f, "{}", number.unsigned_abs())?,
+                Term::Label(label) => write!(f, "{}", label)?,
+            }
+        }
+        Ok(())
+    }
+}
+
+fn is_label_char(c: char) -> bool {
+    c.is_ascii_alphanumeric() || c == '_' || c == '.'
+}
+
+fn is_label(word: &str) -> bool {
+    word.starts_with(|c: char| c.is_ascii_alphabetic() || c == '_' || c == '.') && word.chars().all(is_label_char)
+}
+
+#[derive(Debug)]
+enum Statement {
+    Instruction { opcode: Opcode, operands: Vec<(ParamMode, Expr)> },
+    Data(Vec<Expr>),
+}
+
+impl Statement {
+    fn parse(input: &str) -> Result<Option<Self>, AsmErrorKind> {
+        let input = input.trim();
+        if input.is_empty() {
+            return Ok(None);
+        }
+        let (mnemonic, rest) = match input.find(char::is_whitespace) {
+            Some(pos) => (&input[..pos], input[pos..].trim()),
+            None => (input, ""),
+        };
+        let args: Vec<&str> = if rest.is_empty() { vec![] } else { rest.split(',').map(str::trim).collect() };
+        if mnemonic == "data" || mnemonic == "db" {
+            let values = args.iter()
+                .map(|arg| Expr::parse(arg).ok_or_else(|| AsmErrorKind::InvalidExpression(arg.to_string())))
+                .collect::<Result<Vec<Expr>, AsmErrorKind>>()?;
+            return Ok(Some(Statement::Data(values)));
+        }
+        let opcode = Opcode::from_mnemonic(mnemonic).ok_or_else(|| AsmErrorKind::UnknownMnemonic(mnemonic.to_string()))?;
+        if args.len() != opcode.arity() {
+            return Err(AsmErrorKind::OperandCount {
+                mnemonic: mnemonic.to_string(), expected: opcode.arity(), found: args.len(),
+            });
+        }
+        let operands = args.iter().map(|arg| parse_operand(arg)).collect::<Result<Vec<(ParamMode, Expr)>, AsmErrorKind>>()?;
+        if let Some(pos) = opcode.write_param() {
+            if operands[pos].0 == ParamMode::Immediate {
+                return Err(AsmErrorKind::ImmediateWrite);
+            }
+        }
+        Ok(Some(Statement::Instruction { opcode, operands }))
+    }
+
+    fn size(&self) -> usize {
+        match self {
+            Statement::Instruction { operands, .. } => 1 + operands.len(),
+            Statement::Data(values) => values.len(),
+        }
+    }
+
+    fn encode(&self, labels: &HashMap<String, Value>) -> Result<Vec<Value>, AsmErrorKind> {
+        match self {
+            Statement::Instruction { opcode, operands } => {
+                let mut instruction = Value::from(opcode.code());
+                let mut values = vec![];
+                for (pos, (mode, expr)) in operands.iter().enumerate() {
+                    let digit = match mode {
+                        ParamMode::Position => 0,
+                        ParamMode::Immediate => 1,
+                        ParamMode::Relative => 2,
+                    };
+                    instruction += digit * 10_i64.pow(pos as u32 + 2);
+                    values.push(expr.evaluate(labels)?);
+                }
+                values.insert(0, instruction);
+                Ok(values)
+            },
+            Statement::Data(values) => values.iter().map(|expr| expr.evaluate(labels)).collect(),
+        }
+    }
+}
+
+fn parse_operand(input: &str) -> Result<(ParamMode, Expr), AsmErrorKind> {
+    let invalid = || AsmErrorKind::InvalidOperand(input.to_string());
+    let (mode, expr) = if input.starts_with('[') && input.ends_with(']') {
+        (ParamMode::Position, &input[1..(input.len() - 1)])
+    } else if let Some(expr) = input.strip_prefix('#') {
+        (ParamMode::Immediate, expr)
+    } else if let Some(offset) = input.strip_prefix("rb") {
+        let offset = offset.trim_start();
+        if offset.is_empty() {
+            (ParamMode::Relative, "0")
+        } else if offset.starts_with('+') || offset.starts_with('-') {
+            (ParamMode::Relative, offset)
+        } else {
+            return Err(invalid());
+        }
+    } else {
+        return Err(invalid());
+    };
+    Ok((mode, Expr::parse(expr).ok_or_else(invalid)?))
+}
+
+/// Assembles source in the syntax of the disassembler's listings into a program.
+///
+/// Each line may start with any number of labels (`loop:`). Numeric labels like the addresses in a listing
+/// (`12:`) don't define anything, but make sure the line ends up at that address. `data` (or `db`) puts the
+/// given values into memory as they are. Operands and values can use `+` and `-` to calculate with labels and
+/// numbers. Comments start with `;`.
+pub fn assemble(source: &str) -> Result<Program, AsmError> {
+    let mut labels = HashMap::new();
+    let mut statements = vec![];
+    let mut address = 0;
+    for (line_idx, line) in source.lines().enumerate() {
+        let line_no = line_idx + 1;
+        let error = |kind| AsmError { line: line_no, kind };
+        let code = match line.find(';') {
+            Some(pos) => &line[..pos],
+            None => line,
+        };
+        let mut parts: Vec<&str> = code.split(':').collect();
+        let statement = parts.pop().unwrap();
+        for label in parts.iter().map(|label| label.trim()) {
+            if let Ok(expected) = label.parse::<Value>() {
+                if expected != address {
+                    return Err(error(AsmErrorKind::AddressMismatch { expected, actual: address }));
+                }
+            } else if !is_label(label) {
+                return Err(error(AsmErrorKind::InvalidLabel(label.to_string())));
+            } else if labels.insert(label.to_string(), address).is_some() {
+                return Err(error(AsmErrorKind::DuplicateLabel(label.to_string())));
+            }
+        }
+        if let Some(statement) = Statement::parse(statement).map_err(error)? {
+            address += statement.size() as Value;
+            statements.push((line_no, statement));
+        }
+    }
+
+    let mut values = vec![];
+    for (line, statement) in statements {
+        values.extend(statement.encode(&labels).map_err(|kind| AsmError { line, kind })?);
+    }
+    Ok(Program::new(values))
+}
+
+#[cfg(test)]
+fn assert_roundtrip(input: &str) {
+    let program: Program = input.parse().unwrap();
+    let listing = crate::disasm::listing(&program);
+    assert_eq!(assemble(&listing).map(|program| program.to_string()), Ok(input.to_string()), "listing:\n{}", listing);
+}
+
+#[test]
+fn test_roundtrip_day2() {
+    assert_roundtrip("1,9,10,3,2,3,11,0,99,30,40,50");
+}
+
+#[test]
+fn test_roundtrip_day9_quine() {
+    assert_roundtrip("109,1,204,-1,1001,100,1,100,1008,100,16,101,1006,101,0,99");
+}
+
+#[test]
+fn test_roundtrip_write_to_relative() {
+    assert_roundtrip("109,5,21101,5,23,14,99");
+}
+
+#[test]
+fn test_roundtrip_day5() {
+    assert_roundtrip("3,21,1008,21,8,20,1005,20,22,107,8,21,20,1006,20,31,1106,0,36,98,0,0,1002,21,125,20,4,20,1105,1,46,104,999,1105,1,46,1101,1000,1,20,4,20,1105,1,46,98,99");
+}
+
+#[test]
+fn test_roundtrip_day7_feedback() {
+    assert_roundtrip("3,26,1001,26,-4,26,3,27,1002,27,2,27,1,27,26,27,4,27,1001,28,-1,28,1005,28,6,99,0,0,5");
+}
+
+#[test]
+fn test_roundtrip_extreme_values() {
+    assert_roundtrip("104,-9223372036854775808,104,9223372036854775807,1101,-9223372036854775808,-1,20,99");
+    assert!(assemble("db 9223372036854775808").is_err());
+    assert_eq!(assemble("db 0 - 9223372036854775808").map(|program| program.to_string()), Ok("-9223372036854775808".to_string()));
+}
+
+#[test]
+fn test_labels_day9_quine() {
+    let program = assemble("
+        start:  arb #1
+                out rb-1
+                add [100], #1, [100]
+                eq [100], #end, [101]   ; all of the program has been printed?
+                jf [101], #start
+                hlt
+        end:
+    ").unwrap();
+    assert_eq!(program.to_string(), "109,1,204,-1,1001,100,1,100,1008,100,16,101,1006,101,0,99");
+}
+
+#[test]
+fn test_label_arithmetic_and_db() {
+    let program = assemble("
+                out [table+2]
+                out #end-table
+                hlt
+        table:  db 10, -20, 30
+        end:
+    ").unwrap();
+    assert_eq!(program.to_string(), "4,7,104,3,99,10,-20,30");
+}
+
+#[test]
+fn test_assembled_countdown_runs() {
+    let program = assemble("
+                in [counter]
+        loop:   out [counter]
+                add [counter], #-1, [counter]
+                jt [counter], #loop
+                hlt
+        counter: data 0
+    ").unwrap();
+    let mut machine = crate::IntcodeMachine::from_string(&program.to_string()).unwrap();
+    machine.push_input(3);
+    machine.compute().unwrap();
+    assert_eq!(machine.get_outputs(), vec![3,2,1]);
+}
+
+#[test]
+fn test_assembler_errors() {
+    assert_eq!(assemble("hlt\njmp #0"), Err(AsmError { line: 2, kind: AsmErrorKind::UnknownMnemonic("jmp".to_string()) }));
+    assert_eq!(assemble("add [1], [2]"), Err(AsmError { line: 1, kind: AsmErrorKind::OperandCount {
+        mnemonic: "add".to_string(), expected: 3, found: 2,
+    }}));
+    assert_eq!(assemble("in #4"), Err(AsmError { line: 1, kind: AsmErrorKind::ImmediateWrite }));
+    assert_eq!(assemble("out [nowhere]"), Err(AsmError { line: 1, kind: AsmErrorKind::UndefinedLabel("nowhere".to_string()) }));
+    assert_eq!(assemble("hlt\n2: hlt"), Err(AsmError { line: 2, kind: AsmErrorKind::AddressMismatch { expected: 2, actual: 1 } }));
+    assert_eq!(assemble("a: hlt\na: hlt"), Err(AsmError { line: 2, kind: AsmErrorKind::DuplicateLabel("a".to_string()) }));
+    assert_eq!(assemble("out 5"), Err(AsmError { line: 1, kind: AsmErrorKind::InvalidOperand("5".to_string()) }));
+    assert_eq!(assemble("hlt\ndb 9223372036854775807 + 1"),
+        Err(AsmError { line: 2, kind: AsmErrorKind::InvalidExpression("9223372036854775807+1".to_string()) }));
+    assert_eq!(assemble("end: out [-9223372036854775807 - 2 + end]"),
+        Err(AsmError { line: 1, kind: AsmErrorKind::InvalidExpression("-9223372036854775807-2+end".to_string()) }));
+}
@@ -0,0 +1,491 @@
+use std::collections::{HashMap, VecDeque};
+use std::future::{self, Future};
+use std::pin::Pin;
+use std::sync::{Arc, Mutex};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::task::{ready, Context, Poll, Wake, Waker};
+use std::thread::{self, Thread};
+use crate::{IntcodeError, IntcodeMachine, MachineState, Memory, Value};
+
+/// How many instructions a machine executes before yielding to the executor, so that a long computation
+/// doesn't starve the other tasks.
+const STEPS_PER_YIELD: u64 = 1024;
+
+/// Where opcode 3 takes its values from when running asynchronously, like a `Stream`. `Ready(None)` means that
+/// no more input will arrive.
+pub trait AsyncInput<V = Value> {
+    fn poll_input(&mut self, cx: &mut Context) -> Poll<Option<V>>;
+}
+
+/// Where opcode 4 sends its values to when running asynchronously, like a `Sink`: a value is only sent once
+/// `poll_ready` returned `Ready(Ok(()))`.
+pub trait AsyncOutput<V = Value> {
+    fn poll_ready(&mut self, cx: &mut Context) -> Poll<Result<(), IntcodeError<V>>>;
+    fn start_send(&mut self, value: V) -> Result<(), IntcodeError<V>>;
+}
+
+impl<V, T: AsyncInput<V> + ?Sized> AsyncInput<V> for &mut T {
+    fn poll_input(&mut self, cx: &mut Context) -> Poll<Option<V>> {
+        (**self).poll_input(cx)
+    }
+}
+
+impl<V, T: AsyncOutput<V> + ?Sized> AsyncOutput<V> for &mut T {
+    fn poll_ready(&mut self, cx: &mut Context) -> Poll<Result<(), IntcodeError<V>>> {
+        (**self).poll_ready(cx)
+    }
+
+    fn start_send(&mut self, value: V) -> Result<(), IntcodeError<V>> {
+        (**self).start_send(value)
+    }
+}
+
+/// The future returned by `IntcodeMachine::run_async`.
+pub struct Run<'a, M: Memory, I, O> {
+    machine: &'a mut IntcodeMachine<M>,
+    input: I,
+    output: O,
+    /// Received, but not consumed by the machine yet.
+    next_input: Option<M::Value>,
+    /// Produced by the machine, but not sent yet.
+    pending_output: VecDeque<M::Value>,
+    /// Reported once the outputs from before it have been sent.
+    error: Option<IntcodeError<M::Value>>,
+}
+
+// Nothing is pinned structurally, the input and output are only used through `&mut`.
+impl<M: Memory, I, O> Unpin for Run<'_, M, I, O> {}
+
+impl<M: Memory, I: AsyncInput<M::Value>, O: AsyncOutput<M::Value>> Run<'_, M, I, O> {
+    fn flush(&mut self, cx: &mut Context) -> Poll<Result<(), IntcodeError<M::Value>>> {
+        while !self.pending_output.is_empty() {
+            ready!(self.output.poll_ready(cx))?;
+            self.output.start_send(self.pending_output.pop_front().unwrap())?;
+        }
+        Poll::Ready(Ok(()))
+    }
+
+    fn poll_error(&mut self, cx: &mut Context) -> Poll<Result<MachineState, IntcodeError<M::Value>>> {
+        // A closed output doesn't matter anymore, the machine failed anyway.
+        let _ = ready!(self.flush(cx));
+        Poll::Ready(Err(self.error.take().unwrap()))
+    }
+}
+
+impl<M: Memory, I: AsyncInput<M::Value>, O: AsyncOutput<M::Value>> Future for Run<'_, M, I, O> {
+    type Output = Result<MachineState, IntcodeError<M::Value>>;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context) -> Poll<Self::Output> {
+        let run = self.get_mut();
+        if run.error.is_some() {
+            return run.poll_error(cx);
+        }
+        for _ in 0..STEPS_PER_YIELD {
+            ready!(run.flush(cx))?;
+            let mut next_input = run.next_input.take();
+            let result = run.machine.step_with(&mut || next_input.take(), &mut run.pending_output);
+            run.next_input = next_input;
+            let state = match result {
+                Ok(state) => state,
+                // Custom instructions can output before failing.
+                Err(error) => {
+                    run.error = Some(error);
+                    return run.poll_error(cx);
+                },
+            };
+            match state {
+                MachineState::Running => (),
+                MachineState::Waiting => match ready!(run.input.poll_input(cx)) {
+                    Some(value) => run.next_input = Some(value),
+                    None => return Poll::Ready(Ok(state)),
+                },
+                state => {
+                    ready!(run.flush(cx))?;
+                    return Poll::Ready(Ok(state));
+                },
+            }
+        }
+        cx.waker().wake_by_ref();
+        Poll::Pending
+    }
+}
+
+impl<M: Memory> IntcodeMachine<M> {
+    /// Like `compute_with`, but awaits input and the readiness of the output instead of blocking. Outputs are
+    /// sent before awaiting more input. Returns `Waiting` only once the input is closed.
+    pub fn run_async<I: AsyncInput<M::Value>, O: AsyncOutput<M::Value>>(&mut self, input: I, output: O) -> Run<'_, M, I, O> {
+        Run { machine: self, input, output, next_input: None, pending_output: VecDeque::new(), error: None }
+    }
+}
+
+struct Shared<V> {
+    values: VecDeque<V>,
+    capacity: usize,
+    senders: usize,
+    next_sender_id: usize,
+    receiver_alive: bool,
+    receiver_waker: Option<Waker>,
+    /// The waker of each sender waiting for room, by sender id.
+    sender_wakers: HashMap<usize, Waker>,
+}
+
+impl<V> Shared<V> {
+    fn pop(&mut self) -> Option<V> {
+        let value = self.values.pop_front();
+        if value.is_some() {
+            self.sender_wakers.drain().for_each(|(_, waker)| waker.wake());
+        }
+        value
+    }
+}
+
+/// The sending half of an in-memory channel, see `channel` and `bounded`.
+pub struct Sender<V = Value> {
+    shared: Arc<Mutex<Shared<V>>>,
+    id: usize,
+}
+
+pub struct Receiver<V = Value> {
+    shared: Arc<Mutex<Shared<V>>>,
+}
+
+/// A channel that never makes the sender wait.
+pub fn channel<V>() -> (Sender<V>, Receiver<V>) {
+    bounded(usize::MAX)
+}
+
+/// A channel holding at most `capacity` values, the sender waits until the receiver takes some.
+pub fn bounded<V>(capacity: usize) -> (Sender<V>, Receiver<V>) {
+    assert!(capacity > 0, "a channel needs room for at least one value");
+    let shared = Arc::new(Mutex::new(Shared {
+        values: VecDeque::new(), capacity, senders: 1, next_sender_id: 1, receiver_alive: true, receiver_waker: None,
+        sender_wakers: HashMap::new(),
+    }));
+    (Sender { shared: shared.clone(), id: 0 }, Receiver { shared })
+}
+
+impl<V> Sender<V> {
+    /// Sends a value without waiting, even if the channel is full.
+    pub fn send(&self, value: V) -> Result<(), IntcodeError<V>> {
+        let mut shared = self.shared.lock().unwrap();
+        if !shared.receiver_alive {
+            return Err(IntcodeError::OutputClosed);
+        }
+        shared.values.push_back(value);
+        if let Some(waker) = shared.receiver_waker.take() {
+            waker.wake();
+        }
+        Ok(())
+    }
+}
+
+impl<V> Clone for Sender<V> {
+    fn clone(&self) -> Self {
+        let mut shared = self.shared.lock().unwrap();
+        shared.senders += 1;
+        let id = shared.next_sender_id;
+        shared.next_sender_id += 1;
+        Self { shared: self.shared.clone(), id }
+    }
+}
+
+impl<V> Drop for Sender<V> {
+    fn drop(&mut self) {
+        let mut shared = self.shared.lock().unwrap();
+        shared.senders -= 1;
+        shared.sender_wakers.remove(&self.id);
+        if shared.senders == 0 {
+            if let Some(waker) = shared.receiver_waker.take() {
+                waker.wake();
+            }
+        }
+    }
+}
+
+impl<V> AsyncOutput<V> for Sender<V> {
+    fn poll_ready(&mut self, cx: &mut Context) -> Poll<Result<(), IntcodeError<V>>> {
+        let mut shared = self.shared.lock().unwrap();
+        if !shared.receiver_alive {
+            return Poll::Ready(Err(IntcodeError::OutputClosed));
+        }
+        if shared.values.len() < shared.capacity {
+            return Poll::Ready(Ok(()));
+        }
+        shared.sender_wakers.insert(self.id, cx.waker().clone());
+        Poll::Pending
+    }
+
+    fn start_send(&mut self, value: V) -> Result<(), IntcodeError<V>> {
+        self.send(value)
+    }
+}
+
+impl<V> Receiver<V> {
+    /// Takes a value if there is one, without waiting.
+    pub fn try_recv(&mut self) -> Option<V> {
+        self.shared.lock().unwrap().pop()
+    }
+
+    /// Waits for the next value. `None` once all senders are gone and every value was received.
+    pub async fn recv(&mut self) -> Option<V> {
+        future::poll_fn(|cx| self.poll_input(cx)).await
+    }
+}
+
+impl<V> Drop for Receiver<V> {
+    fn drop(&mut self) {
+        let mut shared = self.shared.lock().unwrap();
+        shared.receiver_alive = false;
+        shared.sender_wakers.drain().for_each(|(_, waker)| waker.wake());
+    }
+}
+
+impl<V> AsyncInput<V> for Receiver<V> {
+    fn poll_input(&mut self, cx: &mut Context) -> Poll<Option<V>> {
+        let mut shared = self.shared.lock().unwrap();
+        if let Some(value) = shared.pop() {
+            return Poll::Ready(Some(value));
+        }
+        if shared.senders == 0 {
+            return Poll::Ready(None);
+        }
+        shared.receiver_waker = Some(cx.waker().clone());
+        Poll::Pending
+    }
+}
+
+struct Flag {
+    woken: AtomicBool,
+    thread: Thread,
+}
+
+impl Flag {
+    fn new() -> Arc<Self> {
+        Arc::new(Self { woken: AtomicBool::new(true), thread: thread::current() })
+    }
+
+    fn take(&self) -> bool {
+        self.woken.swap(false, Ordering::AcqRel)
+    }
+}
+
+impl Wake for Flag {
+    fn wake(self: Arc<Self>) {
+        self.wake_by_ref();
+    }
+
+    fn wake_by_ref(self: &Arc<Self>) {
+        self.woken.store(true, Ordering::Release);
+        self.thread.unpark();
+    }
+}
+
+/// Runs a future on the current thread, parking it while the future waits to be woken.
+pub fn block_on<F: Future>(future: F) -> F::Output {
+    let flag = Flag::new();
+    let waker = Waker::from(flag.clone());
+    let mut cx = Context::from_waker(&waker);
+    let mut future = Box::pin(future);
+    loop {
+        if flag.take() {
+            if let Poll::Ready(output) = future.as_mut().poll(&mut cx) {
+                return output;
+            }
+        } else {
+            thread::park();
+        }
+    }
+}
+
+struct Task<'a> {
+    future: Pin<Box<dyn Future<Output = ()> + 'a>>,
+    flag: Arc<Flag>,
+}
+
+/// Runs several futures on the current thread, like machines connected by channels. Unlike `block_on`, it
+/// doesn't wait for wake-ups from other threads: once none of the remaining tasks has been woken, they are stuck.
+#[derive(Default)]
+pub struct LocalExecutor<'a> {
+    tasks: Vec<Task<'a>>,
+}
+
+impl<'a> LocalExecutor<'a> {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn spawn(&mut self, future: impl Future<Output = ()> + 'a) {
+        self.tasks.push(Task { future: Box::pin(future), flag: Flag::new() });
+    }
+
+    /// Polls the tasks until all of them are done or stuck, and returns the number of stuck ones. Those can be
+    /// resumed by running again after waking them up, e.g. by sending them input.
+    pub fn run(&mut self) -> usize {
+        loop {
+            let mut progress = false;
+            self.tasks.retain_mut(|task| {
+                if !task.flag.take() {
+                    return true;
+                }
+                progress = true;
+                let waker = Waker::from(task.flag.clone());
+                task.future.as_mut().poll(&mut Context::from_waker(&waker)).is_pending()
+            });
+            if !progress {
+                return self.tasks.len();
+            }
+        }
+    }
+}
+
+#[test]
+fn test_run_async() {
+    // Doubles every input.
+    let mut machine = IntcodeMachine::from_string("3,9,1002,9,2,9,4,9,1105,1,0").unwrap();
+    let (input, mut receiver) = channel();
+    let (sender, mut outputs) = channel();
+    for value in 1..=3 {
+        input.send(value).unwrap();
+    }
+    drop(input);
+    assert_eq!(block_on(machine.run_async(&mut receiver, sender)), Ok(MachineState::Waiting));
+    assert_eq!(block_on(outputs.recv()), Some(2));
+    assert_eq!(outputs.try_recv(), Some(4));
+    assert_eq!(outputs.try_recv(), Some(6));
+    assert_eq!(block_on(outputs.recv()), None);
+}
+
+#[test]
+fn test_feedback_loop() {
+    let template = IntcodeMachine::from_string(
+        "3,26,1001,26,-4,26,3,27,1002,27,2,27,1,27,26,27,4,27,1001,28,-1,28,1005,28,6,99,0,0,5").unwrap();
+    let mut machines = vec![template; 5];
+    let (senders, mut receivers): (Vec<Sender>, Vec<Receiver>) = (0..5).map(|_| channel()).unzip();
+    for (sender, phase) in senders.iter().zip(&[9, 8, 7, 6, 5]) {
+        sender.send(*phase).unwrap();
+    }
+    senders[0].send(0).unwrap();
+
+    let mut executor = LocalExecutor::new();
+    let outputs = senders.iter().cycle().skip(1).cloned();
+    for ((machine, receiver), sender) in machines.iter_mut().zip(receivers.iter_mut()).zip(outputs) {
+        executor.spawn(async move {
+            assert_eq!(machine.run_async(receiver, sender).await, Ok(MachineState::Done));
+        });
+    }
+    assert_eq!(executor.run(), 0);
+    drop(executor);
+    assert_eq!(receivers[0].try_recv(), Some(139629729));
+}
+
+#[test]
+fn test_stuck_and_resumed() {
+    // Both machines echo their input, waiting for each other first.
+    let mut first = IntcodeMachine::from_string("3,5,4,5,99,0").unwrap();
+    let mut second = first.clone();
+    let (to_first, mut first_input) = channel();
+    let (to_second, mut second_input) = channel();
+    let kick = to_first.clone();
+
+    let mut executor = LocalExecutor::new();
+    executor.spawn(async {
+        assert_eq!(first.run_async(&mut first_input, to_second).await, Ok(MachineState::Done));
+    });
+    executor.spawn(async {
+        assert_eq!(second.run_async(&mut second_input, to_first).await, Ok(MachineState::Done));
+    });
+    assert_eq!(executor.run(), 2);
+    kick.send(7).unwrap();
+    assert_eq!(executor.run(), 0);
+    drop(executor);
+    assert_eq!(first_input.try_recv(), Some(7));
+}
+
+#[test]
+fn test_bounded_output() {
+    // Outputs 1, 2 and 3.
+    let mut machine = IntcodeMachine::from_string("104,1,104,2,104,3,99").unwrap();
+    let (_input, mut receiver) = channel();
+    let (sender, mut outputs) = bounded(1);
+    let mut received = vec![];
+    {
+        let mut executor = LocalExecutor::new();
+        executor.spawn(async {
+            assert_eq!(machine.run_async(&mut receiver, sender).await, Ok(MachineState::Done));
+        });
+        assert_eq!(executor.run(), 1);
+        while let Some(value) = outputs.try_recv() {
+            received.push(value);
+            executor.run();
+        }
+    }
+    assert_eq!(received, vec![1, 2, 3]);
+    assert_eq!(machine.state(), MachineState::Done);
+}
+
+#[test]
+fn test_closed_output() {
+    let mut machine = IntcodeMachine::from_string("104,1,99").unwrap();
+    let (_input, receiver) = channel();
+    let (sender, outputs) = channel::<Value>();
+    drop(outputs);
+    assert_eq!(block_on(machine.run_async(receiver, sender)), Err(IntcodeError::OutputClosed));
+}
+
+#[test]
+fn test_long_computation_yields() {
+    // Counts [100] up to 10000, then outputs it.
+    let counter = "1001,100,1,100,1007,100,10000,101,1005,101,0,4,100,99";
+    let mut busy = IntcodeMachine::from_string(counter).unwrap();
+    let mut quick = IntcodeMachine::from_string("104,1,99").unwrap();
+    let order = Mutex::new(vec![]);
+    let mut executor = LocalExecutor::new();
+    for (name, machine) in [("busy", &mut busy), ("quick", &mut quick)] {
+        let order = &order;
+        executor.spawn(async move {
+            let (_input, receiver) = channel();
+            let (sender, _outputs) = channel::<Value>();
+            machine.run_async(receiver, sender).await.unwrap();
+            order.lock().unwrap().push(name);
+        });
+    }
+    assert_eq!(executor.run(), 0);
+    assert_eq!(*order.lock().unwrap(), vec!["quick", "busy"]);
+}
+
+#[test]
+fn test_outputs_before_an_error() {
+    // Opcode 20 outputs 5, then fails.
+    let mut machine = crate::extension::MachineBuilder::new(&"104,1,20,99".parse().unwrap())
+        .opcode(20, crate::extension::OpcodeSpec::new(0), |call| {
+            call.output(5)?;
+            Err(call.unknown_opcode())
+        })
+        .build();
+    let (_input, receiver) = channel();
+    let (sender, mut outputs) = bounded(1);
+    let mut executor = LocalExecutor::new();
+    executor.spawn(async {
+        assert_eq!(machine.run_async(receiver, sender).await, Err(IntcodeError::UnknownOpcode { opcode: 20, ip: 2 }));
+    });
+    assert_eq!(executor.run(), 1);
+    assert_eq!(outputs.try_recv(), Some(1));
+    assert_eq!(executor.run(), 0);
+    assert_eq!(outputs.try_recv(), Some(5));
+}
+
+#[test]
+fn test_one_waker_per_sender() {
+    let (mut sender, _receiver) = bounded::<Value>(1);
+    sender.send(1).unwrap();
+    let waker = Waker::from(Flag::new());
+    let mut cx = Context::from_waker(&waker);
+    for _ in 0..10 {
+        assert!(sender.poll_ready(&mut cx).is_pending());
+    }
+    let mut other = sender.clone();
+    assert!(other.poll_ready(&mut cx).is_pending());
+    assert_eq!(sender.shared.lock().unwrap().sender_wakers.len(), 2);
+    drop(other);
+    assert_eq!(sender.shared.lock().unwrap().sender_wakers.len(), 1);
+}
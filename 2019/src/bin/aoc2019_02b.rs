@@ -1,77 +1,75 @@
 use std::error::Error;
-use std::io::{self, BufRead, BufReader};
+use std::io::Read;
+use std::ops::Range;
+use aoc2019::{Address,DEFAULT_STEP_BUDGET,IntcodeMachine,Program,Value};
+use aoc2019::search::search_inputs;
+use aoc2019::symbolic::solve_linear;
 
-fn compute(program: &mut [u32]) {
-    let mut pos = 0;
-    loop {
-        match program[pos] {
-            1 => program[program[pos+3] as usize] = program[program[pos+1] as usize] + program[program[pos+2] as usize],
-            2 => program[program[pos+3] as usize] = program[program[pos+1] as usize] * program[program[pos+2] as usize],
-            99 => break,
-            _ => panic!("unknown opcode {} at position {}", program[pos], pos),
-        }
-        pos += 4;
-    }
+const TARGET: Value = 19690720;
+
+/// Solves for the noun and verb symbolically, and falls back to trying all of them if that doesn't work.
+fn find_noun_and_verb(program: &Program, target: Value) -> Option<Vec<Value>> {
+    let cells: [(Address, Range<Value>); 2] = [(1, 0..100), (2, 0..100)];
+    solve_linear(program, &cells, 0, target).ok().flatten()
+        .or_else(|| search_inputs(&IntcodeMachine::from_program(program), &cells, 0, target, DEFAULT_STEP_BUDGET))
+}
+
+#[cfg(test)]
+fn final_memory(program: &str) -> Vec<Value> {
+    let mut machine = IntcodeMachine::from_string(program).unwrap();
+    machine.compute().unwrap();
+    machine.get_memory_vec(0..(program.split(',').count() as u64))
 }
 
 #[test]
 fn example0() {
-    let mut program = vec![1,9,10,3,2,3,11,0,99,30,40,50];
-    compute(&mut program);
-    assert_eq!(program, vec![3500,9,10,70,2,3,11,0,99,30,40,50]);
+    assert_eq!(final_memory("1,9,10,3,2,3,11,0,99,30,40,50"), vec![3500,9,10,70,2,3,11,0,99,30,40,50]);
 }
 
 #[test]
 fn example1() {
-    let mut program = vec![1,0,0,0,99];
-    compute(&mut program);
-    assert_eq!(program, vec![2,0,0,0,99]);
+    assert_eq!(final_memory("1,0,0,0,99"), vec![2,0,0,0,99]);
 }
 
 #[test]
 fn example2() {
-    let mut program = vec![2,3,0,3,99];
-    compute(&mut program);
-    assert_eq!(program, vec![2,3,0,6,99]);
+    assert_eq!(final_memory("2,3,0,3,99"), vec![2,3,0,6,99]);
 }
 
 #[test]
 fn example3() {
-    let mut program = vec![2,4,4,5,99,0];
-    compute(&mut program);
-    assert_eq!(program, vec![2,4,4,5,99,9801]);
+    assert_eq!(final_memory("2,4,4,5,99,0"), vec![2,4,4,5,99,9801]);
 }
 
 #[test]
 fn example4() {
-    let mut program = vec![1,1,1,4,99,5,6,0,99];
-    compute(&mut program);
-    assert_eq!(program, vec![30,1,1,4,2,5,6,0,99]);
+    assert_eq!(final_memory("1,1,1,4,99,5,6,0,99"), vec![30,1,1,4,2,5,6,0,99]);
 }
 
-fn main() -> Result<(), Box<dyn Error>> {
-    let buffered = BufReader::new(io::stdin());
-    let mut program = vec![];
+#[test]
+fn finds_noun_and_verb() {
+    // Multiplies the values at positions noun and verb, the largest one is the 99 at position 4.
+    assert_eq!(find_noun_and_verb(&"2,0,0,0,99".parse().unwrap(), 99 * 99), Some(vec![4, 4]));
+}
 
-    for line in buffered.lines() {
-        for number in line?.trim().split(',') {
-            program.push(number.parse::<u32>()?);
-        }
-    }
+#[test]
+fn solves_linear_programs() {
+    // Computes 100 * noun + verb + 3 after a first instruction whose result is overwritten.
+    let program = "1,0,0,3,1002,1,100,3,1,3,2,3,1001,3,3,0,99".parse().unwrap();
+    assert_eq!(find_noun_and_verb(&program, 4216), Some(vec![42, 13]));
+}
 
-    let original_program = program.to_vec();
+fn main() -> Result<(), Box<dyn Error>> {
+    let mut input = String::new();
+    std::io::stdin().read_to_string(&mut input)?;
+    let program: Program = input.parse()?;
 
-    'outer: for noun in 0..100 {
-        for verb in 0..100 {
-            program = original_program.to_vec();
-            program[1] = noun;
-            program[2] = verb;
-            compute(&mut program);
-            if program[0] == 19690720 {
-                println!("Found matching program. Noun = {}, verb = {}, answer is therefore: {}", noun, verb, 100 * noun + verb);
-                break 'outer;
-            }
-        }
+    match find_noun_and_verb(&program, TARGET) {
+        Some(values) => {
+            let (noun, verb) = (values[0], values[1]);
+            println!("Found matching program. Noun = {}, verb = {}, answer is therefore: {}", noun, verb, 100 * noun + verb);
+        },
+        None => println!("No noun and verb produce {}.", TARGET),
     }
 
     Ok(())
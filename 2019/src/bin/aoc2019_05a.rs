@@ -2,24 +2,24 @@ use std::error::Error;
 use aoc2019::IntcodeMachine;
 
 fn main() -> Result<(), Box<dyn Error>> {
-    let mut machine = IntcodeMachine::from_stdin();
+    let mut machine = IntcodeMachine::from_stdin()?;
     machine.set_input(vec![1]);
-    machine.compute();
-    println!("Diagnostic code: {}", machine.get_output());
+    machine.compute()?;
+    println!("Diagnostic code: {}", machine.get_output()?);
     Ok(())
 }
 
 #[test]
 fn test_input() {
-    let mut machine = IntcodeMachine::from_string("3,0,99");
+    let mut machine = IntcodeMachine::from_string("3,0,99").unwrap();
     machine.set_input(vec![23]);
-    machine.compute();
+    machine.compute().unwrap();
     assert_eq!(machine.get_memory_vec(0..3), vec![23,0,99]);
 }
 
 #[test]
 fn test_output() {
-    let mut machine = IntcodeMachine::from_string("4,0,99");
-    machine.compute();
-    assert_eq!(machine.get_output(), 4);
+    let mut machine = IntcodeMachine::from_string("4,0,99").unwrap();
+    machine.compute().unwrap();
+    assert_eq!(machine.get_output(), Ok(4));
 }
@@ -1,6 +1,8 @@
+use std::cell::RefCell;
 use std::collections::HashMap;
+use std::error::Error;
 use std::ops::Add;
-use aoc2019::{IntcodeMachine,MachineState};
+use aoc2019::{IntcodeError,IntcodeMachine};
 
 
 enum Direction {
@@ -67,33 +69,35 @@ impl Robot {
         }
     }
 
-    fn run(&mut self, hull: &mut Hull, machine: &mut IntcodeMachine) {
-        loop {
-            machine.push_input(match hull.color_at(self.position) {
+    fn run(&mut self, hull: &mut Hull, machine: &mut IntcodeMachine) -> Result<(), IntcodeError> {
+        let robot = RefCell::new(self);
+        let hull = RefCell::new(hull);
+        let mut output = vec![];
+        machine.compute_with(
+            &mut || Some(match hull.borrow().color_at(robot.borrow().position) {
                 Color::Black => 0,
                 Color::White => 1,
-            });
-            match machine.compute() {
-                MachineState::Done => break,
-                MachineState::Waiting => {
-                    let output = machine.get_outputs_and_clear();
-                    if output.len() != 2 {
-                        panic!("Unexpected robot output length {}", output.len());
-                    }
-                    hull.set_color_at(self.position, match output[0] {
-                        0 => Color::Black,
-                        1 => Color::White,
-                        _ => panic!("Unexpected color output {}", output[0]),
-                    });
-                    match output[1] {
-                        0 => self.advance_left(),
-                        1 => self.advance_right(),
-                        _ => panic!("Unexpected direction output {}", output[1]),
-                    }
+            }),
+            &mut |value| {
+                output.push(value);
+                if output.len() < 2 {
+                    return;
                 }
-                _ => unimplemented!(),
-            }
-        }
+                let mut robot = robot.borrow_mut();
+                hull.borrow_mut().set_color_at(robot.position, match output[0] {
+                    0 => Color::Black,
+                    1 => Color::White,
+                    _ => panic!("Unexpected color output {}", output[0]),
+                });
+                match output[1] {
+                    0 => robot.advance_left(),
+                    1 => robot.advance_right(),
+                    _ => panic!("Unexpected direction output {}", output[1]),
+                }
+                output.clear();
+            },
+        )?;
+        Ok(())
     }
 }
 
@@ -149,13 +153,13 @@ impl Hull {
 }
 
 
-fn main() {
-    let mut machine = IntcodeMachine::from_stdin();
+fn main() -> Result<(), Box<dyn Error>> {
+    let mut machine = IntcodeMachine::from_stdin()?;
     let mut machine_b = machine.clone();
     let mut hull = Hull::new();
     let mut robot = Robot { position: Position(0,0), direction: Direction::Up };
 
-    robot.run(&mut hull, &mut machine);
+    robot.run(&mut hull, &mut machine)?;
 
     println!("The robot would paint {} panels like this:", hull.count_painted());
     hull.print();
@@ -163,8 +167,10 @@ fn main() {
     hull = Hull::new();
     hull.set_color_at(robot.position, Color::White);
 
-    robot.run(&mut hull, &mut machine_b);
+    robot.run(&mut hull, &mut machine_b)?;
 
     println!("When starting at a white square, the result looks like this:");
     hull.print();
+
+    Ok(())
 }
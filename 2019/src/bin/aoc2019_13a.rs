@@ -1,4 +1,5 @@
 use std::collections::HashMap;
+use std::error::Error;
 use aoc2019::IntcodeMachine;
 
 
@@ -94,11 +95,11 @@ impl Screen {
 }
 
 
-fn main() {
-    let mut machine = IntcodeMachine::from_stdin();
+fn main() -> Result<(), Box<dyn Error>> {
+    let mut machine = IntcodeMachine::from_stdin()?;
     let mut screen = Screen::new();
 
-    machine.compute();
+    machine.compute()?;
 
     for chunk in machine.get_outputs().chunks_exact(3) {
         screen.set_tile_from_chunk(chunk);
@@ -106,4 +107,6 @@ fn main() {
     screen.print();
 
     println!("This final screen contains {} block tiles.", screen.count_tiles(&Tile::Block));
+
+    Ok(())
 }
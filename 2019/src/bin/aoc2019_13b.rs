@@ -1,4 +1,5 @@
 use std::collections::HashMap;
+use std::error::Error;
 use std::thread;
 use aoc2019::{IntcodeMachine,MachineState,ParamMode};
 
@@ -125,11 +126,11 @@ fn draw_screen_from_outputs(machine: &IntcodeMachine, screen: &mut Screen) {
 }
 
 
-fn main() {
-    let mut machine = IntcodeMachine::from_stdin();
+fn main() -> Result<(), Box<dyn Error>> {
+    let mut machine = IntcodeMachine::from_stdin()?;
     let mut screen = Screen::new();
 
-    machine.set_memory(ParamMode::Position, 0, 2);
+    machine.set_memory(ParamMode::Position, 0, 2)?;
 
     loop {
         if let Some(ball) = screen.get_position_of(&Tile::Ball) {
@@ -143,7 +144,7 @@ fn main() {
                 }
             }
         }
-        match machine.compute() {
+        match machine.compute()? {
             MachineState::Waiting => {
                 draw_screen_from_outputs(&machine, &mut screen);
                 thread::sleep_ms(5);
@@ -156,4 +157,6 @@ fn main() {
         }
     }
     println!("Game over! Your score is: {}", screen.score);
+
+    Ok(())
 }
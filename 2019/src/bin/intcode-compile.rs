@@ -0,0 +1,18 @@
+use std::env;
+use std::error::Error;
+use std::io::{self, Read};
+use aoc2019::Program;
+use aoc2019::compile::translate;
+
+fn main() -> Result<(), Box<dyn Error>> {
+    let module = env::args().nth(1).unwrap_or_else(|| "program".to_string());
+    let mut input = String::new();
+    io::stdin().read_to_string(&mut input)?;
+    let program: Program = input.parse()?;
+    let translation = translate(&program, &module);
+    for address in translation.self_modifying {
+        eprintln!("warning: instruction at {} writes to code, the program will fall back to the interpreter", address);
+    }
+    print!("{}", translation.source);
+    Ok(())
+}
@@ -0,0 +1,194 @@
+use std::env;
+use std::error::Error;
+use std::fs;
+use std::io::{self, BufRead, Write};
+use aoc2019::{Address, IntcodeMachine, Value};
+use aoc2019::debugger::{Debugger, StopReason};
+use aoc2019::disasm::Instruction;
+
+const HELP: &str = "\
+Commands:
+  s, step [n]             execute n instructions (default 1)
+  c, continue             run until a breakpoint, a watchpoint, waiting for input or halting
+  b, break <addr>         set a breakpoint on an instruction address
+  d, delete <addr>        remove a breakpoint
+  w, watch <addr>         stop whenever the given address is written to
+  u, unwatch <addr>       remove a watchpoint
+  i, info                 show registers, pending input, breakpoints and watchpoints
+  x, mem <addr> [n]       show n memory cells starting at addr (default 1, at most 1000)
+  l, list [addr] [n]      disassemble n instructions starting at addr (default: ip, 5)
+  in, input <values...>   queue input values
+  o, output               show and clear the outputs produced so far
+  rec, record [on|off]    record executed instructions so that they can be undone (default on)
+  bs, back [n]            undo the last n recorded instructions (default 1)
+  rw, rewind <steps>      undo recorded instructions until the step count is the given one
+  lw, lastwrite <addr>    go back to just before the last recorded write to the given address
+  h, help                 show this help
+  q, quit                 exit the debugger";
+
+/// The most memory cells or instructions `mem` and `list` show at once.
+const MAX_COUNT: Address = 1000;
+
+fn show_instruction(machine: &IntcodeMachine, address: Address) -> usize {
+    let words: Vec<Value> = (0..4).map_while(|offset| address.checked_add(offset)).map(|address| machine.get_memory(address)).collect();
+    match Instruction::decode(&words, 0) {
+        Some(instruction) => {
+            println!("{:5}: {}", address, instruction);
+            instruction.size()
+        },
+        None => {
+            println!("{:5}: data {}", address, words[0]);
+            1
+        },
+    }
+}
+
+fn report(debugger: &Debugger, reason: StopReason) {
+    match reason {
+        StopReason::Stepped => (),
+        StopReason::Breakpoint(address) => println!("Breakpoint at {}.", address),
+        StopReason::Watchpoint { address, value } => println!("Watchpoint: {} was set to {}.", address, value),
+        StopReason::Waiting => println!("Waiting for input."),
+        StopReason::Done => println!("Program halted after {} steps.", debugger.steps()),
+    }
+    show_instruction(debugger.machine(), debugger.machine().ip());
+}
+
+fn parse_args<T: std::str::FromStr>(args: &[&str]) -> Result<Vec<T>, String> {
+    args.iter().map(|arg| arg.parse().map_err(|_| format!("invalid number: {}", arg))).collect()
+}
+
+fn execute(debugger: &mut Debugger, command: &str, args: &[&str]) -> Result<bool, Box<dyn Error>> {
+    match command {
+        "s" | "step" => {
+            let count = parse_args::<u64>(args)?.first().copied().unwrap_or(1);
+            let mut reason = StopReason::Stepped;
+            for _ in 0..count {
+                reason = debugger.step()?;
+                if reason != StopReason::Stepped {
+                    break;
+                }
+            }
+            report(debugger, reason);
+        },
+        "c" | "continue" => {
+            let reason = debugger.run()?;
+            report(debugger, reason);
+        },
+        "b" | "break" => for address in parse_args(args)? {
+            debugger.add_breakpoint(address);
+        },
+        "d" | "delete" => for address in parse_args(args)? {
+            if !debugger.remove_breakpoint(address) {
+                println!("There is no breakpoint at {}.", address);
+            }
+        },
+        "w" | "watch" => for address in parse_args(args)? {
+            debugger.add_watchpoint(address);
+        },
+        "u" | "unwatch" => for address in parse_args(args)? {
+            if !debugger.remove_watchpoint(address) {
+                println!("There is no watchpoint on {}.", address);
+            }
+        },
+        "i" | "info" => {
+            let machine = debugger.machine();
+            println!("ip: {}, relative base: {}, state: {:?}, steps: {}",
+                machine.ip(), machine.relative_base(), machine.state(), debugger.steps());
+            println!("pending input: {:?}", machine.pending_input());
+            println!("breakpoints: {:?}", debugger.breakpoints());
+            println!("watchpoints: {:?}", debugger.watchpoints());
+            if debugger.is_recording() {
+                println!("recording since step {}", debugger.earliest_step());
+            }
+        },
+        "x" | "mem" => {
+            let args = parse_args::<Address>(args)?;
+            let address = *args.first().ok_or("mem needs an address")?;
+            let count = args.get(1).copied().unwrap_or(1).min(MAX_COUNT);
+            for (offset, value) in debugger.machine().get_memory_vec(address..address.saturating_add(count)).iter().enumerate() {
+                println!("{:5}: {}", address + offset as Address, value);
+            }
+        },
+        "l" | "list" => {
+            let args = parse_args::<Address>(args)?;
+            let mut address = args.first().copied().unwrap_or_else(|| debugger.machine().ip());
+            for _ in 0..args.get(1).copied().unwrap_or(5).min(MAX_COUNT) {
+                match address.checked_add(show_instruction(debugger.machine(), address) as Address) {
+                    Some(next) => address = next,
+                    None => break,
+                }
+            }
+        },
+        "in" | "input" => for value in parse_args::<Value>(args)? {
+            debugger.machine_mut().push_input(value);
+        },
+        "o" | "output" => println!("{:?}", debugger.machine_mut().get_outputs_and_clear()),
+        "rec" | "record" => match args.first().copied().unwrap_or("on") {
+            "on" => debugger.set_recording(true),
+            "off" => debugger.set_recording(false),
+            arg => println!("Expected \"on\" or \"off\", not {:?}.", arg),
+        },
+        "bs" | "back" => {
+            let count = parse_args::<u64>(args)?.first().copied().unwrap_or(1);
+            let undone = (0..count).take_while(|_| debugger.step_back()).count();
+            if (undone as u64) < count {
+                println!("Reached the start of the recording.");
+            }
+            show_instruction(debugger.machine(), debugger.machine().ip());
+        },
+        "rw" | "rewind" => {
+            let steps = *parse_args::<u64>(args)?.first().ok_or("rewind needs a step count")?;
+            if !debugger.rewind_to(steps) {
+                return Err(format!("only steps {} to {} can be rewound to", debugger.earliest_step(), debugger.steps()).into());
+            }
+            show_instruction(debugger.machine(), debugger.machine().ip());
+        },
+        "lw" | "lastwrite" => {
+            let address = *parse_args::<Address>(args)?.first().ok_or("lastwrite needs an address")?;
+            match debugger.rewind_to_last_write(address) {
+                Some(steps) => println!("Rewound to step {}.", steps),
+                None => println!("There is no recorded write to {}.", address),
+            }
+            show_instruction(debugger.machine(), debugger.machine().ip());
+        },
+        "h" | "help" => println!("{}", HELP),
+        "q" | "quit" => return Ok(false),
+        _ => println!("Unknown command {:?}, try \"help\".", command),
+    }
+    Ok(true)
+}
+
+fn main() -> Result<(), Box<dyn Error>> {
+    let path = env::args().nth(1).ok_or("usage: intcode-dbg <program file>")?;
+    let mut debugger = Debugger::new(IntcodeMachine::from_string(&fs::read_to_string(path)?)?);
+    show_instruction(debugger.machine(), 0);
+
+    let stdin = io::stdin();
+    loop {
+        print!("(dbg) ");
+        io::stdout().flush()?;
+        let mut line = String::new();
+        if stdin.lock().read_line(&mut line)? == 0 {
+            break;
+        }
+        let words: Vec<&str> = line.split_whitespace().collect();
+        if words.is_empty() {
+            continue;
+        }
+        match execute(&mut debugger, words[0], &words[1..]) {
+            Ok(true) => (),
+            Ok(false) => break,
+            Err(error) => println!("Error: {}", error),
+        }
+    }
+    Ok(())
+}
+
+#[test]
+fn shows_memory_at_the_end_of_the_address_space() {
+    let mut debugger = Debugger::new(IntcodeMachine::from_string("99").unwrap());
+    assert!(execute(&mut debugger, "x", &["18446744073709551615", "2"]).unwrap());
+    assert!(execute(&mut debugger, "x", &["0", "18446744073709551615"]).unwrap());
+    assert!(execute(&mut debugger, "l", &["18446744073709551614", "3"]).unwrap());
+}
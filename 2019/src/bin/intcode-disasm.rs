@@ -0,0 +1,23 @@
+use std::env;
+use std::error::Error;
+use std::io::{self, Read};
+use aoc2019::Program;
+use aoc2019::cfg::ControlFlowGraph;
+use aoc2019::disasm::listing;
+
+/// Prints a listing of the program on stdin, or with `--dot` its control flow graph in Graphviz format.
+fn main() -> Result<(), Box<dyn Error>> {
+    let mut input = String::new();
+    io::stdin().read_to_string(&mut input)?;
+    let program: Program = input.parse()?;
+    if env::args().skip(1).any(|arg| arg == "--dot") {
+        let cfg = ControlFlowGraph::analyze(&program);
+        for write in &cfg.code_writes {
+            eprintln!("warning: instruction at {} writes to code at {}", write.ip, write.target);
+        }
+        print!("{}", cfg.to_dot());
+    } else {
+        print!("{}", listing(&program));
+    }
+    Ok(())
+}
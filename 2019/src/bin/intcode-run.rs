@@ -0,0 +1,55 @@
+use std::env;
+use std::error::Error;
+use std::fs;
+use std::io::{self, BufRead, Write};
+use aoc2019::{IntcodeMachine, MachineState, Value};
+
+const USAGE: &str = "usage: intcode-run [--ascii] <program file>";
+
+/// Runs a program interactively. Whenever it waits for input, a line is read from stdin: with `--ascii` as text,
+/// otherwise as comma or whitespace separated numbers. Text output is printed as is, other values one per line.
+fn main() -> Result<(), Box<dyn Error>> {
+    let args: Vec<String> = env::args().skip(1).collect();
+    let ascii = args.iter().any(|arg| arg == "--ascii");
+    let path = args.iter().find(|arg| !arg.starts_with("--")).ok_or(USAGE)?;
+    let mut machine = IntcodeMachine::from_string(&fs::read_to_string(path)?)?;
+
+    let stdin = io::stdin();
+    loop {
+        let state = machine.compute()?;
+        if ascii {
+            for output in machine.get_ascii_outputs_and_clear() {
+                print!("{}", output);
+            }
+        } else {
+            for value in machine.get_outputs_and_clear() {
+                println!("{}", value);
+            }
+        }
+        if state == MachineState::Done {
+            break;
+        }
+        if !ascii {
+            print!("> ");
+        }
+        io::stdout().flush()?;
+        let mut line = String::new();
+        if stdin.lock().read_line(&mut line)? == 0 {
+            eprintln!("End of input while the program is waiting for more.");
+            break;
+        }
+        if ascii {
+            machine.push_line(&line);
+        } else {
+            let values: Result<Vec<Value>, _> = line.split(|c: char| c == ',' || c.is_whitespace())
+                .filter(|word| !word.is_empty())
+                .map(|word| word.parse())
+                .collect();
+            match values {
+                Ok(values) => values.into_iter().for_each(|value| machine.push_input(value)),
+                Err(error) => eprintln!("Invalid input: {}", error),
+            }
+        }
+    }
+    Ok(())
+}
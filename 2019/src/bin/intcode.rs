@@ -0,0 +1,226 @@
+use std::env;
+use std::error::Error;
+use std::fs;
+use std::io::{self, Read};
+use std::process;
+use itertools::Itertools;
+use aoc2019::{AccessKind, Address, IntcodeMachine, MachineState, MemoryPolicy, ParamMode, Value};
+use aoc2019::ascii::{decode, encode_line};
+
+const USAGE: &str = "\
+usage: intcode [options] [program file]
+
+Runs an Intcode program, read from the file or from stdin if there is none or it is \"-\".
+
+Options:
+  -i, --input <values>        queue comma separated input values, can be repeated
+      --input-file <file>     queue the comma or whitespace separated values in the file
+  -l, --line <text>           queue a line of ASCII text as input, can be repeated
+  -s, --set <addr>=<value>    set a memory cell before running, can be repeated
+  -f, --format <format>       print outputs as raw (one per line, the default), csv, ascii or json
+      --max-steps <n>         stop after executing n instructions
+  -m, --dump-memory           print the memory after running
+      --strict                fail when reading memory beyond the program that was never written to
+      --read-only-image       fail when writing to the addresses the program was loaded into
+      --audit                 list the accesses to memory beyond the program on stderr
+  -h, --help                  show this help";
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum Format {
+    Raw,
+    Csv,
+    Ascii,
+    Json,
+}
+
+#[derive(Debug, PartialEq)]
+struct Options {
+    program: Option<String>,
+    inputs: Vec<Value>,
+    patches: Vec<(Address, Value)>,
+    format: Format,
+    max_steps: Option<u64>,
+    dump_memory: bool,
+    policy: MemoryPolicy,
+}
+
+fn parse_values(text: &str) -> Result<Vec<Value>, String> {
+    text.split(|c: char| c == ',' || c.is_whitespace())
+        .filter(|word| !word.is_empty())
+        .map(|word| word.parse().map_err(|_| format!("invalid number: {}", word)))
+        .collect()
+}
+
+fn parse_patch(text: &str) -> Result<(Address, Value), String> {
+    let invalid = || format!("invalid memory patch {:?}, expected <addr>=<value>", text);
+    let (address, value) = text.split('=').collect_tuple().ok_or_else(invalid)?;
+    Ok((address.trim().parse().map_err(|_| invalid())?, value.trim().parse().map_err(|_| invalid())?))
+}
+
+/// Returns `None` if help was requested.
+fn parse_options(args: &[String]) -> Result<Option<Options>, String> {
+    let mut options = Options {
+        program: None, inputs: vec![], patches: vec![], format: Format::Raw, max_steps: None, dump_memory: false,
+        policy: MemoryPolicy::default(),
+    };
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        let mut value = || args.next().ok_or_else(|| format!("{} needs a value", arg));
+        match arg.as_str() {
+            "-i" | "--input" => options.inputs.extend(parse_values(value()?)?),
+            "--input-file" => {
+                let path = value()?;
+                let text = fs::read_to_string(path).map_err(|error| format!("can't read {}: {}", path, error))?;
+                options.inputs.extend(parse_values(&text)?);
+            },
+            "-l" | "--line" => options.inputs.extend(encode_line(value()?)),
+            "-s" | "--set" => options.patches.push(parse_patch(value()?)?),
+            "-f" | "--format" => options.format = match value()?.as_str() {
+                "raw" => Format::Raw,
+                "csv" => Format::Csv,
+                "ascii" => Format::Ascii,
+                "json" => Format::Json,
+                format => return Err(format!("unknown format: {}", format)),
+            },
+            "--max-steps" => {
+                let max_steps = value()?;
+                options.max_steps = Some(max_steps.parse().map_err(|_| format!("invalid step limit: {}", max_steps))?);
+            },
+            "-m" | "--dump-memory" => options.dump_memory = true,
+            "--strict" => options.policy.strict_reads = true,
+            "--read-only-image" => options.policy.read_only_image = true,
+            "--audit" => options.policy.audit = true,
+            "-h" | "--help" => return Ok(None),
+            _ if arg.starts_with('-') && arg != "-" => return Err(format!("unknown option: {}", arg)),
+            _ if options.program.is_some() => return Err(format!("unexpected argument: {}", arg)),
+            _ => options.program = Some(arg.clone()),
+        }
+    }
+    Ok(Some(options))
+}
+
+fn memory(machine: &IntcodeMachine) -> Vec<Value> {
+    let end = machine.snapshot().memory.last().map_or(0, |(start, values)| start + values.len() as Address);
+    machine.get_memory_vec(0..end)
+}
+
+fn load(source: &str, options: &Options) -> Result<IntcodeMachine, Box<dyn Error>> {
+    let mut machine = IntcodeMachine::from_string(source)?;
+    // Patched cells beyond the program count as initialized once the policy is set.
+    machine.set_memory_policy(options.policy);
+    for &(address, value) in &options.patches {
+        machine.set_memory(ParamMode::Position, address as Value, value)?;
+    }
+    machine.set_input(options.inputs.clone());
+    Ok(machine)
+}
+
+fn main() -> Result<(), Box<dyn Error>> {
+    let args: Vec<String> = env::args().skip(1).collect();
+    let options = match parse_options(&args) {
+        Ok(Some(options)) => options,
+        Ok(None) => {
+            println!("{}", USAGE);
+            return Ok(());
+        },
+        Err(error) => {
+            eprintln!("{}\n\n{}", error, USAGE);
+            process::exit(2);
+        },
+    };
+
+    let source = match options.program.as_deref() {
+        None | Some("-") => {
+            let mut source = String::new();
+            io::stdin().read_to_string(&mut source)?;
+            source
+        },
+        Some(path) => fs::read_to_string(path)?,
+    };
+    let mut machine = load(&source, &options)?;
+    let result = match options.max_steps {
+        Some(max_steps) => machine.compute_with_budget(max_steps),
+        None => machine.compute(),
+    };
+
+    let outputs = machine.get_outputs();
+    match options.format {
+        Format::Raw => outputs.iter().for_each(|value| println!("{}", value)),
+        Format::Csv => println!("{}", outputs.iter().join(",")),
+        Format::Ascii => decode(&outputs).iter().for_each(|output| print!("{}", output)),
+        Format::Json => {
+            let mut json = serde_json::json!({ "outputs": outputs, "ip": machine.ip() });
+            match &result {
+                Ok(state) => json["state"] = serde_json::to_value(state)?,
+                Err(error) => json["error"] = error.to_string().into(),
+            }
+            if options.dump_memory {
+                json["memory"] = memory(&machine).into();
+            }
+            println!("{}", json);
+        },
+    }
+    if options.dump_memory && options.format != Format::Json {
+        println!("{}", memory(&machine).iter().join(","));
+    }
+    for access in machine.audit_log() {
+        let kind = match access.kind {
+            AccessKind::Read => "read from",
+            AccessKind::Write => "written to",
+        };
+        eprintln!("audit: {} {} by the instruction at {}", access.address, kind, access.ip);
+    }
+
+    match result? {
+        MachineState::Done => Ok(()),
+        MachineState::Waiting => Err("the program is waiting for more input".into()),
+        MachineState::BudgetExhausted => Err(format!("the program didn't halt within {} steps", options.max_steps.unwrap()).into()),
+        state => Err(format!("the program stopped in state {:?}", state).into()),
+    }
+}
+
+#[cfg(test)]
+fn args(args: &str) -> Vec<String> {
+    args.split_whitespace().map(String::from).collect()
+}
+
+#[test]
+fn parses_options() {
+    let options = parse_options(&args("-i 1,2 --set 1=12 -s 2=2 day2.txt --line AB -f csv --max-steps 100 -m -i 3 --strict --audit")).unwrap().unwrap();
+    assert_eq!(options, Options {
+        program: Some("day2.txt".to_string()),
+        inputs: vec![1, 2, 65, 66, 10, 3],
+        patches: vec![(1, 12), (2, 2)],
+        format: Format::Csv,
+        max_steps: Some(100),
+        dump_memory: true,
+        policy: MemoryPolicy { strict_reads: true, read_only_image: false, audit: true },
+    });
+    assert_eq!(parse_options(&args("-")).unwrap().unwrap().program, Some("-".to_string()));
+    assert_eq!(parse_options(&args("-i 1 --help")).unwrap(), None);
+}
+
+#[test]
+fn rejects_invalid_options() {
+    assert_eq!(parse_options(&args("--set 1")), Err("invalid memory patch \"1\", expected <addr>=<value>".to_string()));
+    assert_eq!(parse_options(&args("-f xml")), Err("unknown format: xml".to_string()));
+    assert_eq!(parse_options(&args("-i")), Err("-i needs a value".to_string()));
+    assert_eq!(parse_options(&args("-i x")), Err("invalid number: x".to_string()));
+    assert_eq!(parse_options(&args("a b")), Err("unexpected argument: b".to_string()));
+    assert_eq!(parse_options(&args("--verbose")), Err("unknown option: --verbose".to_string()));
+}
+
+#[test]
+fn patches_beyond_the_program_are_scratch_memory() {
+    // Writes 3 to [100] and outputs it.
+    let program = "1101,1,2,100,4,100,99";
+    let run = |args_: &str| {
+        let options = parse_options(&args(args_)).unwrap().unwrap();
+        let mut machine = load(program, &options).unwrap();
+        machine.compute().map(|_| machine.get_outputs())
+    };
+    assert_eq!(run("--read-only-image"), Ok(vec![3]));
+    assert_eq!(run("--read-only-image --set 200=0"), Ok(vec![3]));
+    assert_eq!(run("--strict --set 5000=0"), Ok(vec![3]));
+    assert_eq!(run("--strict --set 100=5"), Ok(vec![3]));
+}
@@ -0,0 +1,229 @@
+use std::collections::{BTreeMap, BTreeSet};
+use std::fmt::Write;
+use crate::{Address, Opcode, ParamMode, Value};
+use crate::disasm::{decode_reachable, Instruction, Operand};
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum EdgeKind {
+    /// Execution continues with the next instruction, including when a conditional jump isn't taken.
+    Next,
+    Jump,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct BasicBlock {
+    pub start: Address,
+    /// The address after the block's last instruction.
+    pub end: Address,
+    pub instructions: Vec<(Address, Instruction)>,
+    pub successors: Vec<(Address, EdgeKind)>,
+    /// The block ends with a jump whose target is only known at runtime.
+    pub indirect_jump: bool,
+}
+
+/// An instruction that writes to an address occupied by an instruction.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct CodeWrite {
+    pub ip: Address,
+    pub target: Address,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct ControlFlowGraph {
+    pub blocks: BTreeMap<Address, BasicBlock>,
+    pub code_writes: Vec<CodeWrite>,
+}
+
+impl ControlFlowGraph {
+    /// Splits the instructions reachable from address 0 into basic blocks. Only jumps with immediate targets
+    /// can be followed, and writes in relative mode can't be checked for self-modification.
+    pub fn analyze(program: &[Value]) -> Self {
+        let instructions = decode_reachable(program);
+        let mut leaders: BTreeSet<usize> = vec![0].into_iter().collect();
+        for (address, instruction) in &instructions {
+            if ends_block(instruction) {
+                leaders.extend(instruction.successors(*address));
+                leaders.insert(address + instruction.size());
+            }
+        }
+
+        let mut blocks = BTreeMap::new();
+        let mut current: Option<BasicBlock> = None;
+        for (&address, instruction) in &instructions {
+            let continues = current.as_ref().is_some_and(|block| block.end == address as Address);
+            if !continues || leaders.contains(&address) {
+                if let Some(block) = current.take() {
+                    blocks.insert(block.start, finish(block, &instructions));
+                }
+                current = Some(BasicBlock {
+                    start: address as Address, end: address as Address, instructions: vec![], successors: vec![], indirect_jump: false,
+                });
+            }
+            let block = current.as_mut().unwrap();
+            block.end += instruction.size() as Address;
+            block.instructions.push((address as Address, instruction.clone()));
+            if ends_block(instruction) {
+                blocks.insert(block.start, finish(current.take().unwrap(), &instructions));
+            }
+        }
+        if let Some(block) = current {
+            blocks.insert(block.start, finish(block, &instructions));
+        }
+        Self { blocks, code_writes: code_writes(&instructions) }
+    }
+
+    /// The block containing the instruction at `address`.
+    pub fn block_at(&self, address: Address) -> Option<&BasicBlock> {
+        self.blocks.range(..=address).next_back().map(|(_, block)| block).filter(|block| address < block.end)
+    }
+
+    pub fn jump_targets(&self) -> BTreeSet<Address> {
+        self.blocks.values()
+            .flat_map(|block| block.successors.iter())
+            .filter(|(_, kind)| *kind == EdgeKind::Jump)
+            .map(|(target, _)| *target)
+            .collect()
+    }
+
+    pub fn is_self_modifying(&self) -> bool {
+        !self.code_writes.is_empty()
+    }
+
+    /// The graph in Graphviz DOT format. Blocks that write to code are red, indirect jumps lead to a `?` node.
+    pub fn to_dot(&self) -> String {
+        let mut dot = String::new();
+        writeln!(dot, "digraph intcode {{").unwrap();
+        writeln!(dot, "    node [shape=box, fontname=monospace];").unwrap();
+        for block in self.blocks.values() {
+            let label: String = block.instructions.iter()
+                .map(|(address, instruction)| format!("{}: {}\\l", address, instruction))
+                .collect();
+            let writes_code = self.code_writes.iter().any(|write| block.start <= write.ip && write.ip < block.end);
+            let color = if writes_code { ", color=red" } else { "" };
+            writeln!(dot, "    b{} [label=\"{}\"{}];", block.start, label, color).unwrap();
+        }
+        if self.blocks.values().any(|block| block.indirect_jump) {
+            writeln!(dot, "    unknown [label=\"?\", shape=circle];").unwrap();
+        }
+        for block in self.blocks.values() {
+            for (target, kind) in &block.successors {
+                let style = match kind {
+                    EdgeKind::Next => "",
+                    EdgeKind::Jump => " [label=\"jump\"]",
+                };
+                writeln!(dot, "    b{} -> b{}{};", block.start, target, style).unwrap();
+            }
+            if block.indirect_jump {
+                writeln!(dot, "    b{} -> unknown [style=dashed];", block.start).unwrap();
+            }
+        }
+        writeln!(dot, "}}").unwrap();
+        dot
+    }
+}
+
+fn ends_block(instruction: &Instruction) -> bool {
+    matches!(instruction.opcode, Opcode::JumpIfTrue | Opcode::JumpIfFalse | Opcode::Halt)
+}
+
+fn finish(mut block: BasicBlock, instructions: &BTreeMap<usize, Instruction>) -> BasicBlock {
+    let last = &block.instructions.last().unwrap().1;
+    let next = block.end;
+    let mut successors = vec![];
+    match last.opcode {
+        Opcode::Halt => (),
+        Opcode::JumpIfTrue | Opcode::JumpIfFalse => {
+            if may_continue(last) {
+                successors.push((next, EdgeKind::Next));
+            }
+            match last.operands[1] {
+                Operand { mode: ParamMode::Immediate, value } if value >= 0 => if may_jump(last) {
+                    successors.push((value as Address, EdgeKind::Jump));
+                },
+                Operand { mode: ParamMode::Immediate, .. } => (),
+                _ => block.indirect_jump = may_jump(last),
+            }
+        },
+        _ => successors.push((next, EdgeKind::Next)),
+    }
+    block.successors = successors.into_iter().filter(|(target, _)| instructions.contains_key(&(*target as usize))).collect();
+    block
+}
+
+fn may_jump(instruction: &Instruction) -> bool {
+    match instruction.operands[0] {
+        Operand { mode: ParamMode::Immediate, value } => (value != 0) == (instruction.opcode == Opcode::JumpIfTrue),
+        _ => true,
+    }
+}
+
+fn may_continue(instruction: &Instruction) -> bool {
+    match instruction.operands[0] {
+        Operand { mode: ParamMode::Immediate, .. } => !may_jump(instruction),
+        _ => true,
+    }
+}
+
+/// Instructions that write to an address in position mode that one of the instructions occupies.
+pub(crate) fn code_writes(instructions: &BTreeMap<usize, Instruction>) -> Vec<CodeWrite> {
+    let end = instructions.iter().next_back().map_or(0, |(address, instruction)| address + instruction.size());
+    let mut code = vec![false; end];
+    for (address, instruction) in instructions {
+        for cell in &mut code[*address..(address + instruction.size())] {
+            *cell = true;
+        }
+    }
+    instructions.iter()
+        .filter_map(|(address, instruction)| match instruction.opcode.write_param().map(|pos| instruction.operands[pos]) {
+            Some(Operand { mode: ParamMode::Position, value }) if value >= 0 && code.get(value as usize) == Some(&true) =>
+                Some(CodeWrite { ip: *address as Address, target: value as Address }),
+            _ => None,
+        })
+        .collect()
+}
+
+#[test]
+fn test_basic_blocks() {
+    // in [11]; jt [11], #8; out #0; hlt; out #1; hlt
+    let cfg = ControlFlowGraph::analyze(&[3,11,1005,11,8,104,0,99,104,1,99,0]);
+    assert_eq!(cfg.blocks.keys().copied().collect::<Vec<_>>(), vec![0, 5, 8]);
+    assert_eq!(cfg.blocks[&0].end, 5);
+    assert_eq!(cfg.blocks[&0].successors, vec![(5, EdgeKind::Next), (8, EdgeKind::Jump)]);
+    assert!(cfg.blocks[&5].successors.is_empty());
+    assert_eq!(cfg.jump_targets(), vec![8].into_iter().collect());
+    assert_eq!(cfg.block_at(6).map(|block| block.start), Some(5));
+    assert_eq!(cfg.block_at(11), None);
+    assert!(!cfg.is_self_modifying());
+}
+
+#[test]
+fn test_loops_split_blocks() {
+    // Counts [100] up to 10: the loop body starts at 4, in the middle of straight-line code.
+    let cfg = ControlFlowGraph::analyze(&[1101,0,0,100,1001,100,1,100,1007,100,10,101,1005,101,4,4,100,99]);
+    assert_eq!(cfg.blocks.keys().copied().collect::<Vec<_>>(), vec![0, 4, 15]);
+    assert_eq!(cfg.blocks[&0].successors, vec![(4, EdgeKind::Next)]);
+    assert_eq!(cfg.blocks[&4].successors, vec![(15, EdgeKind::Next), (4, EdgeKind::Jump)]);
+}
+
+#[test]
+fn test_self_modification() {
+    let cfg = ControlFlowGraph::analyze(&[1,9,10,3,2,3,11,0,99,30,40,50]);
+    assert_eq!(cfg.code_writes, vec![CodeWrite { ip: 0, target: 3 }, CodeWrite { ip: 4, target: 0 }]);
+    assert!(cfg.to_dot().contains("b0 [label=\"0: add [9], [10], [3]\\l4: mul [3], [11], [0]\\l8: hlt\\l\", color=red];"));
+}
+
+#[test]
+fn test_dot_export() {
+    // in [9]; jf [9], rb+0; hlt
+    let cfg = ControlFlowGraph::analyze(&[3,9,2006,9,0,99,0,0,0,0]);
+    assert!(cfg.blocks[&0].indirect_jump);
+    assert_eq!(cfg.to_dot(), "digraph intcode {
+    node [shape=box, fontname=monospace];
+    b0 [label=\"0: in [9]\\l2: jf [9], rb+0\\l\"];
+    b5 [label=\"5: hlt\\l\"];
+    unknown [label=\"?\", shape=circle];
+    b0 -> b5;
+    b0 -> unknown [style=dashed];
+}
+");
+}
@@ -0,0 +1,434 @@
+use std::collections::BTreeMap;
+use std::fmt::Write;
+use std::mem;
+use itertools::Itertools;
+use crate::{Address, InputSource, IntcodeError, IntcodeMachine, MachineState, Memory, Opcode, OutputSink, ParamMode, Value};
+use crate::cfg::code_writes;
+use crate::disasm::{Instruction, Operand};
+
+/// Signature of the function a translated program is compiled to.
+pub type CompiledFn = fn(&mut Cpu, &mut dyn InputSource, &mut dyn OutputSink) -> Result<Exit, IntcodeError>;
+
+/// Why compiled code stopped running.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Exit {
+    Halted,
+    /// Stopped before an input instruction that had no input, like `MachineState::Waiting`.
+    Waiting,
+    /// The instruction pointer is somewhere that wasn't compiled, or the code has been modified.
+    Interpret,
+}
+
+/// The machine state compiled code operates on.
+#[derive(Clone)]
+pub struct Cpu {
+    machine: IntcodeMachine,
+    /// Whether an address is occupied by a compiled instruction.
+    code: Vec<bool>,
+    modified: bool,
+}
+
+impl Cpu {
+    #[inline]
+    pub fn ip(&self) -> Address {
+        self.machine.ip
+    }
+
+    #[inline]
+    pub fn jump(&mut self, address: Address) {
+        self.machine.ip = address;
+    }
+
+    #[inline]
+    pub fn load(&self, address: Address) -> Value {
+        self.machine.memory.get(address)
+    }
+
+    /// Fails where the interpreter would: beyond the memory limit, or where the memory policy forbids writing.
+    #[inline]
+    pub fn check_store(&mut self, address: Address) -> Result<(), IntcodeError> {
+        self.machine.check_limit(address)?;
+        self.machine.check_write(address)
+    }
+
+    /// Returns whether the write modified compiled code, which then must not run anymore.
+    #[inline]
+    pub fn store(&mut self, address: Address, value: Value) -> Result<bool, IntcodeError> {
+        self.check_store(address)?;
+        self.machine.write(address, value);
+        self.check_code(address);
+        Ok(self.modified)
+    }
+
+    #[inline]
+    fn check_code(&mut self, address: Address) {
+        if self.code.get(address as usize) == Some(&true) {
+            self.modified = true;
+        }
+    }
+
+    #[inline]
+    pub fn address(value: Value) -> Result<Address, IntcodeError> {
+        crate::to_address(value)
+    }
+
+    #[inline]
+    pub fn relative(&self, offset: Value) -> Result<Address, IntcodeError> {
+        Self::address(self.machine.relative(&offset)?)
+    }
+
+    #[inline]
+    pub fn adjust_relative_base(&mut self, offset: Value) -> Result<(), IntcodeError> {
+        let relative_base = self.machine.relative(&offset)?;
+        if relative_base < 0 {
+            return Err(IntcodeError::RelativeBaseUnderflow { relative_base: self.machine.relative_base, offset });
+        }
+        self.machine.relative_base = relative_base as Address;
+        Ok(())
+    }
+}
+
+/// A program translated by `translate`. Runs the compiled code where it can and the interpreter everywhere
+/// else: at addresses the translator didn't find instructions at, and for good once the program modifies its
+/// own code.
+#[derive(Clone)]
+pub struct CompiledMachine {
+    cpu: Cpu,
+    run: CompiledFn,
+}
+
+impl CompiledMachine {
+    /// `code` are the ranges of addresses the compiled instructions occupy.
+    pub fn new(program: &[Value], code: &[(Address, Address)], run: CompiledFn) -> Self {
+        let mut mask = vec![false; program.len()];
+        for &(start, end) in code {
+            for cell in &mut mask[start as usize..end as usize] {
+                *cell = true;
+            }
+        }
+        let machine = IntcodeMachine::from_program(&program.to_vec().into());
+        Self { cpu: Cpu { machine, code: mask, modified: false }, run }
+    }
+
+    pub fn ip(&self) -> Address {
+        self.cpu.machine.ip
+    }
+
+    pub fn state(&self) -> MachineState {
+        self.cpu.machine.state
+    }
+
+    /// Whether the program has modified its code, so that only the interpreter runs it from now on.
+    pub fn is_interpreted(&self) -> bool {
+        self.cpu.modified
+    }
+
+    pub fn machine(&self) -> &IntcodeMachine {
+        &self.cpu.machine
+    }
+
+    pub fn into_machine(self) -> IntcodeMachine {
+        self.cpu.machine
+    }
+
+    pub fn get_memory(&self, address: Address) -> Value {
+        self.cpu.machine.get_memory(address)
+    }
+
+    pub fn push_input(&mut self, input: Value) {
+        self.cpu.machine.push_input(input);
+    }
+
+    pub fn get_outputs(&self) -> Vec<Value> {
+        self.cpu.machine.get_outputs()
+    }
+
+    pub fn compute(&mut self) -> Result<MachineState, IntcodeError> {
+        let mut input = mem::take(&mut self.cpu.machine.input);
+        let mut output = mem::take(&mut self.cpu.machine.output);
+        let result = self.compute_with(&mut input, &mut output);
+        self.cpu.machine.input = input;
+        self.cpu.machine.output = output;
+        result
+    }
+
+    pub fn compute_with(&mut self, input: &mut impl InputSource, output: &mut impl OutputSink) -> Result<MachineState, IntcodeError> {
+        loop {
+            if self.cpu.modified {
+                return self.cpu.machine.compute_with(input, output);
+            }
+            self.cpu.machine.state = MachineState::Running;
+            let state = match (self.run)(&mut self.cpu, input, output)? {
+                Exit::Halted => MachineState::Done,
+                Exit::Waiting => MachineState::Waiting,
+                Exit::Interpret if self.cpu.modified => continue,
+                // Step until execution gets back to compiled code.
+                Exit::Interpret => {
+                    let state = self.cpu.machine.step_with(input, output)?;
+                    // Interpreted instructions can modify the compiled code too.
+                    if let Some((address, _)) = self.cpu.machine.last_write {
+                        self.cpu.check_code(address);
+                    }
+                    match state {
+                        MachineState::Running => continue,
+                        state => state,
+                    }
+                },
+            };
+            self.cpu.machine.state = state;
+            return Ok(state);
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Translation {
+    /// A module named after the program, with the program's `run` function and a `machine()` that creates a
+    /// `CompiledMachine` for it.
+    pub source: String,
+    /// Instructions that write to addresses occupied by compiled instructions. The program falls back to the
+    /// interpreter once one of them runs.
+    pub self_modifying: Vec<Address>,
+}
+
+/// Translates a program to Rust source, e.g. from a `build.rs` that writes `source` to `OUT_DIR` for the
+/// crate to `include!` it.
+pub fn translate(program: &[Value], module: &str) -> Translation {
+    let instructions = find_instructions(program);
+    let mut code = vec![false; program.len()];
+    for (address, instruction) in &instructions {
+        for cell in &mut code[*address..(address + instruction.size())] {
+            *cell = true;
+        }
+    }
+    let self_modifying = code_writes(&instructions).iter().map(|write| write.ip).collect();
+    let ranges = code.iter().enumerate()
+        .group_by(|(_, is_code)| **is_code).into_iter()
+        .filter(|(is_code, _)| *is_code)
+        .map(|(_, mut cells)| {
+            let start = cells.next().unwrap().0;
+            format!("({}, {})", start, start + 1 + cells.count())
+        })
+        .join(", ");
+
+    let mut source = String::new();
+    writeln!(source, "// Generated from an Intcode program by aoc2019::compile, do not edit.").unwrap();
+    writeln!(source, "#[allow(clippy::all, unused)]").unwrap();
+    writeln!(source, "pub mod {} {{", module).unwrap();
+    writeln!(source, "    use aoc2019::{{InputSource, IntcodeError, OutputSink, Value}};").unwrap();
+    writeln!(source, "    use aoc2019::compile::{{CompiledMachine, Cpu, Exit}};").unwrap();
+    writeln!(source).unwrap();
+    writeln!(source, "    pub const PROGRAM: &[Value] = &[{}];", program.iter().join(", ")).unwrap();
+    writeln!(source, "    pub const CODE: &[(u64, u64)] = &[{}];", ranges).unwrap();
+    writeln!(source).unwrap();
+    writeln!(source, "    pub fn machine() -> CompiledMachine {{").unwrap();
+    writeln!(source, "        CompiledMachine::new(PROGRAM, CODE, run)").unwrap();
+    writeln!(source, "    }}").unwrap();
+    writeln!(source).unwrap();
+    writeln!(source, "    pub fn run(cpu: &mut Cpu, input: &mut dyn InputSource, output: &mut dyn OutputSink) -> Result<Exit, IntcodeError> {{").unwrap();
+    writeln!(source, "        loop {{").unwrap();
+    writeln!(source, "            match cpu.ip() {{").unwrap();
+    for (address, instruction) in &instructions {
+        writeln!(source, "                // {}", instruction).unwrap();
+        writeln!(source, "                {} => {{", address).unwrap();
+        for line in translate_instruction(instruction, *address) {
+            writeln!(source, "                    {}", line).unwrap();
+        }
+        writeln!(source, "                }},").unwrap();
+    }
+    writeln!(source, "                _ => return Ok(Exit::Interpret),").unwrap();
+    writeln!(source, "            }}").unwrap();
+    writeln!(source, "        }}").unwrap();
+    writeln!(source, "    }}").unwrap();
+    writeln!(source, "}}").unwrap();
+    Translation { source, self_modifying }
+}
+
+/// The instructions reachable from address 0. Since calls usually return to the instruction after the jump,
+/// that instruction counts as reachable even if the jump is always taken.
+fn find_instructions(program: &[Value]) -> BTreeMap<usize, Instruction> {
+    let mut instructions = BTreeMap::new();
+    let mut pending = vec![0];
+    while let Some(address) = pending.pop() {
+        if instructions.contains_key(&address) {
+            continue;
+        }
+        let instruction = match Instruction::decode(program, address) {
+            Some(instruction) => instruction,
+            None => continue,
+        };
+        pending.extend(instruction.successors(address));
+        if let Opcode::JumpIfTrue | Opcode::JumpIfFalse = instruction.opcode {
+            pending.push(address + instruction.size());
+        }
+        instructions.insert(address, instruction);
+    }
+    instructions
+}
+
+fn read(operand: &Operand) -> String {
+    match operand.mode {
+        ParamMode::Immediate => operand.value.to_string(),
+        ParamMode::Position if operand.value >= 0 => format!("cpu.load({})", operand.value),
+        ParamMode::Position => format!("cpu.load(Cpu::address({})?)", operand.value),
+        ParamMode::Relative => format!("cpu.load(cpu.relative({})?)", operand.value),
+    }
+}
+
+fn write_address(operand: &Operand) -> String {
+    match operand.mode {
+        ParamMode::Position if operand.value >= 0 => operand.value.to_string(),
+        ParamMode::Relative => format!("cpu.relative({})?", operand.value),
+        _ => format!("Cpu::address({})?", operand.value),
+    }
+}
+
+fn store(address: &str, value: &str, next: usize) -> Vec<String> {
+    vec![
+        format!("let modified = cpu.store({}, {})?;", address, value),
+        format!("cpu.jump({});", next),
+        "if modified { return Ok(Exit::Interpret); }".to_string(),
+    ]
+}
+
+/// Like the interpreter, reads the operands before looking at the address to write to.
+fn compute(address: &str, value: &str, next: usize) -> Vec<String> {
+    let mut lines = vec![format!("let value = {};", value)];
+    lines.extend(store(address, "value", next));
+    lines
+}
+
+fn translate_instruction(instruction: &Instruction, address: usize) -> Vec<String> {
+    let ops = &instruction.operands;
+    let next = address + instruction.size();
+    let overflow = format!("ok_or(IntcodeError::Overflow {{ ip: {} }})?", address);
+    match instruction.opcode {
+        Opcode::Add => compute(&write_address(&ops[2]), &format!("Value::checked_add({}, {}).{}", read(&ops[0]), read(&ops[1]), overflow), next),
+        Opcode::Mul => compute(&write_address(&ops[2]), &format!("Value::checked_mul({}, {}).{}", read(&ops[0]), read(&ops[1]), overflow), next),
+        Opcode::LessThan => compute(&write_address(&ops[2]), &format!("({} < {}) as Value", read(&ops[0]), read(&ops[1])), next),
+        Opcode::Equals => compute(&write_address(&ops[2]), &format!("({} == {}) as Value", read(&ops[0]), read(&ops[1])), next),
+        Opcode::Input => {
+            // Like the interpreter, make sure the write will work before consuming the value.
+            let mut lines = vec![
+                format!("let address = {};", write_address(&ops[0])),
+                "cpu.check_store(address)?;".to_string(),
+                "let value = match input.read_input() { Some(value) => value, None => return Ok(Exit::Waiting) };".to_string(),
+            ];
+            lines.extend(store("address", "value", next));
+            lines
+        },
+        Opcode::Output => vec![format!("output.write_output({})?;", read(&ops[0])), format!("cpu.jump({});", next)],
+        Opcode::JumpIfTrue | Opcode::JumpIfFalse => {
+            let target = match ops[1] {
+                Operand { mode: ParamMode::Immediate, value } if value >= 0 => value.to_string(),
+                operand => format!("Cpu::address({})?", read(&operand)),
+            };
+            let comparison = if instruction.opcode == Opcode::JumpIfTrue { "!=" } else { "==" };
+            vec![format!("if {} {} 0 {{ cpu.jump({}); }} else {{ cpu.jump({}); }}", read(&ops[0]), comparison, target, next)]
+        },
+        Opcode::AdjustRelativeBase => vec![format!("cpu.adjust_relative_base({})?;", read(&ops[0])), format!("cpu.jump({});", next)],
+        Opcode::Halt => vec!["return Ok(Exit::Halted);".to_string()],
+    }
+}
+
+#[cfg(test)]
+mod examples;
+
+#[cfg(test)]
+const EXAMPLES: [(&str, &str); 13] = [
+    ("compare_to_8", "3,21,1008,21,8,20,1005,20,22,107,8,21,20,1006,20,31,1106,0,36,98,0,0,1002,21,125,20,4,20,1105,1,46,104,999,1105,1,46,1101,1000,1,20,4,20,1105,1,46,98,99"),
+    ("quine", "109,1,204,-1,1001,100,1,100,1008,100,16,101,1006,101,0,99"),
+    ("day2", "1,9,10,3,2,3,11,0,99,30,40,50"),
+    // Doubles its input in a function that returns through the stack.
+    ("call", "109,100,3,50,21101,11,0,0,1105,1,15,4,50,99,0,1002,50,2,50,2106,0,0"),
+    // Overwrites the operand of its output instruction.
+    ("patch", "1101,42,0,5,104,1,99"),
+    ("echo", "3,9,4,9,1105,1,0,99,99,0"),
+    // The counting loop from the benchmarks.
+    ("counting_loop", "1101,0,0,100,1001,100,1,100,1007,100,100000,101,1005,101,4,4,100,99"),
+    // Reads a value into the first address beyond the default memory limit.
+    ("memory_limit", "3,1048576,99"),
+    // Fail where the interpreter fails because a value or the relative base overflows.
+    ("overflow", "1101,9223372036854775807,1,0,99"),
+    ("relative_overflow", "109,9223372036854775807,204,1,99"),
+    ("relative_base_overflow", "109,9223372036854775807,109,1,99"),
+    ("relative_base_underflow", "109,5,109,-6,99"),
+    // Jumps to code the translator can't find, which overwrites the operand of the output instruction.
+    ("interpreted_patch", "6,30,31,104,1,99,0,0,0,0,1101,7,0,4,1105,1,3,0,0,0,0,0,0,0,0,0,0,0,0,0,0,10"),
+];
+
+/// Runs a program compiled and interpreted, and compares everything the two did.
+#[cfg(test)]
+fn assert_same_behaviour(mut compiled: CompiledMachine, source: &str, inputs: &[Value]) {
+    let mut interpreted = IntcodeMachine::from_string(source).unwrap();
+    for &value in inputs {
+        compiled.push_input(value);
+        interpreted.push_input(value);
+    }
+    assert_eq!(compiled.compute(), interpreted.compute(), "{} with {:?}", source, inputs);
+    assert_eq!(compiled.ip(), interpreted.ip());
+    assert_eq!(compiled.get_outputs(), interpreted.get_outputs());
+    assert_eq!(compiled.machine().pending_input(), interpreted.pending_input());
+    assert_eq!(compiled.into_machine().snapshot(), interpreted.snapshot());
+}
+
+#[test]
+fn test_examples_are_up_to_date() {
+    let expected = EXAMPLES.iter()
+        .map(|(name, source)| translate(&source.parse::<crate::Program>().unwrap(), name).source)
+        .join("\n");
+    assert_eq!(include_str!("compile/examples.rs"), expected, "regenerate src/compile/examples.rs with intcode-compile");
+}
+
+#[test]
+fn test_differential() {
+    let (_, compare_to_8) = EXAMPLES[0];
+    for input in 0..16 {
+        assert_same_behaviour(examples::compare_to_8::machine(), compare_to_8, &[input]);
+    }
+    assert_same_behaviour(examples::quine::machine(), EXAMPLES[1].1, &[]);
+    assert_same_behaviour(examples::day2::machine(), EXAMPLES[2].1, &[]);
+    for input in -3..3 {
+        assert_same_behaviour(examples::call::machine(), EXAMPLES[3].1, &[input]);
+    }
+    assert_same_behaviour(examples::patch::machine(), EXAMPLES[4].1, &[]);
+    assert_same_behaviour(examples::echo::machine(), EXAMPLES[5].1, &[1, 2, 3]);
+    assert_same_behaviour(examples::counting_loop::machine(), EXAMPLES[6].1, &[]);
+    assert_same_behaviour(examples::memory_limit::machine(), EXAMPLES[7].1, &[5]);
+    assert_same_behaviour(examples::overflow::machine(), EXAMPLES[8].1, &[]);
+    assert_same_behaviour(examples::relative_overflow::machine(), EXAMPLES[9].1, &[]);
+    assert_same_behaviour(examples::relative_base_overflow::machine(), EXAMPLES[10].1, &[]);
+    assert_same_behaviour(examples::relative_base_underflow::machine(), EXAMPLES[11].1, &[]);
+    assert_same_behaviour(examples::interpreted_patch::machine(), EXAMPLES[12].1, &[]);
+}
+
+#[test]
+fn test_self_modification() {
+    let program = |index: usize| EXAMPLES[index].1.parse::<crate::Program>().unwrap();
+    assert_eq!(translate(&program(2), "day2").self_modifying, vec![0, 4]);
+    assert_eq!(translate(&program(4), "patch").self_modifying, vec![0]);
+    assert!(translate(&program(3), "call").self_modifying.is_empty());
+    let mut patch = examples::patch::machine();
+    assert_eq!(patch.compute(), Ok(MachineState::Done));
+    assert!(patch.is_interpreted());
+    assert_eq!(patch.get_outputs(), vec![42]);
+    let mut call = examples::call::machine();
+    call.push_input(21);
+    assert_eq!(call.compute(), Ok(MachineState::Done));
+    assert!(!call.is_interpreted());
+    assert_eq!(call.get_outputs(), vec![42]);
+}
+
+#[test]
+fn test_waiting() {
+    let mut echo = examples::echo::machine();
+    assert_eq!(echo.compute(), Ok(MachineState::Waiting));
+    assert_eq!(echo.ip(), 0);
+    echo.push_input(5);
+    assert_eq!(echo.compute(), Ok(MachineState::Waiting));
+    assert_eq!(echo.get_outputs(), vec![5]);
+    let mut inputs = vec![8, 9].into_iter();
+    let mut output = vec![];
+    assert_eq!(echo.compute_with(&mut || inputs.next(), &mut output), Ok(MachineState::Waiting));
+    assert_eq!(output, vec![8, 9]);
+}
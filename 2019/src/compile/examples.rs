@@ -0,0 +1,611 @@
+// Generated from an Intcode program by aoc2019::compile, do not edit.
+#[allow(clippy::all, unused)]
+pub mod compare_to_8 {
+    use aoc2019::{InputSource, IntcodeError, OutputSink, Value};
+    use aoc2019::compile::{CompiledMachine, Cpu, Exit};
+
+    pub const PROGRAM: &[Value] = &[3, 21, 1008, 21, 8, 20, 1005, 20, 22, 107, 8, 21, 20, 1006, 20, 31, 1106, 0, 36, 98, 0, 0, 1002, 21, 125, 20, 4, 20, 1105, 1, 46, 104, 999, 1105, 1, 46, 1101, 1000, 1, 20, 4, 20, 1105, 1, 46, 98, 99];
+    pub const CODE: &[(u64, u64)] = &[(0, 19), (22, 45), (46, 47)];
+
+    pub fn machine() -> CompiledMachine {
+        CompiledMachine::new(PROGRAM, CODE, run)
+    }
+
+    pub fn run(cpu: &mut Cpu, input: &mut dyn InputSource, output: &mut dyn OutputSink) -> Result<Exit, IntcodeError> {
+        loop {
+            match cpu.ip() {
+                // in [21]
+                0 => {
+                    let address = 21;
+                    cpu.check_store(address)?;
+                    let value = match input.read_input() { Some(value) => value, None => return Ok(Exit::Waiting) };
+                    let modified = cpu.store(address, value)?;
+                    cpu.jump(2);
+                    if modified { return Ok(Exit::Interpret); }
+                },
+                // eq [21], #8, [20]
+                2 => {
+                    let value = (cpu.load(21) == 8) as Value;
+                    let modified = cpu.store(20, value)?;
+                    cpu.jump(6);
+                    if modified { return Ok(Exit::Interpret); }
+                },
+                // jt [20], #22
+                6 => {
+                    if cpu.load(20) != 0 { cpu.jump(22); } else { cpu.jump(9); }
+                },
+                // lt #8, [21], [20]
+                9 => {
+                    let value = (8 < cpu.load(21)) as Value;
+                    let modified = cpu.store(20, value)?;
+                    cpu.jump(13);
+                    if modified { return Ok(Exit::Interpret); }
+                },
+                // jf [20], #31
+                13 => {
+                    if cpu.load(20) == 0 { cpu.jump(31); } else { cpu.jump(16); }
+                },
+                // jf #0, #36
+                16 => {
+                    if 0 == 0 { cpu.jump(36); } else { cpu.jump(19); }
+                },
+                // mul [21], #125, [20]
+                22 => {
+                    let value = Value::checked_mul(cpu.load(21), 125).ok_or(IntcodeError::Overflow { ip: 22 })?;
+                    let modified = cpu.store(20, value)?;
+                    cpu.jump(26);
+                    if modified { return Ok(Exit::Interpret); }
+                },
+                // out [20]
+                26 => {
+                    output.write_output(cpu.load(20))?;
+                    cpu.jump(28);
+                },
+                // jt #1, #46
+                28 => {
+                    if 1 != 0 { cpu.jump(46); } else { cpu.jump(31); }
+                },
+                // out #999
+                31 => {
+                    output.write_output(999)?;
+                    cpu.jump(33);
+                },
+                // jt #1, #46
+                33 => {
+                    if 1 != 0 { cpu.jump(46); } else { cpu.jump(36); }
+                },
+                // add #1000, #1, [20]
+                36 => {
+                    let value = Value::checked_add(1000, 1).ok_or(IntcodeError::Overflow { ip: 36 })?;
+                    let modified = cpu.store(20, value)?;
+                    cpu.jump(40);
+                    if modified { return Ok(Exit::Interpret); }
+                },
+                // out [20]
+                40 => {
+                    output.write_output(cpu.load(20))?;
+                    cpu.jump(42);
+                },
+                // jt #1, #46
+                42 => {
+                    if 1 != 0 { cpu.jump(46); } else { cpu.jump(45); }
+                },
+                // hlt
+                46 => {
+                    return Ok(Exit::Halted);
+                },
+                _ => return Ok(Exit::Interpret),
+            }
+        }
+    }
+}
+
+// Generated from an Intcode program by aoc2019::compile, do not edit.
+#[allow(clippy::all, unused)]
+pub mod quine {
+    use aoc2019::{InputSource, IntcodeError, OutputSink, Value};
+    use aoc2019::compile::{CompiledMachine, Cpu, Exit};
+
+    pub const PROGRAM: &[Value] = &[109, 1, 204, -1, 1001, 100, 1, 100, 1008, 100, 16, 101, 1006, 101, 0, 99];
+    pub const CODE: &[(u64, u64)] = &[(0, 16)];
+
+    pub fn machine() -> CompiledMachine {
+        CompiledMachine::new(PROGRAM, CODE, run)
+    }
+
+    pub fn run(cpu: &mut Cpu, input: &mut dyn InputSource, output: &mut dyn OutputSink) -> Result<Exit, IntcodeError> {
+        loop {
+            match cpu.ip() {
+                // arb #1
+                0 => {
+                    cpu.adjust_relative_base(1)?;
+                    cpu.jump(2);
+                },
+                // out rb-1
+                2 => {
+                    output.write_output(cpu.load(cpu.relative(-1)?))?;
+                    cpu.jump(4);
+                },
+                // add [100], #1, [100]
+                4 => {
+                    let value = Value::checked_add(cpu.load(100), 1).ok_or(IntcodeError::Overflow { ip: 4 })?;
+                    let modified = cpu.store(100, value)?;
+                    cpu.jump(8);
+                    if modified { return Ok(Exit::Interpret); }
+                },
+                // eq [100], #16, [101]
+                8 => {
+                    let value = (cpu.load(100) == 16) as Value;
+                    let modified = cpu.store(101, value)?;
+                    cpu.jump(12);
+                    if modified { return Ok(Exit::Interpret); }
+                },
+                // jf [101], #0
+                12 => {
+                    if cpu.load(101) == 0 { cpu.jump(0); } else { cpu.jump(15); }
+                },
+                // hlt
+                15 => {
+                    return Ok(Exit::Halted);
+                },
+                _ => return Ok(Exit::Interpret),
+            }
+        }
+    }
+}
+
+// Generated from an Intcode program by aoc2019::compile, do not edit.
+#[allow(clippy::all, unused)]
+pub mod day2 {
+    use aoc2019::{InputSource, IntcodeError, OutputSink, Value};
+    use aoc2019::compile::{CompiledMachine, Cpu, Exit};
+
+    pub const PROGRAM: &[Value] = &[1, 9, 10, 3, 2, 3, 11, 0, 99, 30, 40, 50];
+    pub const CODE: &[(u64, u64)] = &[(0, 9)];
+
+    pub fn machine() -> CompiledMachine {
+        CompiledMachine::new(PROGRAM, CODE, run)
+    }
+
+    pub fn run(cpu: &mut Cpu, input: &mut dyn InputSource, output: &mut dyn OutputSink) -> Result<Exit, IntcodeError> {
+        loop {
+            match cpu.ip() {
+                // add [9], [10], [3]
+                0 => {
+                    let value = Value::checked_add(cpu.load(9), cpu.load(10)).ok_or(IntcodeError::Overflow { ip: 0 })?;
+                    let modified = cpu.store(3, value)?;
+                    cpu.jump(4);
+                    if modified { return Ok(Exit::Interpret); }
+                },
+                // mul [3], [11], [0]
+                4 => {
+                    let value = Value::checked_mul(cpu.load(3), cpu.load(11)).ok_or(IntcodeError::Overflow { ip: 4 })?;
+                    let modified = cpu.store(0, value)?;
+                    cpu.jump(8);
+                    if modified { return Ok(Exit::Interpret); }
+                },
+                // hlt
+                8 => {
+                    return Ok(Exit::Halted);
+                },
+                _ => return Ok(Exit::Interpret),
+            }
+        }
+    }
+}
+
+// Generated from an Intcode program by aoc2019::compile, do not edit.
+#[allow(clippy::all, unused)]
+pub mod call {
+    use aoc2019::{InputSource, IntcodeError, OutputSink, Value};
+    use aoc2019::compile::{CompiledMachine, Cpu, Exit};
+
+    pub const PROGRAM: &[Value] = &[109, 100, 3, 50, 21101, 11, 0, 0, 1105, 1, 15, 4, 50, 99, 0, 1002, 50, 2, 50, 2106, 0, 0];
+    pub const CODE: &[(u64, u64)] = &[(0, 14), (15, 22)];
+
+    pub fn machine() -> CompiledMachine {
+        CompiledMachine::new(PROGRAM, CODE, run)
+    }
+
+    pub fn run(cpu: &mut Cpu, input: &mut dyn InputSource, output: &mut dyn OutputSink) -> Result<Exit, IntcodeError> {
+        loop {
+            match cpu.ip() {
+                // arb #100
+                0 => {
+                    cpu.adjust_relative_base(100)?;
+                    cpu.jump(2);
+                },
+                // in [50]
+                2 => {
+                    let address = 50;
+                    cpu.check_store(address)?;
+                    let value = match input.read_input() { Some(value) => value, None => return Ok(Exit::Waiting) };
+                    let modified = cpu.store(address, value)?;
+                    cpu.jump(4);
+                    if modified { return Ok(Exit::Interpret); }
+                },
+                // add #11, #0, rb+0
+                4 => {
+                    let value = Value::checked_add(11, 0).ok_or(IntcodeError::Overflow { ip: 4 })?;
+                    let modified = cpu.store(cpu.relative(0)?, value)?;
+                    cpu.jump(8);
+                    if modified { return Ok(Exit::Interpret); }
+                },
+                // jt #1, #15
+                8 => {
+                    if 1 != 0 { cpu.jump(15); } else { cpu.jump(11); }
+                },
+                // out [50]
+                11 => {
+                    output.write_output(cpu.load(50))?;
+                    cpu.jump(13);
+                },
+                // hlt
+                13 => {
+                    return Ok(Exit::Halted);
+                },
+                // mul [50], #2, [50]
+                15 => {
+                    let value = Value::checked_mul(cpu.load(50), 2).ok_or(IntcodeError::Overflow { ip: 15 })?;
+                    let modified = cpu.store(50, value)?;
+                    cpu.jump(19);
+                    if modified { return Ok(Exit::Interpret); }
+                },
+                // jf #0, rb+0
+                19 => {
+                    if 0 == 0 { cpu.jump(Cpu::address(cpu.load(cpu.relative(0)?))?); } else { cpu.jump(22); }
+                },
+                _ => return Ok(Exit::Interpret),
+            }
+        }
+    }
+}
+
+// Generated from an Intcode program by aoc2019::compile, do not edit.
+#[allow(clippy::all, unused)]
+pub mod patch {
+    use aoc2019::{InputSource, IntcodeError, OutputSink, Value};
+    use aoc2019::compile::{CompiledMachine, Cpu, Exit};
+
+    pub const PROGRAM: &[Value] = &[1101, 42, 0, 5, 104, 1, 99];
+    pub const CODE: &[(u64, u64)] = &[(0, 7)];
+
+    pub fn machine() -> CompiledMachine {
+        CompiledMachine::new(PROGRAM, CODE, run)
+    }
+
+    pub fn run(cpu: &mut Cpu, input: &mut dyn InputSource, output: &mut dyn OutputSink) -> Result<Exit, IntcodeError> {
+        loop {
+            match cpu.ip() {
+                // add #42, #0, [5]
+                0 => {
+                    let value = Value::checked_add(42, 0).ok_or(IntcodeError::Overflow { ip: 0 })?;
+                    let modified = cpu.store(5, value)?;
+                    cpu.jump(4);
+                    if modified { return Ok(Exit::Interpret); }
+                },
+                // out #1
+                4 => {
+                    output.write_output(1)?;
+                    cpu.jump(6);
+                },
+                // hlt
+                6 => {
+                    return Ok(Exit::Halted);
+                },
+                _ => return Ok(Exit::Interpret),
+            }
+        }
+    }
+}
+
+// Generated from an Intcode program by aoc2019::compile, do not edit.
+#[allow(clippy::all, unused)]
+pub mod echo {
+    use aoc2019::{InputSource, IntcodeError, OutputSink, Value};
+    use aoc2019::compile::{CompiledMachine, Cpu, Exit};
+
+    pub const PROGRAM: &[Value] = &[3, 9, 4, 9, 1105, 1, 0, 99, 99, 0];
+    pub const CODE: &[(u64, u64)] = &[(0, 8)];
+
+    pub fn machine() -> CompiledMachine {
+        CompiledMachine::new(PROGRAM, CODE, run)
+    }
+
+    pub fn run(cpu: &mut Cpu, input: &mut dyn InputSource, output: &mut dyn OutputSink) -> Result<Exit, IntcodeError> {
+        loop {
+            match cpu.ip() {
+                // in [9]
+                0 => {
+                    let address = 9;
+                    cpu.check_store(address)?;
+                    let value = match input.read_input() { Some(value) => value, None => return Ok(Exit::Waiting) };
+                    let modified = cpu.store(address, value)?;
+                    cpu.jump(2);
+                    if modified { return Ok(Exit::Interpret); }
+                },
+                // out [9]
+                2 => {
+                    output.write_output(cpu.load(9))?;
+                    cpu.jump(4);
+                },
+                // jt #1, #0
+                4 => {
+                    if 1 != 0 { cpu.jump(0); } else { cpu.jump(7); }
+                },
+                // hlt
+                7 => {
+                    return Ok(Exit::Halted);
+                },
+                _ => return Ok(Exit::Interpret),
+            }
+        }
+    }
+}
+
+// Generated from an Intcode program by aoc2019::compile, do not edit.
+#[allow(clippy::all, unused)]
+pub mod counting_loop {
+    use aoc2019::{InputSource, IntcodeError, OutputSink, Value};
+    use aoc2019::compile::{CompiledMachine, Cpu, Exit};
+
+    pub const PROGRAM: &[Value] = &[1101, 0, 0, 100, 1001, 100, 1, 100, 1007, 100, 100000, 101, 1005, 101, 4, 4, 100, 99];
+    pub const CODE: &[(u64, u64)] = &[(0, 18)];
+
+    pub fn machine() -> CompiledMachine {
+        CompiledMachine::new(PROGRAM, CODE, run)
+    }
+
+    pub fn run(cpu: &mut Cpu, input: &mut dyn InputSource, output: &mut dyn OutputSink) -> Result<Exit, IntcodeError> {
+        loop {
+            match cpu.ip() {
+                // add #0, #0, [100]
+                0 => {
+                    let value = Value::checked_add(0, 0).ok_or(IntcodeError::Overflow { ip: 0 })?;
+                    let modified = cpu.store(100, value)?;
+                    cpu.jump(4);
+                    if modified { return Ok(Exit::Interpret); }
+                },
+                // add [100], #1, [100]
+                4 => {
+                    let value = Value::checked_add(cpu.load(100), 1).ok_or(IntcodeError::Overflow { ip: 4 })?;
+                    let modified = cpu.store(100, value)?;
+                    cpu.jump(8);
+                    if modified { return Ok(Exit::Interpret); }
+                },
+                // lt [100], #100000, [101]
+                8 => {
+                    let value = (cpu.load(100) < 100000) as Value;
+                    let modified = cpu.store(101, value)?;
+                    cpu.jump(12);
+                    if modified { return Ok(Exit::Interpret); }
+                },
+                // jt [101], #4
+                12 => {
+                    if cpu.load(101) != 0 { cpu.jump(4); } else { cpu.jump(15); }
+                },
+                // out [100]
+                15 => {
+                    output.write_output(cpu.load(100))?;
+                    cpu.jump(17);
+                },
+                // hlt
+                17 => {
+                    return Ok(Exit::Halted);
+                },
+                _ => return Ok(Exit::Interpret),
+            }
+        }
+    }
+}
+
+// Generated from an Intcode program by aoc2019::compile, do not edit.
+#[allow(clippy::all, unused)]
+pub mod memory_limit {
+    use aoc2019::{InputSource, IntcodeError, OutputSink, Value};
+    use aoc2019::compile::{CompiledMachine, Cpu, Exit};
+
+    pub const PROGRAM: &[Value] = &[3, 1048576, 99];
+    pub const CODE: &[(u64, u64)] = &[(0, 3)];
+
+    pub fn machine() -> CompiledMachine {
+        CompiledMachine::new(PROGRAM, CODE, run)
+    }
+
+    pub fn run(cpu: &mut Cpu, input: &mut dyn InputSource, output: &mut dyn OutputSink) -> Result<Exit, IntcodeError> {
+        loop {
+            match cpu.ip() {
+                // in [1048576]
+                0 => {
+                    let address = 1048576;
+                    cpu.check_store(address)?;
+                    let value = match input.read_input() { Some(value) => value, None => return Ok(Exit::Waiting) };
+                    let modified = cpu.store(address, value)?;
+                    cpu.jump(2);
+                    if modified { return Ok(Exit::Interpret); }
+                },
+                // hlt
+                2 => {
+                    return Ok(Exit::Halted);
+                },
+                _ => return Ok(Exit::Interpret),
+            }
+        }
+    }
+}
+
+// Generated from an Intcode program by aoc2019::compile, do not edit.
+#[allow(clippy::all, unused)]
+pub mod overflow {
+    use aoc2019::{InputSource, IntcodeError, OutputSink, Value};
+    use aoc2019::compile::{CompiledMachine, Cpu, Exit};
+
+    pub const PROGRAM: &[Value] = &[1101, 9223372036854775807, 1, 0, 99];
+    pub const CODE: &[(u64, u64)] = &[(0, 5)];
+
+    pub fn machine() -> CompiledMachine {
+        CompiledMachine::new(PROGRAM, CODE, run)
+    }
+
+    pub fn run(cpu: &mut Cpu, input: &mut dyn InputSource, output: &mut dyn OutputSink) -> Result<Exit, IntcodeError> {
+        loop {
+            match cpu.ip() {
+                // add #9223372036854775807, #1, [0]
+                0 => {
+                    let value = Value::checked_add(9223372036854775807, 1).ok_or(IntcodeError::Overflow { ip: 0 })?;
+                    let modified = cpu.store(0, value)?;
+                    cpu.jump(4);
+                    if modified { return Ok(Exit::Interpret); }
+                },
+                // hlt
+                4 => {
+                    return Ok(Exit::Halted);
+                },
+                _ => return Ok(Exit::Interpret),
+            }
+        }
+    }
+}
+
+// Generated from an Intcode program by aoc2019::compile, do not edit.
+#[allow(clippy::all, unused)]
+pub mod relative_overflow {
+    use aoc2019::{InputSource, IntcodeError, OutputSink, Value};
+    use aoc2019::compile::{CompiledMachine, Cpu, Exit};
+
+    pub const PROGRAM: &[Value] = &[109, 9223372036854775807, 204, 1, 99];
+    pub const CODE: &[(u64, u64)] = &[(0, 5)];
+
+    pub fn machine() -> CompiledMachine {
+        CompiledMachine::new(PROGRAM, CODE, run)
+    }
+
+    pub fn run(cpu: &mut Cpu, input: &mut dyn InputSource, output: &mut dyn OutputSink) -> Result<Exit, IntcodeError> {
+        loop {
+            match cpu.ip() {
+                // arb #9223372036854775807
+                0 => {
+                    cpu.adjust_relative_base(9223372036854775807)?;
+                    cpu.jump(2);
+                },
+                // out rb+1
+                2 => {
+                    output.write_output(cpu.load(cpu.relative(1)?))?;
+                    cpu.jump(4);
+                },
+                // hlt
+                4 => {
+                    return Ok(Exit::Halted);
+                },
+                _ => return Ok(Exit::Interpret),
+            }
+        }
+    }
+}
+
+// Generated from an Intcode program by aoc2019::compile, do not edit.
+#[allow(clippy::all, unused)]
+pub mod relative_base_overflow {
+    use aoc2019::{InputSource, IntcodeError, OutputSink, Value};
+    use aoc2019::compile::{CompiledMachine, Cpu, Exit};
+
+    pub const PROGRAM: &[Value] = &[109, 9223372036854775807, 109, 1, 99];
+    pub const CODE: &[(u64, u64)] = &[(0, 5)];
+
+    pub fn machine() -> CompiledMachine {
+        CompiledMachine::new(PROGRAM, CODE, run)
+    }
+
+    pub fn run(cpu: &mut Cpu, input: &mut dyn InputSource, output: &mut dyn OutputSink) -> Result<Exit, IntcodeError> {
+        loop {
+            match cpu.ip() {
+                // arb #9223372036854775807
+                0 => {
+                    cpu.adjust_relative_base(9223372036854775807)?;
+                    cpu.jump(2);
+                },
+                // arb #1
+                2 => {
+                    cpu.adjust_relative_base(1)?;
+                    cpu.jump(4);
+                },
+                // hlt
+                4 => {
+                    return Ok(Exit::Halted);
+                },
+                _ => return Ok(Exit::Interpret),
+            }
+        }
+    }
+}
+
+// Generated from an Intcode program by aoc2019::compile, do not edit.
+#[allow(clippy::all, unused)]
+pub mod relative_base_underflow {
+    use aoc2019::{InputSource, IntcodeError, OutputSink, Value};
+    use aoc2019::compile::{CompiledMachine, Cpu, Exit};
+
+    pub const PROGRAM: &[Value] = &[109, 5, 109, -6, 99];
+    pub const CODE: &[(u64, u64)] = &[(0, 5)];
+
+    pub fn machine() -> CompiledMachine {
+        CompiledMachine::new(PROGRAM, CODE, run)
+    }
+
+    pub fn run(cpu: &mut Cpu, input: &mut dyn InputSource, output: &mut dyn OutputSink) -> Result<Exit, IntcodeError> {
+        loop {
+            match cpu.ip() {
+                // arb #5
+                0 => {
+                    cpu.adjust_relative_base(5)?;
+                    cpu.jump(2);
+                },
+                // arb #-6
+                2 => {
+                    cpu.adjust_relative_base(-6)?;
+                    cpu.jump(4);
+                },
+                // hlt
+                4 => {
+                    return Ok(Exit::Halted);
+                },
+                _ => return Ok(Exit::Interpret),
+            }
+        }
+    }
+}
+
+// Generated from an Intcode program by aoc2019::compile, do not edit.
+#[allow(clippy::all, unused)]
+pub mod interpreted_patch {
+    use aoc2019::{InputSource, IntcodeError, OutputSink, Value};
+    use aoc2019::compile::{CompiledMachine, Cpu, Exit};
+
+    pub const PROGRAM: &[Value] = &[6, 30, 31, 104, 1, 99, 0, 0, 0, 0, 1101, 7, 0, 4, 1105, 1, 3, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 10];
+    pub const CODE: &[(u64, u64)] = &[(0, 6)];
+
+    pub fn machine() -> CompiledMachine {
+        CompiledMachine::new(PROGRAM, CODE, run)
+    }
+
+    pub fn run(cpu: &mut Cpu, input: &mut dyn InputSource, output: &mut dyn OutputSink) -> Result<Exit, IntcodeError> {
+        loop {
+            match cpu.ip() {
+                // jf [30], [31]
+                0 => {
+                    if cpu.load(30) == 0 { cpu.jump(Cpu::address(cpu.load(31))?); } else { cpu.jump(3); }
+                },
+                // out #1
+                3 => {
+                    output.write_output(1)?;
+                    cpu.jump(5);
+                },
+                // hlt
+                5 => {
+                    return Ok(Exit::Halted);
+                },
+                _ => return Ok(Exit::Interpret),
+            }
+        }
+    }
+}
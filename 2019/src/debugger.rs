@@ -0,0 +1,441 @@
+use std::collections::{BTreeSet, VecDeque};
+use crate::{Address, IntcodeError, IntcodeMachine, MachineState, Memory, OpValue, Opcode, Value, VecMemory};
+
+/// How many instructions a recording keeps by default, see `Debugger::set_history_limit`.
+pub const DEFAULT_HISTORY_LIMIT: usize = 1 << 20;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum StopReason {
+    /// A single step was executed and the machine can continue.
+    Stepped,
+    Breakpoint(Address),
+    Watchpoint { address: Address, value: Value },
+    Waiting,
+    Done,
+}
+
+/// What an instruction changed, so that it can be undone.
+struct Record<M: Memory> {
+    ip: Address,
+    relative_base: Address,
+    state: MachineState,
+    /// The address that was written to and the value it had before.
+    write: Option<(Address, Value)>,
+    /// Whether the write initialized scratch memory for the memory policy.
+    first_write: bool,
+    input: Option<Value>,
+    /// The number of outputs after the instruction, if it produced one.
+    output: Option<usize>,
+    /// The whole machine before custom instructions, whose writes can't be predicted.
+    checkpoint: Option<Box<IntcodeMachine<M>>>,
+}
+
+pub struct Debugger<M: Memory = VecMemory> {
+    machine: IntcodeMachine<M>,
+    breakpoints: BTreeSet<Address>,
+    watchpoints: BTreeSet<Address>,
+    steps: u64,
+    /// Where the machine was when the debugger last returned from stepping or running.
+    stopped_at: Option<Address>,
+    history: Option<VecDeque<Record<M>>>,
+    history_limit: usize,
+}
+
+impl<M: Memory<Value = Value>> Debugger<M> {
+    pub fn new(machine: IntcodeMachine<M>) -> Self {
+        Self {
+            machine, breakpoints: BTreeSet::new(), watchpoints: BTreeSet::new(), steps: 0, stopped_at: None, history: None,
+            history_limit: DEFAULT_HISTORY_LIMIT,
+        }
+    }
+
+    pub fn machine(&self) -> &IntcodeMachine<M> {
+        &self.machine
+    }
+
+    pub fn machine_mut(&mut self) -> &mut IntcodeMachine<M> {
+        &mut self.machine
+    }
+
+    pub fn into_machine(self) -> IntcodeMachine<M> {
+        self.machine
+    }
+
+    /// The number of instructions executed so far.
+    pub fn steps(&self) -> u64 {
+        self.steps
+    }
+
+    pub fn add_breakpoint(&mut self, address: Address) -> bool {
+        self.breakpoints.insert(address)
+    }
+
+    pub fn remove_breakpoint(&mut self, address: Address) -> bool {
+        self.breakpoints.remove(&address)
+    }
+
+    pub fn breakpoints(&self) -> &BTreeSet<Address> {
+        &self.breakpoints
+    }
+
+    pub fn add_watchpoint(&mut self, address: Address) -> bool {
+        self.watchpoints.insert(address)
+    }
+
+    pub fn remove_watchpoint(&mut self, address: Address) -> bool {
+        self.watchpoints.remove(&address)
+    }
+
+    pub fn watchpoints(&self) -> &BTreeSet<Address> {
+        &self.watchpoints
+    }
+
+    /// While recording, every executed instruction can be undone. Changes made through `machine_mut` are not
+    /// recorded, so stepping back past them leaves them in place, and neither is the audit log of the memory
+    /// policy. Stopping the recording discards it.
+    pub fn set_recording(&mut self, recording: bool) {
+        if recording != self.is_recording() {
+            self.history = if recording { Some(VecDeque::new()) } else { None };
+        }
+    }
+
+    /// Makes the recording forget the oldest instructions beyond the last `limit`, see `earliest_step`.
+    pub fn set_history_limit(&mut self, limit: usize) {
+        self.history_limit = limit;
+        if let Some(history) = &mut self.history {
+            history.drain(..history.len().saturating_sub(limit));
+        }
+    }
+
+    pub fn is_recording(&self) -> bool {
+        self.history.is_some()
+    }
+
+    /// The earliest step that can be rewound to.
+    pub fn earliest_step(&self) -> u64 {
+        self.steps - self.history.as_ref().map_or(0, |history| history.len() as u64)
+    }
+
+    fn record(&self) -> Record<M> {
+        let machine = &self.machine;
+        let opvalue = OpValue::new(machine.get_memory(machine.ip)).ok();
+        let builtin = opvalue.as_ref()
+            .filter(|opvalue| machine.disabled_opcodes & (1 << opvalue.opcode) == 0)
+            .and_then(|opvalue| Opcode::from_code(opvalue.opcode));
+        let write = match (&opvalue, builtin.and_then(Opcode::write_param)) {
+            (Some(opvalue), Some(pos)) => machine.param(pos).and_then(|param| machine.write_address(opvalue.param_mode(pos), param)).ok()
+                .map(|address| (address, machine.get_memory(address))),
+            _ => None,
+        };
+        let first_write = match (&write, &machine.access) {
+            (Some((address, _)), Some(access)) => !access.is_initialized(*address),
+            _ => false,
+        };
+        Record {
+            ip: machine.ip,
+            relative_base: machine.relative_base,
+            state: machine.state,
+            write,
+            first_write,
+            input: machine.input.front().copied(),
+            output: None,
+            checkpoint: if builtin.is_none() { Some(Box::new(machine.clone())) } else { None },
+        }
+    }
+
+    /// Undoes the last recorded instruction. Returns `false` if there is none.
+    pub fn step_back(&mut self) -> bool {
+        let record = match self.history.as_mut().and_then(|history| history.pop_back()) {
+            Some(record) => record,
+            None => return false,
+        };
+        self.steps -= 1;
+        let machine = &mut self.machine;
+        if let Some(checkpoint) = record.checkpoint {
+            *machine = *checkpoint;
+            self.stopped_at = Some(record.ip);
+            return true;
+        }
+        if let Some((address, value)) = record.write {
+            machine.memory.set(address, value);
+            if let (true, Some(access)) = (record.first_write, &mut machine.access) {
+                access.uninitialize(address);
+            }
+        }
+        if let Some(value) = record.input {
+            machine.input.push_front(value);
+        }
+        if record.output.is_some() && record.output == Some(machine.output.len()) {
+            machine.output.pop();
+        }
+        machine.ip = record.ip;
+        machine.relative_base = record.relative_base;
+        machine.state = record.state;
+        machine.last_write = None;
+        self.stopped_at = Some(record.ip);
+        true
+    }
+
+    /// Steps back until `steps` instructions have been executed. Returns `false`, without stepping back at all,
+    /// if that's before the recording started or after the current step.
+    pub fn rewind_to(&mut self, steps: u64) -> bool {
+        if steps < self.earliest_step() || steps > self.steps {
+            return false;
+        }
+        while self.steps > steps {
+            self.step_back();
+        }
+        true
+    }
+
+    /// Steps back to just before the most recent recorded instruction that wrote to `address`, and returns the
+    /// step it is at then. Calling it again finds the write before that one.
+    pub fn rewind_to_last_write(&mut self, address: Address) -> Option<u64> {
+        let history = self.history.as_ref()?;
+        let index = history.iter().rposition(|record| record.write.map(|(written, _)| written) == Some(address))?;
+        let steps = self.earliest_step() + index as u64;
+        self.rewind_to(steps);
+        Some(steps)
+    }
+
+    /// Executes a single instruction, regardless of breakpoints.
+    pub fn step(&mut self) -> Result<StopReason, IntcodeError> {
+        let mut record = self.history.as_ref().map(|_| self.record());
+        let (inputs, outputs) = (self.machine.input.len(), self.machine.output.len());
+        let state = self.machine.step()?;
+        self.stopped_at = Some(self.machine.ip());
+        if state == MachineState::Waiting {
+            return Ok(StopReason::Waiting);
+        }
+        if let (Some(history), Some(mut record)) = (self.history.as_mut(), record.take()) {
+            if self.machine.input.len() == inputs {
+                record.input = None;
+            }
+            if self.machine.output.len() > outputs {
+                record.output = Some(self.machine.output.len());
+            }
+            if record.checkpoint.is_some() {
+                record.write = self.machine.last_write();
+            }
+            history.push_back(record);
+            if history.len() > self.history_limit {
+                history.pop_front();
+            }
+        }
+        self.steps += 1;
+        if state == MachineState::Done {
+            return Ok(StopReason::Done);
+        }
+        if let Some((address, value)) = self.machine.last_write() {
+            if self.watchpoints.contains(&address) {
+                return Ok(StopReason::Watchpoint { address, value });
+            }
+        }
+        if self.breakpoints.contains(&self.machine.ip()) {
+            return Ok(StopReason::Breakpoint(self.machine.ip()));
+        }
+        Ok(StopReason::Stepped)
+    }
+
+    /// Runs until a breakpoint or watchpoint is hit, or the machine waits for input or is done. A breakpoint
+    /// on the current instruction stops the machine before executing it, unless the debugger already stopped
+    /// there, so that you can continue from it.
+    pub fn run(&mut self) -> Result<StopReason, IntcodeError> {
+        let ip = self.machine.ip();
+        if self.breakpoints.contains(&ip) && self.stopped_at != Some(ip) {
+            self.stopped_at = Some(ip);
+            return Ok(StopReason::Breakpoint(ip));
+        }
+        loop {
+            match self.step()? {
+                StopReason::Stepped => continue,
+                reason => return Ok(reason),
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+fn debugger_for(program: &str) -> Debugger {
+    Debugger::new(IntcodeMachine::from_string(program).unwrap())
+}
+
+#[test]
+fn test_single_step() {
+    let mut debugger = debugger_for("1,9,10,3,2,3,11,0,99,30,40,50");
+    assert_eq!(debugger.step(), Ok(StopReason::Stepped));
+    assert_eq!(debugger.machine().ip(), 4);
+    assert_eq!(debugger.machine().get_memory(3), 70);
+    assert_eq!(debugger.step(), Ok(StopReason::Stepped));
+    assert_eq!(debugger.step(), Ok(StopReason::Done));
+    assert_eq!(debugger.steps(), 3);
+}
+
+#[test]
+fn test_breakpoint() {
+    let mut debugger = debugger_for("1,9,10,3,2,3,11,0,99,30,40,50");
+    debugger.add_breakpoint(4);
+    assert_eq!(debugger.run(), Ok(StopReason::Breakpoint(4)));
+    assert_eq!(debugger.machine().get_memory(0), 1);
+    assert_eq!(debugger.run(), Ok(StopReason::Done));
+    assert_eq!(debugger.machine().get_memory(0), 3500);
+}
+
+#[test]
+fn test_breakpoint_on_entry() {
+    let mut debugger = debugger_for("1,9,10,3,2,3,11,0,99,30,40,50");
+    debugger.add_breakpoint(0);
+    assert_eq!(debugger.run(), Ok(StopReason::Breakpoint(0)));
+    assert_eq!(debugger.steps(), 0);
+    assert_eq!(debugger.run(), Ok(StopReason::Done));
+}
+
+#[test]
+fn test_breakpoint_in_loop() {
+    // Counts down from 3, outputting every value.
+    let mut debugger = debugger_for("1101,3,0,14,4,14,1001,14,-1,14,1005,14,4,99,0");
+    debugger.add_breakpoint(4);
+    for _ in 0..3 {
+        assert_eq!(debugger.run(), Ok(StopReason::Breakpoint(4)));
+    }
+    assert_eq!(debugger.machine().get_outputs(), vec![3,2]);
+    assert_eq!(debugger.run(), Ok(StopReason::Done));
+}
+
+#[test]
+fn test_watchpoint() {
+    let mut debugger = debugger_for("1101,3,0,14,4,14,1001,14,-1,14,1005,14,4,99,0");
+    debugger.add_watchpoint(14);
+    assert_eq!(debugger.run(), Ok(StopReason::Watchpoint { address: 14, value: 3 }));
+    assert_eq!(debugger.machine().ip(), 4);
+    assert_eq!(debugger.run(), Ok(StopReason::Watchpoint { address: 14, value: 2 }));
+    assert!(debugger.remove_watchpoint(14));
+    assert_eq!(debugger.run(), Ok(StopReason::Done));
+}
+
+#[test]
+fn test_waiting_for_input() {
+    let mut debugger = debugger_for("3,5,4,5,99,0");
+    assert_eq!(debugger.run(), Ok(StopReason::Waiting));
+    assert_eq!(debugger.steps(), 0);
+    debugger.machine_mut().push_input(42);
+    assert_eq!(debugger.machine().pending_input().len(), 1);
+    assert_eq!(debugger.run(), Ok(StopReason::Done));
+    assert_eq!(debugger.machine().get_outputs(), vec![42]);
+}
+
+#[test]
+fn test_step_back() {
+    // in [13]; add [13], #1 -> [13]; out [13]; rb += [13]; halt
+    let mut debugger = debugger_for("3,13,1001,13,1,13,4,13,9,13,99,0,0,0");
+    debugger.set_recording(true);
+    debugger.machine_mut().push_input(5);
+    assert_eq!(debugger.run(), Ok(StopReason::Done));
+    assert_eq!(debugger.machine().get_outputs(), vec![6]);
+    assert_eq!(debugger.machine().relative_base(), 6);
+    assert_eq!(debugger.steps(), 5);
+
+    assert!(debugger.step_back());
+    assert_eq!(debugger.machine().state(), MachineState::Running);
+    assert!(debugger.step_back());
+    assert_eq!(debugger.machine().relative_base(), 0);
+    assert!(debugger.step_back());
+    assert!(debugger.machine().get_outputs().is_empty());
+    assert!(debugger.step_back());
+    assert_eq!(debugger.machine().get_memory(13), 5);
+    assert!(debugger.step_back());
+    assert_eq!(debugger.machine().get_memory(13), 0);
+    assert_eq!(*debugger.machine().pending_input(), [5]);
+    assert_eq!(debugger.machine().ip(), 0);
+    assert!(!debugger.step_back());
+    assert_eq!(debugger.steps(), 0);
+
+    // Replaying gives the same result.
+    assert_eq!(debugger.run(), Ok(StopReason::Done));
+    assert_eq!(debugger.machine().get_outputs(), vec![6]);
+}
+
+#[test]
+fn test_rewind() {
+    // Counts down from 3, outputting every value.
+    let mut debugger = debugger_for("1101,3,0,14,4,14,1001,14,-1,14,1005,14,4,99,0");
+    assert_eq!(debugger.step(), Ok(StopReason::Stepped));
+    debugger.set_recording(true);
+    assert_eq!(debugger.run(), Ok(StopReason::Done));
+    assert_eq!(debugger.steps(), 11);
+    assert_eq!(debugger.earliest_step(), 1);
+    assert!(!debugger.rewind_to(0));
+    assert!(!debugger.rewind_to(12));
+    assert_eq!(debugger.steps(), 11);
+
+    assert!(debugger.rewind_to(4));
+    assert_eq!(debugger.machine().ip(), 4);
+    assert_eq!(debugger.machine().get_memory(14), 2);
+    assert_eq!(debugger.machine().get_outputs(), vec![3]);
+
+    // Every decrement of [14] is found, from the last one backwards.
+    assert_eq!(debugger.run(), Ok(StopReason::Done));
+    assert_eq!(debugger.rewind_to_last_write(14), Some(8));
+    assert_eq!(debugger.machine().ip(), 6);
+    assert_eq!(debugger.machine().get_memory(14), 1);
+    assert_eq!(debugger.rewind_to_last_write(14), Some(5));
+    assert_eq!(debugger.machine().get_memory(14), 2);
+    assert_eq!(debugger.rewind_to_last_write(14), Some(2));
+    assert_eq!(debugger.machine().get_memory(14), 3);
+    // The first write happened before the recording started.
+    assert_eq!(debugger.rewind_to_last_write(14), None);
+    assert_eq!(debugger.steps(), 2);
+
+    debugger.set_recording(false);
+    assert!(!debugger.step_back());
+}
+
+#[test]
+fn test_step_back_over_extension() {
+    // Opcode 20 doubles the value at its parameter in place.
+    let machine = crate::extension::MachineBuilder::new(&"20,3,99,21".parse().unwrap())
+        .opcode(20, crate::extension::OpcodeSpec::new(1).writes(0), |call| {
+            let value = call.read(0)?;
+            call.write(0, value * 2)
+        })
+        .build();
+    let mut debugger = Debugger::new(machine);
+    debugger.set_recording(true);
+    assert_eq!(debugger.run(), Ok(StopReason::Done));
+    assert_eq!(debugger.machine().get_memory(3), 42);
+    assert_eq!(debugger.rewind_to_last_write(3), Some(0));
+    assert_eq!(debugger.machine().get_memory(3), 21);
+}
+
+#[test]
+fn test_history_limit() {
+    // Counts down from 3, outputting every value.
+    let mut debugger = debugger_for("1101,3,0,14,4,14,1001,14,-1,14,1005,14,4,99,0");
+    debugger.set_recording(true);
+    debugger.set_history_limit(5);
+    assert_eq!(debugger.run(), Ok(StopReason::Done));
+    assert_eq!(debugger.steps(), 11);
+    assert_eq!(debugger.earliest_step(), 6);
+    assert!(!debugger.rewind_to(5));
+    assert!(debugger.rewind_to(6));
+    debugger.set_history_limit(0);
+    assert_eq!(debugger.earliest_step(), 6);
+    assert!(!debugger.step_back());
+}
+
+#[test]
+fn test_step_back_with_strict_reads() {
+    // Writes [100] twice, then outputs it.
+    let mut debugger = debugger_for("1101,1,2,100,1101,3,4,100,4,100,99");
+    debugger.machine_mut().set_memory_policy(crate::MemoryPolicy { strict_reads: true, ..crate::MemoryPolicy::default() });
+    debugger.set_recording(true);
+    let initialized = |debugger: &Debugger| debugger.machine().snapshot().memory_policy.unwrap().initialized;
+    assert_eq!(debugger.step(), Ok(StopReason::Stepped));
+    assert_eq!(debugger.step(), Ok(StopReason::Stepped));
+    assert!(debugger.step_back());
+    assert_eq!(initialized(&debugger), vec![100]);
+    assert!(debugger.step_back());
+    assert!(initialized(&debugger).is_empty());
+    assert_eq!(debugger.run(), Ok(StopReason::Done));
+    assert_eq!(debugger.machine().get_outputs(), vec![7]);
+}
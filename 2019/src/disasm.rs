@@ -0,0 +1,205 @@
+use std::collections::BTreeMap;
+use std::fmt;
+use itertools::Itertools;
+use crate::{Address, OpValue, Opcode, ParamMode, Value};
+
+const DATA_PER_LINE: usize = 8;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Operand {
+    pub mode: ParamMode,
+    pub value: Value,
+}
+
+impl fmt::Display for Operand {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self.mode {
+            ParamMode::Position => write!(f, "[{}]", self.value),
+            ParamMode::Immediate => write!(f, "#{}", self.value),
+            ParamMode::Relative if self.value < 0 => write!(f, "rb{}", self.value),
+            ParamMode::Relative => write!(f, "rb+{}", self.value),
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Instruction {
+    pub opcode: Opcode,
+    pub operands: Vec<Operand>,
+}
+
+impl Instruction {
+    /// Decodes the instruction at `address`. Returns `None` for words the machine could not execute, and for
+    /// instructions whose mode digits would get lost when assembling them again.
+    pub fn decode(program: &[Value], address: usize) -> Option<Self> {
+        let opvalue = OpValue::new(*program.get(address)?).ok()?;
+        let opcode = Opcode::from_code(opvalue.opcode())?;
+        if opvalue.has_modes_beyond(opcode.arity()) {
+            return None;
+        }
+        let operands = program.get((address + 1)..(address + 1 + opcode.arity()))?.iter().enumerate()
+            .map(|(pos, value)| Operand { mode: opvalue.param_mode(pos), value: *value })
+            .collect::<Vec<Operand>>();
+        if let Some(pos) = opcode.write_param() {
+            if operands[pos].mode == ParamMode::Immediate {
+                return None;
+            }
+        }
+        Some(Instruction { opcode, operands })
+    }
+
+    /// The number of memory cells this instruction occupies.
+    pub fn size(&self) -> usize {
+        1 + self.operands.len()
+    }
+
+    /// Addresses execution can continue at after this instruction, as far as they can be known statically.
+    pub fn successors(&self, address: usize) -> Vec<usize> {
+        let next = address + self.size();
+        match self.opcode {
+            Opcode::Halt => vec![],
+            Opcode::JumpIfTrue | Opcode::JumpIfFalse => {
+                let condition = self.operands[0];
+                let target = self.operands[1];
+                let mut result = vec![];
+                let (may_jump, may_continue) = match condition.mode {
+                    ParamMode::Immediate => {
+                        let jumps = (condition.value != 0) == (self.opcode == Opcode::JumpIfTrue);
+                        (jumps, !jumps)
+                    },
+                    _ => (true, true),
+                };
+                if may_continue {
+                    result.push(next);
+                }
+                if may_jump && target.mode == ParamMode::Immediate && target.value >= 0 {
+                    result.push(target.value as usize);
+                }
+                result
+            },
+            _ => vec![next],
+        }
+    }
+}
+
+impl fmt::Display for Instruction {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.opcode.mnemonic())?;
+        if !self.operands.is_empty() {
+            write!(f, " {}", self.operands.iter().join(", "))?;
+        }
+        Ok(())
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum Line {
+    Code { address: Address, instruction: Instruction },
+    Data { address: Address, values: Vec<Value> },
+}
+
+impl fmt::Display for Line {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Line::Code { address, instruction } => write!(f, "{:5}: {}", address, instruction),
+            Line::Data { address, values } => write!(f, "{:5}: data {}", address, values.iter().join(", ")),
+        }
+    }
+}
+
+/// Finds the instructions reachable from address 0 and decodes them. Everything else is treated as data.
+pub fn decode_reachable(program: &[Value]) -> BTreeMap<usize, Instruction> {
+    let mut claimed = vec![false; program.len()];
+    let mut decoded = BTreeMap::new();
+    let mut pending = vec![0];
+    while let Some(address) = pending.pop() {
+        if address >= program.len() || claimed[address] {
+            continue;
+        }
+        let instruction = match Instruction::decode(program, address) {
+            Some(instruction) => instruction,
+            None => continue,
+        };
+        // Don't decode instructions that overlap with ones we already know.
+        if claimed[address..(address + instruction.size())].iter().any(|&taken| taken) {
+            continue;
+        }
+        for word in claimed[address..(address + instruction.size())].iter_mut() {
+            *word = true;
+        }
+        pending.extend(instruction.successors(address));
+        decoded.insert(address, instruction);
+    }
+    decoded
+}
+
+pub fn disassemble(program: &[Value]) -> Vec<Line> {
+    let mut decoded = decode_reachable(program);
+    let mut lines = vec![];
+    let mut address = 0;
+    while address < program.len() {
+        if let Some(instruction) = decoded.remove(&address) {
+            let size = instruction.size();
+            lines.push(Line::Code { address: address as Address, instruction });
+            address += size;
+            continue;
+        }
+        let mut values = vec![];
+        while address < program.len() && !decoded.contains_key(&address) && values.len() < DATA_PER_LINE {
+            values.push(program[address]);
+            address += 1;
+        }
+        lines.push(Line::Data { address: (address - values.len()) as Address, values });
+    }
+    lines
+}
+
+pub fn listing(program: &[Value]) -> String {
+    disassemble(program).iter().map(|line| format!("{}\n", line)).collect()
+}
+
+#[test]
+fn test_day2_listing() {
+    assert_eq!(listing(&[1,9,10,3,2,3,11,0,99,30,40,50]), "    0: add [9], [10], [3]
+    4: mul [3], [11], [0]
+    8: hlt
+    9: data 30, 40, 50
+");
+}
+
+#[test]
+fn test_operand_modes() {
+    assert_eq!(listing(&[109,19,21101,5,-23,-2,204,-2019,99]), "    0: arb #19
+    2: add #5, #-23, rb-2
+    6: out rb-2019
+    8: hlt
+");
+}
+
+#[test]
+fn test_unreachable_code_is_data() {
+    // The jump is always taken, so the output instruction at 3 is never executed.
+    assert_eq!(listing(&[1105,1,5,4,0,99]), "    0: jt #1, #5
+    3: data 4, 0
+    5: hlt
+");
+}
+
+#[test]
+fn test_undecodable_words_are_data() {
+    assert_eq!(listing(&[1,0,0,0,42,11101,1,1,0]), "    0: add [0], [0], [0]
+    4: data 42, 11101, 1, 1, 0
+");
+}
+
+#[test]
+fn test_conditional_jump_follows_both_paths() {
+    assert_eq!(listing(&[3,11,1005,11,8,104,0,99,104,1,99,0]), "    0: in [11]
+    2: jt [11], #8
+    5: out #0
+    7: hlt
+    8: out #1
+   10: hlt
+   11: data 0
+");
+}
@@ -0,0 +1,56 @@
+use std::error::Error;
+use std::fmt;
+use crate::{Address, Value};
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum IntcodeError<V = Value> {
+    UnknownOpcode { opcode: Value, ip: Address },
+    InvalidParamMode { instruction: V, digit: char },
+    ImmediateWrite { ip: Address },
+    NegativeAddress { address: V },
+    /// Only possible with values larger than `i64`.
+    AddressTooLarge { address: V },
+    RelativeBaseUnderflow { relative_base: Address, offset: V },
+    /// The result of the instruction doesn't fit into the machine's values.
+    Overflow { ip: Address },
+    OutputCount { count: usize },
+    OutputClosed,
+    MemoryLimit { address: Address, limit: Address },
+    /// Reading scratch memory that was never written to, see `MemoryPolicy::strict_reads`.
+    UninitializedRead { address: Address, ip: Address },
+    /// Writing into the program image, see `MemoryPolicy::read_only_image`.
+    ReadOnlyWrite { address: Address, ip: Address },
+}
+
+impl<V: fmt::Display> fmt::Display for IntcodeError<V> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Self::UnknownOpcode { opcode, ip } =>
+                write!(f, "unknown opcode {} at position {}", opcode, ip),
+            Self::InvalidParamMode { instruction, digit } =>
+                write!(f, "invalid parameter mode {:?} in instruction {}", digit, instruction),
+            Self::ImmediateWrite { ip } =>
+                write!(f, "instruction at position {} tries to write in immediate mode", ip),
+            Self::NegativeAddress { address } =>
+                write!(f, "cannot access negative address {}", address),
+            Self::AddressTooLarge { address } =>
+                write!(f, "cannot access address {}, it is too large", address),
+            Self::RelativeBaseUnderflow { relative_base, offset } =>
+                write!(f, "adjusting relative base {} by {} would make it negative", relative_base, offset),
+            Self::Overflow { ip } =>
+                write!(f, "instruction at position {} overflows", ip),
+            Self::OutputCount { count } =>
+                write!(f, "expected exactly one output, but there were {}", count),
+            Self::OutputClosed =>
+                write!(f, "output could not be written because the receiving end is closed"),
+            Self::MemoryLimit { address, limit } =>
+                write!(f, "cannot write to address {}, memory is limited to {} cells", address, limit),
+            Self::UninitializedRead { address, ip } =>
+                write!(f, "instruction at position {} reads address {}, which was never written to", ip, address),
+            Self::ReadOnlyWrite { address, ip } =>
+                write!(f, "instruction at position {} writes to address {} in the read-only program image", ip, address),
+        }
+    }
+}
+
+impl<V: fmt::Debug + fmt::Display> Error for IntcodeError<V> {}
@@ -0,0 +1,275 @@
+use std::collections::HashMap;
+use std::sync::Arc;
+use crate::{Address, InputSource, IntcodeError, IntcodeMachine, MachineState, Memory, OpValue, Opcode, OutputSink, Program, VecMemory};
+
+pub type Handler<M> = Arc<dyn Fn(&mut Call<M>) -> Result<(), IntcodeError<<M as Memory>::Value>> + Send + Sync>;
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct OpcodeSpec {
+    pub arity: usize,
+    /// Positions of the parameters the instruction writes to.
+    pub write_params: Vec<usize>,
+}
+
+impl OpcodeSpec {
+    pub fn new(arity: usize) -> Self {
+        Self { arity, write_params: vec![] }
+    }
+
+    pub fn writes(mut self, pos: usize) -> Self {
+        assert!(pos < self.arity, "write parameter {} is out of range for arity {}", pos, self.arity);
+        self.write_params.push(pos);
+        self
+    }
+}
+
+pub(crate) struct Extensions<M: Memory> {
+    custom: HashMap<u8, (OpcodeSpec, Handler<M>)>,
+    fallback: Option<Handler<M>>,
+}
+
+impl<M: Memory> Extensions<M> {
+    pub(crate) fn execute(&self, machine: &mut IntcodeMachine<M>, opvalue: &OpValue, input: &mut dyn InputSource<M::Value>, output: &mut dyn OutputSink<M::Value>) -> Result<(), IntcodeError<M::Value>> {
+        let (spec, handler) = match self.custom.get(&opvalue.opcode) {
+            Some((spec, handler)) => (Some(spec), handler),
+            None => match &self.fallback {
+                Some(fallback) => (None, fallback),
+                None => return Err(IntcodeError::UnknownOpcode { opcode: opvalue.opcode.into(), ip: machine.ip }),
+            },
+        };
+        let arity = spec.map_or(0, |spec| spec.arity);
+        let mut call = Call { machine, opvalue, spec, input, output, next_ip: None, stop: None };
+        handler(&mut call)?;
+        let (next_ip, stop) = (call.next_ip, call.stop);
+        match stop {
+            Some(state) => machine.state = state,
+            None => machine.ip = match next_ip {
+                Some(address) => address,
+                None => machine.after_ip(1 + arity as Address)?,
+            },
+        }
+        Ok(())
+    }
+}
+
+/// A custom instruction being executed. Unless the handler jumps or halts, execution continues after the
+/// instruction's parameters.
+pub struct Call<'a, M: Memory> {
+    machine: &'a mut IntcodeMachine<M>,
+    opvalue: &'a OpValue,
+    /// `None` for the fallback handler.
+    spec: Option<&'a OpcodeSpec>,
+    input: &'a mut dyn InputSource<M::Value>,
+    output: &'a mut dyn OutputSink<M::Value>,
+    next_ip: Option<Address>,
+    /// Set when the instruction halts the machine or has to wait for input.
+    stop: Option<MachineState>,
+}
+
+impl<M: Memory> Call<'_, M> {
+    pub fn opcode(&self) -> u8 {
+        self.opvalue.opcode
+    }
+
+    pub fn ip(&self) -> Address {
+        self.machine.ip
+    }
+
+    pub fn machine(&self) -> &IntcodeMachine<M> {
+        self.machine
+    }
+
+    /// The value of a parameter, according to its mode.
+    pub fn read(&mut self, pos: usize) -> Result<M::Value, IntcodeError<M::Value>> {
+        self.machine.load_param(self.opvalue, pos)
+    }
+
+    /// Writes to the address a parameter refers to. Only parameters declared in the `OpcodeSpec` can be written.
+    pub fn write(&mut self, pos: usize, value: M::Value) -> Result<(), IntcodeError<M::Value>> {
+        if let Some(spec) = self.spec {
+            assert!(spec.write_params.contains(&pos), "parameter {} of opcode {} is not a write parameter", pos, self.opvalue.opcode);
+        }
+        self.machine.write_param(self.opvalue, pos, value)
+    }
+
+    /// Reads from the machine's input. If there is none, the handler should call `wait` and return.
+    pub fn input(&mut self) -> Option<M::Value> {
+        self.input.read_input()
+    }
+
+    pub fn output(&mut self, value: M::Value) -> Result<(), IntcodeError<M::Value>> {
+        self.output.write_output(value)
+    }
+
+    pub fn jump(&mut self, address: Address) {
+        self.next_ip = Some(address);
+    }
+
+    pub fn halt(&mut self) {
+        self.stop = Some(MachineState::Done);
+    }
+
+    /// Stops the machine without finishing the instruction, so that it is executed again once there is input.
+    pub fn wait(&mut self) {
+        self.stop = Some(MachineState::Waiting);
+    }
+
+    /// The error the machine would report without a handler for this opcode.
+    pub fn unknown_opcode(&self) -> IntcodeError<M::Value> {
+        IntcodeError::UnknownOpcode { opcode: self.opvalue.opcode.into(), ip: self.machine.ip }
+    }
+}
+
+/// Builds an `IntcodeMachine` with custom or disabled opcodes.
+pub struct MachineBuilder<M: Memory = VecMemory> {
+    memory: M,
+    disabled: u128,
+    custom: HashMap<u8, (OpcodeSpec, Handler<M>)>,
+    fallback: Option<Handler<M>>,
+}
+
+impl MachineBuilder {
+    pub fn new(program: &Program) -> Self {
+        Self::with_memory(VecMemory::from_values(program))
+    }
+}
+
+impl<M: Memory> MachineBuilder<M> {
+    pub fn with_memory(memory: M) -> Self {
+        Self { memory, disabled: 0, custom: HashMap::new(), fallback: None }
+    }
+
+    /// Adds an instruction. To replace a built-in instruction, disable it first.
+    pub fn opcode(mut self, code: u8, spec: OpcodeSpec, handler: impl Fn(&mut Call<M>) -> Result<(), IntcodeError<M::Value>> + Send + Sync + 'static) -> Self {
+        assert!(code < 100, "opcodes have at most two digits");
+        self.custom.insert(code, (spec, Arc::new(handler)));
+        self
+    }
+
+    pub fn disable(mut self, opcode: Opcode) -> Self {
+        self.disabled |= 1 << opcode.code();
+        self
+    }
+
+    /// Disables all built-in instructions except the given ones, e.g. to run a program the way an earlier puzzle
+    /// day would have.
+    pub fn only(mut self, opcodes: &[Opcode]) -> Self {
+        for opcode in Opcode::ALL.iter() {
+            if !opcodes.contains(opcode) {
+                self = self.disable(*opcode);
+            }
+        }
+        self
+    }
+
+    /// Handles opcodes that are neither built in nor registered. The handler doesn't know the arity of the
+    /// instruction, so unless it jumps, execution continues at the next word.
+    pub fn fallback(mut self, handler: impl Fn(&mut Call<M>) -> Result<(), IntcodeError<M::Value>> + Send + Sync + 'static) -> Self {
+        self.fallback = Some(Arc::new(handler));
+        self
+    }
+
+    pub fn build(self) -> IntcodeMachine<M> {
+        let mut machine = IntcodeMachine::with_memory(self.memory);
+        machine.disabled_opcodes = self.disabled;
+        if !self.custom.is_empty() || self.fallback.is_some() {
+            machine.extensions = Some(Arc::new(Extensions { custom: self.custom, fallback: self.fallback }));
+        }
+        machine
+    }
+}
+
+#[cfg(test)]
+fn program(source: &str) -> Program {
+    source.parse().unwrap()
+}
+
+#[test]
+fn test_custom_opcode() {
+    // 10: writes the maximum of two values.
+    let mut machine = MachineBuilder::new(&program("10,7,8,9,4,9,99,3,5,0"))
+        .opcode(10, OpcodeSpec::new(3).writes(2), |call| {
+            let max = call.read(0)?.max(call.read(1)?);
+            call.write(2, max)
+        })
+        .build();
+    assert_eq!(machine.compute(), Ok(MachineState::Done));
+    assert_eq!(machine.get_outputs(), vec![5]);
+}
+
+#[test]
+fn test_custom_jump_and_halt() {
+    // 20: jumps to its parameter; 21: halts if its parameter is zero.
+    let mut machine = MachineBuilder::new(&program("120,4,104,1,104,2,121,0,104,3"))
+        .opcode(20, OpcodeSpec::new(1), |call| {
+            let target = call.read(0)?;
+            call.jump(target as Address);
+            Ok(())
+        })
+        .opcode(21, OpcodeSpec::new(1), |call| {
+            if call.read(0)? == 0 {
+                call.halt();
+            }
+            Ok(())
+        })
+        .build();
+    assert_eq!(machine.compute(), Ok(MachineState::Done));
+    assert_eq!(machine.get_outputs(), vec![2]);
+    assert_eq!(machine.ip(), 6);
+}
+
+#[test]
+fn test_day2_opcodes_only() {
+    let day9 = program("109,1,204,-1,99");
+    let mut machine = MachineBuilder::new(&day9).only(&[Opcode::Add, Opcode::Mul, Opcode::Halt]).build();
+    assert_eq!(machine.compute(), Err(IntcodeError::UnknownOpcode { opcode: 9, ip: 0 }));
+    let mut machine = MachineBuilder::new(&program("1,9,10,3,2,3,11,0,99,30,40,50"))
+        .only(&[Opcode::Add, Opcode::Mul, Opcode::Halt])
+        .build();
+    assert_eq!(machine.compute(), Ok(MachineState::Done));
+    assert_eq!(machine.get_memory(0), 3500);
+}
+
+#[test]
+fn test_replace_builtins() {
+    // Input negates the value it reads, output doubles its value.
+    let mut machine = MachineBuilder::new(&program("3,5,4,5,99,0"))
+        .disable(Opcode::Input)
+        .disable(Opcode::Output)
+        .opcode(3, OpcodeSpec::new(1).writes(0), |call| {
+            match call.input() {
+                Some(value) => call.write(0, -value)?,
+                None => call.wait(),
+            }
+            Ok(())
+        })
+        .opcode(4, OpcodeSpec::new(1), |call| {
+            let value = call.read(0)?;
+            call.output(value * 2)
+        })
+        .build();
+    assert_eq!(machine.compute(), Ok(MachineState::Waiting));
+    assert_eq!(machine.ip(), 0);
+    machine.push_input(21);
+    assert_eq!(machine.compute(), Ok(MachineState::Done));
+    assert_eq!(machine.get_outputs(), vec![-42]);
+}
+
+#[test]
+fn test_fallback() {
+    // Treats unknown opcodes as no-ops, but still rejects 42.
+    let mut machine = MachineBuilder::new(&program("50,51,104,7,42"))
+        .fallback(|call| if call.opcode() == 42 { Err(call.unknown_opcode()) } else { Ok(()) })
+        .build();
+    assert_eq!(machine.compute(), Err(IntcodeError::UnknownOpcode { opcode: 42, ip: 4 }));
+    assert_eq!(machine.get_outputs(), vec![7]);
+}
+
+#[test]
+#[should_panic(expected = "not a write parameter")]
+fn test_undeclared_write() {
+    let mut machine = MachineBuilder::new(&program("10,0,99"))
+        .opcode(10, OpcodeSpec::new(1), |call| call.write(0, 1))
+        .build();
+    let _ = machine.compute();
+}
@@ -0,0 +1,410 @@
+use std::collections::VecDeque;
+use std::fmt;
+use itertools::Itertools;
+use crate::{Address, IntcodeError, IntcodeMachine, MachineState, Memory, Program, SparseMemory, Value, VecMemory};
+
+/// Generated programs are stopped after this many instructions.
+const MAX_STEPS: u64 = 500;
+/// Generated programs can't write to addresses beyond this.
+const MEMORY_LIMIT: Address = 1 << 16;
+/// How many shrinking attempts to make at most.
+const MAX_SHRINK_ATTEMPTS: usize = 10_000;
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Case {
+    pub program: Program,
+    pub inputs: Vec<Value>,
+}
+
+impl fmt::Display for Case {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "program {} with inputs [{}]", self.program, self.inputs.iter().join(","))
+    }
+}
+
+/// What running a case did.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Outcome {
+    pub result: Result<MachineState, IntcodeError>,
+    pub ip: Address,
+    pub relative_base: Address,
+    pub memory: Vec<Value>,
+    pub outputs: Vec<Value>,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Failure {
+    pub case: Case,
+    pub expected: Outcome,
+    pub actual: Outcome,
+}
+
+impl fmt::Display for Failure {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}: expected {:?}, but got {:?}", self.case, self.expected, self.actual)
+    }
+}
+
+/// Runs the case on an `IntcodeMachine` with the given memory.
+pub fn run_machine<M: Memory<Value = Value>>(case: &Case) -> Outcome {
+    let mut machine = IntcodeMachine::with_memory(M::from_values(&case.program));
+    machine.set_memory_limit(Some(MEMORY_LIMIT));
+    machine.set_input(case.inputs.clone());
+    let result = machine.compute_with_budget(MAX_STEPS);
+    let mut memory = vec![];
+    for (start, values) in machine.memory.segments() {
+        let start = start as usize;
+        if memory.len() < start + values.len() {
+            memory.resize(start + values.len(), 0);
+        }
+        memory[start..(start + values.len())].copy_from_slice(&values);
+    }
+    Outcome { result, ip: machine.ip, relative_base: machine.relative_base, memory, outputs: machine.get_outputs() }
+}
+
+/// A deliberately simple interpreter to compare `IntcodeMachine` with.
+pub fn run_reference(case: &Case) -> Outcome {
+    let mut reference = Reference {
+        memory: case.program.to_vec(), ip: 0, relative_base: 0, inputs: case.inputs.iter().copied().collect(), outputs: vec![],
+    };
+    let mut steps = 0;
+    let result = loop {
+        if steps == MAX_STEPS {
+            break Ok(MachineState::BudgetExhausted);
+        }
+        match reference.step() {
+            Ok(true) => steps += 1,
+            Ok(false) => break Ok(MachineState::Done),
+            Err(Stop::Waiting) => break Ok(MachineState::Waiting),
+            Err(Stop::Error(error)) => break Err(error),
+        }
+    };
+    Outcome {
+        result, ip: reference.ip, relative_base: reference.relative_base as Address, memory: reference.memory, outputs: reference.outputs,
+    }
+}
+
+enum Stop {
+    Waiting,
+    Error(IntcodeError),
+}
+
+impl From<IntcodeError> for Stop {
+    fn from(error: IntcodeError) -> Self {
+        Stop::Error(error)
+    }
+}
+
+struct Reference {
+    memory: Vec<Value>,
+    ip: Address,
+    relative_base: Value,
+    inputs: VecDeque<Value>,
+    outputs: Vec<Value>,
+}
+
+impl Reference {
+    fn load(&self, address: Value) -> Result<Value, Stop> {
+        if address < 0 {
+            return Err(IntcodeError::NegativeAddress { address }.into());
+        }
+        Ok(self.memory.get(address as usize).copied().unwrap_or(0))
+    }
+
+    fn word(&self, pos: Address) -> Value {
+        self.memory.get((self.ip + pos) as usize).copied().unwrap_or(0)
+    }
+
+    fn mode(&self, pos: u32) -> Value {
+        self.word(0) / 10_i64.pow(pos + 2) % 10
+    }
+
+    fn overflow(&self) -> Stop {
+        IntcodeError::Overflow { ip: self.ip }.into()
+    }
+
+    /// The address a parameter refers to.
+    fn address(&self, pos: u32) -> Result<Value, Stop> {
+        let param = self.word(pos as Address + 1);
+        match self.mode(pos) {
+            0 => Ok(param),
+            2 => param.checked_add(self.relative_base).ok_or_else(|| self.overflow()),
+            _ => Err(IntcodeError::ImmediateWrite { ip: self.ip }.into()),
+        }
+    }
+
+    fn read(&self, pos: u32) -> Result<Value, Stop> {
+        if self.mode(pos) == 1 {
+            return Ok(self.word(pos as Address + 1));
+        }
+        self.load(self.address(pos)?)
+    }
+
+    /// Checks that the address can be written to.
+    fn target(&self, address: Value) -> Result<usize, Stop> {
+        if address < 0 {
+            return Err(IntcodeError::NegativeAddress { address }.into());
+        }
+        if address as Address >= MEMORY_LIMIT {
+            return Err(IntcodeError::MemoryLimit { address: address as Address, limit: MEMORY_LIMIT }.into());
+        }
+        Ok(address as usize)
+    }
+
+    fn write(&mut self, address: Value, value: Value) -> Result<(), Stop> {
+        let address = self.target(address)?;
+        if address >= self.memory.len() {
+            self.memory.resize(address + 1, 0);
+        }
+        self.memory[address] = value;
+        Ok(())
+    }
+
+    /// Executes an instruction, returns whether the program is still running.
+    fn step(&mut self) -> Result<bool, Stop> {
+        let instruction = self.word(0);
+        if instruction < 0 {
+            return Err(IntcodeError::InvalidParamMode { instruction, digit: '-' }.into());
+        }
+        if let Some(digit) = (instruction / 100).to_string().chars().rev().find(|&digit| digit > '2') {
+            return Err(IntcodeError::InvalidParamMode { instruction, digit }.into());
+        }
+        match instruction % 100 {
+            opcode @ 1 | opcode @ 2 | opcode @ 7 | opcode @ 8 => {
+                let (a, b) = (self.read(0)?, self.read(1)?);
+                let result = match opcode {
+                    1 => a.checked_add(b).ok_or_else(|| self.overflow())?,
+                    2 => a.checked_mul(b).ok_or_else(|| self.overflow())?,
+                    7 => (a < b) as Value,
+                    _ => (a == b) as Value,
+                };
+                let address = self.address(2)?;
+                self.write(address, result)?;
+                self.ip += 4;
+            },
+            3 => {
+                let address = self.address(0)?;
+                self.target(address)?;
+                let value = self.inputs.pop_front().ok_or(Stop::Waiting)?;
+                self.write(address, value)?;
+                self.ip += 2;
+            },
+            4 => {
+                let value = self.read(0)?;
+                self.outputs.push(value);
+                self.ip += 2;
+            },
+            opcode @ 5 | opcode @ 6 => {
+                let condition = self.read(0)?;
+                if (condition != 0) == (opcode == 5) {
+                    let target = self.read(1)?;
+                    if target < 0 {
+                        return Err(IntcodeError::NegativeAddress { address: target }.into());
+                    }
+                    self.ip = target as Address;
+                } else {
+                    self.ip += 3;
+                }
+            },
+            9 => {
+                let offset = self.read(0)?;
+                let relative_base = self.relative_base.checked_add(offset).ok_or_else(|| self.overflow())?;
+                if relative_base < 0 {
+                    return Err(IntcodeError::RelativeBaseUnderflow { relative_base: self.relative_base as Address, offset }.into());
+                }
+                self.relative_base = relative_base;
+                self.ip += 2;
+            },
+            99 => return Ok(false),
+            opcode => return Err(IntcodeError::UnknownOpcode { opcode, ip: self.ip }.into()),
+        }
+        Ok(true)
+    }
+}
+
+/// A xorshift generator, so that runs can be repeated from their seed.
+pub struct Rng(u64);
+
+impl Rng {
+    pub fn new(seed: u64) -> Self {
+        Rng(seed.max(1))
+    }
+
+    pub fn next_u64(&mut self) -> u64 {
+        self.0 ^= self.0 << 13;
+        self.0 ^= self.0 >> 7;
+        self.0 ^= self.0 << 17;
+        self.0
+    }
+
+    pub fn below(&mut self, bound: u64) -> u64 {
+        self.next_u64() % bound
+    }
+
+    pub fn range(&mut self, low: Value, high: Value) -> Value {
+        low + self.below((high - low) as u64) as Value
+    }
+}
+
+/// Generates a program of valid instructions followed by some data. Operands mostly refer to addresses in or
+/// just after the program, so that instructions interact with each other.
+pub fn generate(rng: &mut Rng) -> Case {
+    const OPCODES: [(Value, usize, Option<usize>); 10] =
+        [(1, 3, Some(2)), (2, 3, Some(2)), (3, 1, Some(0)), (4, 1, None), (5, 2, None), (6, 2, None), (7, 3, Some(2)), (8, 3, Some(2)), (9, 1, None), (99, 0, None)];
+    let instructions = rng.range(1, 12);
+    let size = instructions * 4 + 8;
+    let mut program = vec![];
+    for _ in 0..instructions {
+        let (opcode, arity, write_param) = OPCODES[rng.below(OPCODES.len() as u64) as usize];
+        let mut instruction = opcode;
+        let mut params = vec![];
+        for pos in 0..arity {
+            let mode = if write_param == Some(pos) { [0, 2][rng.below(2) as usize] } else { rng.range(0, 3) };
+            instruction += mode * 10_i64.pow(pos as u32 + 2);
+            params.push(match mode {
+                0 => rng.range(0, size),
+                1 => rng.range(-10, size),
+                _ => rng.range(-5, 15),
+            });
+        }
+        program.push(instruction);
+        program.extend(params);
+    }
+    program.push(99);
+    for _ in 0..rng.below(8) {
+        program.push(rng.range(-5, 50));
+    }
+    let inputs = (0..rng.below(5)).map(|_| rng.range(-5, 50)).collect();
+    Case { program: program.into(), inputs }
+}
+
+/// Compares running the case with `run` to the reference interpreter.
+pub fn check(case: &Case, run: &dyn Fn(&Case) -> Outcome) -> Result<(), Box<Failure>> {
+    let (expected, actual) = (run_reference(case), run(case));
+    if actual == expected { Ok(()) } else { Err(Box::new(Failure { case: case.clone(), expected, actual })) }
+}
+
+/// Removes inputs and program words and simplifies values as long as the case still fails.
+pub fn shrink(mut case: Case, fails: impl Fn(&Case) -> bool) -> Case {
+    let mut attempts = 0;
+    let mut progress = true;
+    while progress && attempts < MAX_SHRINK_ATTEMPTS {
+        progress = false;
+        for candidate in simplifications(&case) {
+            attempts += 1;
+            if fails(&candidate) {
+                case = candidate;
+                progress = true;
+                break;
+            }
+            if attempts >= MAX_SHRINK_ATTEMPTS {
+                break;
+            }
+        }
+    }
+    case
+}
+
+/// Smaller variants of the case, those that remove the most first.
+fn simplifications(case: &Case) -> Vec<Case> {
+    let mut candidates = vec![];
+    let program = case.program.to_vec();
+    for index in 0..case.inputs.len() {
+        let mut inputs = case.inputs.clone();
+        inputs.remove(index);
+        candidates.push(Case { program: case.program.clone(), inputs });
+    }
+    for size in (1..=4).rev() {
+        for start in 0..program.len().saturating_sub(size - 1) {
+            let mut words = program.clone();
+            words.drain(start..(start + size));
+            candidates.push(Case { program: words.into(), inputs: case.inputs.clone() });
+        }
+    }
+    for (index, &value) in program.iter().enumerate() {
+        for simpler in [0, value / 2, value - value.signum()].iter().copied() {
+            if simpler.unsigned_abs() < value.unsigned_abs() {
+                let mut words = program.clone();
+                words[index] = simpler;
+                candidates.push(Case { program: words.into(), inputs: case.inputs.clone() });
+            }
+        }
+    }
+    candidates
+}
+
+/// Checks `cases` generated programs with `run`, and shrinks the first failure.
+pub fn fuzz_with(seed: u64, cases: usize, run: &dyn Fn(&Case) -> Outcome) -> Result<(), Box<Failure>> {
+    let mut rng = Rng::new(seed);
+    for _ in 0..cases {
+        if let Err(failure) = check(&generate(&mut rng), run) {
+            let case = shrink(failure.case, |case| check(case, run).is_err());
+            return check(&case, run);
+        }
+    }
+    Ok(())
+}
+
+/// Checks generated programs on `IntcodeMachine` with both memory backends.
+pub fn fuzz(seed: u64, cases: usize) -> Result<(), Box<Failure>> {
+    fuzz_with(seed, cases, &run_machine::<VecMemory>)?;
+    fuzz_with(seed, cases, &run_machine::<SparseMemory>)
+}
+
+#[cfg(test)]
+fn case(program: &str, inputs: &[Value]) -> Case {
+    Case { program: program.parse().unwrap(), inputs: inputs.to_vec() }
+}
+
+#[test]
+fn test_reference_examples() {
+    let quine = case("109,1,204,-1,1001,100,1,100,1008,100,16,101,1006,101,0,99", &[]);
+    assert_eq!(run_reference(&quine).outputs, quine.program.to_vec());
+    let day2 = run_reference(&case("1,9,10,3,2,3,11,0,99,30,40,50", &[]));
+    assert_eq!(day2.memory, vec![3500,9,10,70,2,3,11,0,99,30,40,50]);
+    let echo = run_reference(&case("3,0,4,0,3,0,99", &[7]));
+    assert_eq!((echo.result, echo.ip, echo.outputs), (Ok(MachineState::Waiting), 4, vec![7]));
+    assert_eq!(run_reference(&case("1101,9223372036854775807,1,0,99", &[])).result, Err(IntcodeError::Overflow { ip: 0 }));
+}
+
+#[test]
+fn test_reference_errors_match() {
+    let programs = ["42", "-1", "301", "11101,0,0,0", "1,-1,0,0", "21101,0,0,-1", "109,-1", "1105,1,-4", "3,-2",
+        "1102,9223372036854775807,2,0", "109,9223372036854775807,209,1", "1101,1,1,65536", "3,9223372036854775807"];
+    for program in programs.iter() {
+        let case = case(program, &[1]);
+        assert_eq!(run_reference(&case), run_machine::<VecMemory>(&case), "{}", program);
+    }
+}
+
+#[test]
+fn test_fuzz_machine() {
+    if let Err(failure) = fuzz(2019, 2000) {
+        panic!("{}", failure);
+    }
+}
+
+#[test]
+fn test_shrinking() {
+    // A machine that gets relative writes wrong: it adds an output whenever one happens.
+    let broken = |case: &Case| {
+        let mut outcome = run_machine::<VecMemory>(case);
+        let relative_write = case.program.iter().any(|&word| word / 10000 % 10 == 2 && [1, 2, 7, 8].contains(&(word % 100)));
+        if relative_write {
+            outcome.outputs.push(0);
+        }
+        outcome
+    };
+    let failure = fuzz_with(7, 1000, &broken).unwrap_err();
+    assert!(failure.case.inputs.is_empty());
+    assert_eq!(failure.case.program.len(), 1, "{}", failure.case);
+    assert_eq!(failure.case.program[0] / 10000 % 10, 2);
+}
+
+#[test]
+fn test_simplifying_extreme_values() {
+    let case = Case { program: vec![i64::MIN, i64::MAX].into(), inputs: vec![] };
+    let simpler: Vec<Vec<Value>> = simplifications(&case).into_iter().map(|case| case.program.to_vec()).collect();
+    assert!(simpler.contains(&vec![i64::MIN / 2, i64::MAX]));
+    assert!(simpler.contains(&vec![i64::MIN, i64::MAX - 1]));
+}
@@ -0,0 +1,110 @@
+use std::collections::VecDeque;
+use std::io::{self, BufRead, Write};
+use std::sync::mpsc::{Receiver, Sender, SyncSender};
+use crate::{IntcodeError, Value};
+use crate::ascii::{decode, encode_line};
+
+/// Where opcode 3 takes its values from. Returning `None` makes the machine wait for more input.
+pub trait InputSource<V = Value> {
+    fn read_input(&mut self) -> Option<V>;
+}
+
+/// Where opcode 4 sends its values to.
+pub trait OutputSink<V = Value> {
+    fn write_output(&mut self, value: V) -> Result<(), IntcodeError<V>>;
+}
+
+impl<V> InputSource<V> for VecDeque<V> {
+    fn read_input(&mut self) -> Option<V> {
+        self.pop_front()
+    }
+}
+
+impl<V> OutputSink<V> for VecDeque<V> {
+    fn write_output(&mut self, value: V) -> Result<(), IntcodeError<V>> {
+        self.push_back(value);
+        Ok(())
+    }
+}
+
+impl<V> OutputSink<V> for Vec<V> {
+    fn write_output(&mut self, value: V) -> Result<(), IntcodeError<V>> {
+        self.push(value);
+        Ok(())
+    }
+}
+
+impl<V, F: FnMut() -> Option<V>> InputSource<V> for F {
+    fn read_input(&mut self) -> Option<V> {
+        self()
+    }
+}
+
+impl<V, F: FnMut(V)> OutputSink<V> for F {
+    fn write_output(&mut self, value: V) -> Result<(), IntcodeError<V>> {
+        self(value);
+        Ok(())
+    }
+}
+
+/// Blocks until a value arrives. If all senders are gone, the machine waits.
+impl<V> InputSource<V> for Receiver<V> {
+    fn read_input(&mut self) -> Option<V> {
+        self.recv().ok()
+    }
+}
+
+impl<V> OutputSink<V> for Sender<V> {
+    fn write_output(&mut self, value: V) -> Result<(), IntcodeError<V>> {
+        self.send(value).map_err(|_| IntcodeError::OutputClosed)
+    }
+}
+
+impl<V> OutputSink<V> for SyncSender<V> {
+    fn write_output(&mut self, value: V) -> Result<(), IntcodeError<V>> {
+        self.send(value).map_err(|_| IntcodeError::OutputClosed)
+    }
+}
+
+/// Reads stdin line by line and feeds it to the machine one character code at a time, see `encode_line`.
+#[derive(Default)]
+pub struct AsciiStdin {
+    buffer: VecDeque<Value>,
+}
+
+impl AsciiStdin {
+    pub fn new() -> Self {
+        Self::default()
+    }
+}
+
+impl InputSource for AsciiStdin {
+    fn read_input(&mut self) -> Option<Value> {
+        if self.buffer.is_empty() {
+            let mut line = String::new();
+            if io::stdin().lock().read_line(&mut line).ok()? == 0 {
+                return None;
+            }
+            self.buffer.extend(encode_line(&line));
+        }
+        self.buffer.pop_front()
+    }
+}
+
+/// Prints ASCII output as text. Values outside of the ASCII range are printed as numbers on their own line.
+#[derive(Default)]
+pub struct AsciiStdout;
+
+impl AsciiStdout {
+    pub fn new() -> Self {
+        Self
+    }
+}
+
+impl OutputSink for AsciiStdout {
+    fn write_output(&mut self, value: Value) -> Result<(), IntcodeError> {
+        let mut stdout = io::stdout();
+        decode(&[value]).iter().try_for_each(|output| write!(stdout, "{}", output))
+            .and_then(|_| stdout.flush()).map_err(|_| IntcodeError::OutputClosed)
+    }
+}
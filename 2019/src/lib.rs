@@ -1,206 +1,497 @@
-use std::collections::HashMap;
-use std::convert::TryInto;
-use std::io::{self, BufRead, BufReader};
+use std::collections::VecDeque;
+use std::error::Error;
+use std::io::Read;
+use std::mem;
 use std::ops::Range;
-use itertools::Itertools;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use serde::{Deserialize, Serialize};
+
+// Lets the code generated by `compile::translate` refer to this crate by name, also from inside of it.
+extern crate self as aoc2019;
+
+pub mod amplifier;
+pub mod ascii;
+pub mod asm;
+pub mod asynchronous;
+pub mod cfg;
+pub mod compile;
+pub mod debugger;
+pub mod disasm;
+mod error;
+pub mod extension;
+pub mod fuzz;
+mod io;
+mod memory;
+pub mod network;
+mod number;
+mod opcode;
+mod policy;
+mod program;
+pub mod runtime;
+pub mod search;
+mod snapshot;
+pub mod symbolic;
+pub mod trace;
+
+pub use error::IntcodeError;
+pub use io::{AsciiStdin, AsciiStdout, InputSource, OutputSink};
+pub use memory::{Memory, SparseMemory, VecMemory};
+pub use number::Number;
+pub use opcode::Opcode;
+pub use policy::{AccessKind, MemoryAccess, MemoryPolicy, PolicyState};
+pub use program::{ParseError, Program};
+pub use snapshot::{Snapshot, SnapshotError};
+
+use extension::Extensions;
+use policy::AccessControl;
+use trace::{NoTracer, TraceEvent, Tracer};
 
 pub type Address = u64;
 pub type Value = i64;
 
-#[derive(Clone,Copy,PartialEq)]
+#[derive(Clone,Copy,Debug,PartialEq,Serialize,Deserialize)]
 pub enum MachineState {
     Ready,
     Running,
     Done,
     Waiting,
+    /// The step or time budget ran out before the machine halted or waited. Computing again resumes it.
+    BudgetExhausted,
 }
 
+/// The memory limit machines start with, see `IntcodeMachine::set_memory_limit`.
+pub const DEFAULT_MEMORY_LIMIT: Address = 1 << 20;
+
+/// A step budget that puzzle programs stay well within, for searches that assume longer runs loop forever.
+pub const DEFAULT_STEP_BUDGET: u64 = 10_000_000;
+
+/// How many instructions to execute between looking at the clock in `compute_with_timeout`.
+const STEPS_PER_CLOCK_CHECK: u64 = 1024;
+
 #[derive(Clone)]
-pub struct IntcodeMachine {
-    memory: HashMap<Address, Value>,
+pub struct IntcodeMachine<M: Memory = VecMemory> {
+    memory: M,
     ip: Address,
     relative_base: Address,
-    input: Vec<Value>,
-    output: Vec<Value>,
+    input: VecDeque<M::Value>,
+    output: Vec<M::Value>,
     state: MachineState,
+    last_write: Option<(Address, M::Value)>,
+    memory_limit: Option<Address>,
+    /// Where the memory the machine was created with ends, the image of memory policies.
+    image_end: Address,
+    /// Bit n is set if the built-in opcode n is disabled.
+    disabled_opcodes: u128,
+    extensions: Option<Arc<Extensions<M>>>,
+    access: Option<Box<AccessControl>>,
 }
 
 impl IntcodeMachine {
-    pub fn from_string(input: &str) -> Self {
+    pub fn from_program(program: &Program) -> Self {
+        Self::with_memory(VecMemory::from_values(program))
+    }
+
+    pub fn from_string(input: &str) -> Result<Self, ParseError> {
+        Ok(Self::from_program(&input.parse()?))
+    }
+
+    pub fn from_stdin() -> Result<Self, Box<dyn Error>> {
+        let mut input = String::new();
+        std::io::stdin().read_to_string(&mut input)?;
+        Ok(Self::from_string(&input)?)
+    }
+}
+
+impl<M: Memory> IntcodeMachine<M> {
+    pub fn with_memory(memory: M) -> Self {
+        let image_end = memory.segments().last().map_or(0, |(start, values)| start + values.len() as Address);
         Self {
-            memory: {
-                let mut hashmap = HashMap::new();
-                for (idx, value) in input.split(',').map(|num| num.parse::<Value>().unwrap()).enumerate() {
-                    hashmap.insert(idx as Address, value);
-                }
-                hashmap
-            },
+            memory,
             ip: 0,
             relative_base: 0,
-            input: vec![],
+            input: VecDeque::new(),
             output: vec![],
             state: MachineState::Ready,
+            last_write: None,
+            memory_limit: Some(DEFAULT_MEMORY_LIMIT),
+            image_end,
+            disabled_opcodes: 0,
+            extensions: None,
+            access: None,
         }
     }
 
-    pub fn from_stdin() -> Self {
-        let buffered = BufReader::new(io::stdin());
-        Self::from_string(&buffered.lines().map(|line| line.unwrap()).join(","))
+    /// Loads the program into memory of type `M`, e.g. `IntcodeMachine::<VecMemory<i128>>::with_program`.
+    pub fn with_program(program: &Program) -> Self {
+        let values: Vec<M::Value> = program.iter().map(|&value| M::Value::from_i64(value)).collect();
+        Self::with_memory(M::from_values(&values))
     }
 
-    pub fn set_input(&mut self, input: Vec<Value>) {
-        self.input = input;
+    pub fn ip(&self) -> Address {
+        self.ip
+    }
+
+    pub fn relative_base(&self) -> Address {
+        self.relative_base
+    }
+
+    pub fn state(&self) -> MachineState {
+        self.state
+    }
+
+    pub fn pending_input(&self) -> &VecDeque<M::Value> {
+        &self.input
+    }
+
+    /// The memory cell written by the most recently executed instruction, if it wrote one.
+    pub fn last_write(&self) -> Option<(Address, M::Value)> {
+        self.last_write.clone()
+    }
+
+    pub fn set_input(&mut self, input: Vec<M::Value>) {
+        self.input = input.into();
     }
 
-    pub fn push_input(&mut self, input: Value) {
-        self.input.push(input);
+    pub fn push_input(&mut self, input: M::Value) {
+        self.input.push_back(input);
     }
 
-    pub fn get_outputs(&self) -> Vec<Value> {
+    pub fn get_outputs(&self) -> Vec<M::Value> {
         self.output.clone()
     }
 
-    pub fn get_outputs_and_clear(&mut self) -> Vec<Value> {
+    pub fn get_outputs_and_clear(&mut self) -> Vec<M::Value> {
         self.output.drain(0..).collect()
     }
 
-    pub fn get_output(&self) -> Value {
-        let output = self.get_outputs();
-        if output.len() < 1 {
-            panic!("There was no output!");
+    pub fn get_output(&self) -> Result<M::Value, IntcodeError<M::Value>> {
+        if self.output.len() != 1 {
+            return Err(IntcodeError::OutputCount { count: self.output.len() });
         }
-        if output.len() > 1 {
-            panic!("There was more than one output!");
-        }
-        self.output[0]
+        Ok(self.output[0].clone())
     }
 
-    pub fn compute(&mut self) -> MachineState {
-        self.state = MachineState::Running;
+    pub fn compute(&mut self) -> Result<MachineState, IntcodeError<M::Value>> {
+        self.with_buffers(|machine, input, output| machine.compute_with(input, output))
+    }
+
+    pub fn compute_with(&mut self, input: &mut impl InputSource<M::Value>, output: &mut impl OutputSink<M::Value>) -> Result<MachineState, IntcodeError<M::Value>> {
+        self.compute_with_tracer(input, output, &mut NoTracer)
+    }
+
+    pub fn compute_traced(&mut self, tracer: &mut impl Tracer<M::Value>) -> Result<MachineState, IntcodeError<M::Value>> {
+        self.with_buffers(|machine, input, output| machine.compute_with_tracer(input, output, tracer))
+    }
+
+    pub fn compute_with_tracer(&mut self, input: &mut impl InputSource<M::Value>, output: &mut impl OutputSink<M::Value>, tracer: &mut impl Tracer<M::Value>) -> Result<MachineState, IntcodeError<M::Value>> {
         loop {
-            let opvalue = OpValue::new(self.get_memory(self.ip));
-            match opvalue.opcode {
-                1 => self.add(&opvalue),
-                2 => self.mul(&opvalue),
-                3 => self.input(&opvalue),
-                4 => self.output(&opvalue),
-                5 => self.jump_if_true(&opvalue),
-                6 => self.jump_if_false(&opvalue),
-                7 => self.less_than(&opvalue),
-                8 => self.equals(&opvalue),
-                9 => self.set_relative_base(&opvalue),
-                99 => break,
-                _ => panic!("unknown opcode {:?} at position {}", opvalue.opcode, self.ip),
+            match self.execute(input, output, tracer)? {
+                MachineState::Running => continue,
+                state => return Ok(state),
             }
-            if self.state == MachineState::Waiting {
-                return self.state;
+        }
+    }
+
+    /// Like `compute`, but executes at most `max_steps` instructions.
+    pub fn compute_with_budget(&mut self, max_steps: u64) -> Result<MachineState, IntcodeError<M::Value>> {
+        self.with_buffers(|machine, input, output| machine.compute_with_budget_with(input, output, max_steps))
+    }
+
+    pub fn compute_with_budget_with(&mut self, input: &mut impl InputSource<M::Value>, output: &mut impl OutputSink<M::Value>, max_steps: u64) -> Result<MachineState, IntcodeError<M::Value>> {
+        self.compute_limited(input, output, |steps| steps < max_steps)
+    }
+
+    /// Like `compute`, but stops after roughly `timeout` has passed.
+    pub fn compute_with_timeout(&mut self, timeout: Duration) -> Result<MachineState, IntcodeError<M::Value>> {
+        self.with_buffers(|machine, input, output| machine.compute_with_timeout_with(input, output, timeout))
+    }
+
+    pub fn compute_with_timeout_with(&mut self, input: &mut impl InputSource<M::Value>, output: &mut impl OutputSink<M::Value>, timeout: Duration) -> Result<MachineState, IntcodeError<M::Value>> {
+        let deadline = Instant::now() + timeout;
+        self.compute_limited(input, output, |steps| steps % STEPS_PER_CLOCK_CHECK != 0 || Instant::now() < deadline)
+    }
+
+    fn compute_limited(&mut self, input: &mut impl InputSource<M::Value>, output: &mut impl OutputSink<M::Value>, mut keep_going: impl FnMut(u64) -> bool) -> Result<MachineState, IntcodeError<M::Value>> {
+        let mut steps = 0;
+        while keep_going(steps) {
+            match self.step_with(input, output)? {
+                MachineState::Running => steps += 1,
+                state => return Ok(state),
+            }
+        }
+        self.state = MachineState::BudgetExhausted;
+        Ok(self.state)
+    }
+
+    /// Executes a single instruction. Returns `Running` if there are more instructions to execute.
+    pub fn step(&mut self) -> Result<MachineState, IntcodeError<M::Value>> {
+        self.with_buffers(|machine, input, output| machine.step_with(input, output))
+    }
+
+    #[inline]
+    fn execute<T: Tracer<M::Value>>(&mut self, input: &mut impl InputSource<M::Value>, output: &mut impl OutputSink<M::Value>, tracer: &mut T) -> Result<MachineState, IntcodeError<M::Value>> {
+        if !T::ENABLED {
+            return self.step_with(input, output);
+        }
+        let event = OpValue::from_number(&self.get_memory(self.ip)).ok().and_then(|opvalue| self.trace_event(&opvalue));
+        let state = self.step_with(input, output)?;
+        if let Some(mut event) = event {
+            if state != MachineState::Waiting {
+                event.write = self.last_write.clone();
+                tracer.trace(&event);
             }
         }
-        MachineState::Done
+        Ok(state)
     }
 
-    pub fn get_memory(&self, address: Address) -> Value {
-        match self.memory.get(&address) {
-            Some(value) => *value,
-            None => 0,
+    #[inline]
+    pub fn step_with(&mut self, input: &mut impl InputSource<M::Value>, output: &mut impl OutputSink<M::Value>) -> Result<MachineState, IntcodeError<M::Value>> {
+        self.state = MachineState::Running;
+        self.last_write = None;
+        let opvalue = OpValue::from_number(&self.get_memory(self.ip))?;
+        if self.disabled_opcodes & (1 << opvalue.opcode) != 0 {
+            self.extension(&opvalue, input, output)?;
+            return Ok(self.state);
         }
+        match opvalue.opcode {
+            1 => self.add(&opvalue)?,
+            2 => self.mul(&opvalue)?,
+            3 => self.input(&opvalue, input)?,
+            4 => self.output(&opvalue, output)?,
+            5 => self.jump_if_true(&opvalue)?,
+            6 => self.jump_if_false(&opvalue)?,
+            7 => self.less_than(&opvalue)?,
+            8 => self.equals(&opvalue)?,
+            9 => self.set_relative_base(&opvalue)?,
+            99 => self.state = MachineState::Done,
+            _ => self.extension(&opvalue, input, output)?,
+        }
+        Ok(self.state)
     }
 
-    pub fn get_memory_vec(&self, range: Range<Address>) -> Vec<Value> {
-        let mut result = vec![];
-        for idx in range {
-            result.push(self.get_memory(idx));
+    fn extension(&mut self, opvalue: &OpValue, input: &mut dyn InputSource<M::Value>, output: &mut dyn OutputSink<M::Value>) -> Result<(), IntcodeError<M::Value>> {
+        match self.extensions.clone() {
+            Some(extensions) => extensions.execute(self, opvalue, input, output),
+            None => Err(IntcodeError::UnknownOpcode { opcode: opvalue.opcode.into(), ip: self.ip }),
         }
-        result
     }
 
-    pub fn set_memory(&mut self, param_mode: ParamMode, address: Value, value: Value) {
-        self.memory.insert(match param_mode {
-            ParamMode::Position => address as Address,
-            ParamMode::Relative => (address + (self.relative_base as i64)) as Address,
-            _ => panic!("set_memory not implemented for param mode {:?}", param_mode),
-        }, value);
+    /// Collects what the instruction is about to do. Errors are ignored, the instruction itself will report them.
+    fn trace_event(&self, opvalue: &OpValue) -> Option<TraceEvent<M::Value>> {
+        let opcode = Opcode::from_code(opvalue.opcode)?;
+        let zero = M::Value::from_i64(0);
+        let mut params = [zero.clone(), zero.clone(), zero.clone()];
+        for (pos, param) in params.iter_mut().enumerate().take(opcode.arity()) {
+            *param = if opcode.write_param() == Some(pos) {
+                self.param(pos).and_then(|param| self.write_address(opvalue.param_mode(pos), param)).map_or(zero.clone(), M::Value::from_address)
+            } else {
+                self.read_param(opvalue, pos).unwrap_or_else(|_| zero.clone())
+            };
+        }
+        Some(TraceEvent { ip: self.ip, relative_base: self.relative_base, opcode, params, write: None })
     }
 
-    fn fetch_params(&mut self, count: Address) -> Vec<Value> {
-        self.ip += 1;
-        let result = self.get_memory_vec(self.ip..(self.ip+count));
-        self.ip += count;
+    fn with_buffers<T>(&mut self, f: impl FnOnce(&mut Self, &mut VecDeque<M::Value>, &mut Vec<M::Value>) -> T) -> T {
+        let mut input = mem::take(&mut self.input);
+        let mut output = mem::take(&mut self.output);
+        let result = f(self, &mut input, &mut output);
+        self.input = input;
+        self.output = output;
         result
     }
 
-    fn resolve_position_params(&self, params: &[Value], opvalue: &OpValue) -> Vec<Value> {
-        let mut result = vec![];
-        for (idx, param) in params.iter().enumerate() {
-            result.push(match opvalue.param_mode(idx) {
-                ParamMode::Immediate => *param,
-                ParamMode::Position => self.get_memory(*param as Address),
-                ParamMode::Relative => self.get_memory((*param + (self.relative_base as i64)) as Address),
-            });
+    pub fn get_memory(&self, address: Address) -> M::Value {
+        self.memory.get(address)
+    }
+
+    pub fn get_memory_vec(&self, range: Range<Address>) -> Vec<M::Value> {
+        range.map(|idx| self.get_memory(idx)).collect()
+    }
+
+    pub fn set_memory(&mut self, param_mode: ParamMode, address: M::Value, value: M::Value) -> Result<(), IntcodeError<M::Value>> {
+        let address = self.write_address(param_mode, address)?;
+        self.write(address, value);
+        Ok(())
+    }
+
+    fn write(&mut self, address: Address, value: M::Value) {
+        if let Some(access) = &mut self.access {
+            access.initialize(address);
         }
-        result
+        self.last_write = Some((address, value.clone()));
+        self.memory.set(address, value);
     }
 
-    fn add(&mut self, opvalue: &OpValue) {
-        let params = self.fetch_params(3);
-        let resolved_params = self.resolve_position_params(&params, &opvalue);
-        self.set_memory(opvalue.param_mode(2), params[2], resolved_params[0] + resolved_params[1]);
+    pub fn memory_limit(&self) -> Option<Address> {
+        self.memory_limit
     }
 
-    fn mul(&mut self, opvalue: &OpValue) {
-        let params = self.fetch_params(3);
-        let resolved_params = self.resolve_position_params(&params, &opvalue);
-        self.set_memory(opvalue.param_mode(2), params[2], resolved_params[0] * resolved_params[1]);
+    /// Makes writes to addresses at or beyond `limit` fail, instead of growing the memory without bounds. Machines
+    /// start with `DEFAULT_MEMORY_LIMIT`, programs that need more memory have to raise or remove it.
+    pub fn set_memory_limit(&mut self, limit: Option<Address>) {
+        self.memory_limit = limit;
     }
 
-    fn input(&mut self, opvalue: &OpValue) {
-        if self.input.len() == 0 {
-            self.state = MachineState::Waiting;
-            return;
+    fn write_address(&self, param_mode: ParamMode, address: M::Value) -> Result<Address, IntcodeError<M::Value>> {
+        let address = match param_mode {
+            ParamMode::Position => to_address(address)?,
+            ParamMode::Relative => to_address(self.relative(&address)?)?,
+            ParamMode::Immediate => return Err(IntcodeError::ImmediateWrite { ip: self.ip }),
+        };
+        self.check_limit(address)?;
+        Ok(address)
+    }
+
+    fn check_limit(&self, address: Address) -> Result<(), IntcodeError<M::Value>> {
+        match self.memory_limit {
+            Some(limit) if address >= limit => Err(IntcodeError::MemoryLimit { address, limit }),
+            _ => Ok(()),
         }
-        let params = self.fetch_params(1);
-        let input = self.input.remove(0);
-        self.set_memory(opvalue.param_mode(0), params[0], input);
     }
 
-    fn output(&mut self, opvalue: &OpValue) {
-        let params = self.fetch_params(1);
-        let resolved_params = self.resolve_position_params(&params, &opvalue);
-        self.output.push(resolved_params[0]);
+    fn relative(&self, offset: &M::Value) -> Result<M::Value, IntcodeError<M::Value>> {
+        M::Value::from_address(self.relative_base).checked_add(offset).ok_or(IntcodeError::Overflow { ip: self.ip })
     }
 
-    fn jump_if_true(&mut self, opvalue: &OpValue) {
-        let params = self.fetch_params(2);
-        let resolved_params = self.resolve_position_params(&params, &opvalue);
-        if resolved_params[0] != 0 {
-            self.ip = resolved_params[1] as Address;
+    /// The address `offset` cells after the instruction pointer. Only values wider than `i64` can jump far
+    /// enough for this to fail.
+    fn after_ip(&self, offset: Address) -> Result<Address, IntcodeError<M::Value>> {
+        match self.ip.checked_add(offset) {
+            Some(address) => Ok(address),
+            None => to_address(M::Value::from_address(self.ip).checked_add(&M::Value::from_address(offset)).ok_or(IntcodeError::Overflow { ip: self.ip })?),
         }
     }
 
-    fn jump_if_false(&mut self, opvalue: &OpValue) {
-        let params = self.fetch_params(2);
-        let resolved_params = self.resolve_position_params(&params, &opvalue);
-        if resolved_params[0] == 0 {
-            self.ip = resolved_params[1] as Address;
+    fn param(&self, pos: usize) -> Result<M::Value, IntcodeError<M::Value>> {
+        Ok(self.get_memory(self.after_ip(1 + pos as Address)?))
+    }
+
+    /// The address a parameter reads from, `None` in immediate mode.
+    fn param_address(&self, opvalue: &OpValue, pos: usize) -> Result<Option<Address>, IntcodeError<M::Value>> {
+        let param = self.param(pos)?;
+        Ok(match opvalue.param_mode(pos) {
+            ParamMode::Immediate => None,
+            ParamMode::Position => Some(to_address(param)?),
+            ParamMode::Relative => Some(to_address(self.relative(&param)?)?),
+        })
+    }
+
+    /// Reads a parameter without applying the memory policy.
+    fn read_param(&self, opvalue: &OpValue, pos: usize) -> Result<M::Value, IntcodeError<M::Value>> {
+        Ok(match self.param_address(opvalue, pos)? {
+            Some(address) => self.get_memory(address),
+            None => self.param(pos)?,
+        })
+    }
+
+    fn load_param(&mut self, opvalue: &OpValue, pos: usize) -> Result<M::Value, IntcodeError<M::Value>> {
+        if self.access.is_none() {
+            return self.read_param(opvalue, pos);
         }
+        match self.param_address(opvalue, pos)? {
+            Some(address) => {
+                let ip = self.ip;
+                self.access.as_mut().unwrap().read(ip, address)?;
+                Ok(self.get_memory(address))
+            },
+            None => self.param(pos),
+        }
+    }
+
+    fn check_write(&mut self, address: Address) -> Result<(), IntcodeError<M::Value>> {
+        let ip = self.ip;
+        match &mut self.access {
+            Some(access) => access.write(ip, address),
+            None => Ok(()),
+        }
+    }
+
+    fn write_param(&mut self, opvalue: &OpValue, pos: usize, value: M::Value) -> Result<(), IntcodeError<M::Value>> {
+        let address = self.write_address(opvalue.param_mode(pos), self.param(pos)?)?;
+        self.check_write(address)?;
+        self.write(address, value);
+        Ok(())
+    }
+
+    fn add(&mut self, opvalue: &OpValue) -> Result<(), IntcodeError<M::Value>> {
+        let sum = self.load_param(opvalue, 0)?.checked_add(&self.load_param(opvalue, 1)?).ok_or(IntcodeError::Overflow { ip: self.ip })?;
+        self.write_param(opvalue, 2, sum)?;
+        self.ip = self.after_ip(4)?;
+        Ok(())
+    }
+
+    fn mul(&mut self, opvalue: &OpValue) -> Result<(), IntcodeError<M::Value>> {
+        let product = self.load_param(opvalue, 0)?.checked_mul(&self.load_param(opvalue, 1)?).ok_or(IntcodeError::Overflow { ip: self.ip })?;
+        self.write_param(opvalue, 2, product)?;
+        self.ip = self.after_ip(4)?;
+        Ok(())
+    }
+
+    fn input(&mut self, opvalue: &OpValue, input: &mut impl InputSource<M::Value>) -> Result<(), IntcodeError<M::Value>> {
+        // Make sure the write will work before consuming the value.
+        let address = self.write_address(opvalue.param_mode(0), self.param(0)?)?;
+        self.check_write(address)?;
+        let value = match input.read_input() {
+            Some(value) => value,
+            None => {
+                self.state = MachineState::Waiting;
+                return Ok(());
+            },
+        };
+        self.write(address, value);
+        self.ip = self.after_ip(2)?;
+        Ok(())
+    }
+
+    fn output(&mut self, opvalue: &OpValue, output: &mut impl OutputSink<M::Value>) -> Result<(), IntcodeError<M::Value>> {
+        let value = self.load_param(opvalue, 0)?;
+        output.write_output(value)?;
+        self.ip = self.after_ip(2)?;
+        Ok(())
+    }
+
+    fn jump_if_true(&mut self, opvalue: &OpValue) -> Result<(), IntcodeError<M::Value>> {
+        self.ip = if !self.load_param(opvalue, 0)?.is_zero() { to_address(self.load_param(opvalue, 1)?)? } else { self.after_ip(3)? };
+        Ok(())
     }
 
-    fn less_than(&mut self, opvalue: &OpValue) {
-        let params = self.fetch_params(3);
-        let resolved_params = self.resolve_position_params(&params, &opvalue);
-        self.set_memory(opvalue.param_mode(2), params[2], if resolved_params[0] < resolved_params[1] { 1 } else { 0 });
+    fn jump_if_false(&mut self, opvalue: &OpValue) -> Result<(), IntcodeError<M::Value>> {
+        self.ip = if self.load_param(opvalue, 0)?.is_zero() { to_address(self.load_param(opvalue, 1)?)? } else { self.after_ip(3)? };
+        Ok(())
     }
 
-    fn equals(&mut self, opvalue: &OpValue) {
-        let params = self.fetch_params(3);
-        let resolved_params = self.resolve_position_params(&params, &opvalue);
-        self.set_memory(opvalue.param_mode(2), params[2], if resolved_params[0] == resolved_params[1] { 1 } else { 0 });
+    fn less_than(&mut self, opvalue: &OpValue) -> Result<(), IntcodeError<M::Value>> {
+        let result = if self.load_param(opvalue, 0)? < self.load_param(opvalue, 1)? { 1 } else { 0 };
+        self.write_param(opvalue, 2, M::Value::from_i64(result))?;
+        self.ip = self.after_ip(4)?;
+        Ok(())
     }
 
-    fn set_relative_base(&mut self, opvalue: &OpValue) {
-        let params = self.fetch_params(1);
-        let resolved_params = self.resolve_position_params(&params, &opvalue);
-        self.relative_base = ((self.relative_base as i64) + resolved_params[0]).try_into().unwrap();
+    fn equals(&mut self, opvalue: &OpValue) -> Result<(), IntcodeError<M::Value>> {
+        let result = if self.load_param(opvalue, 0)? == self.load_param(opvalue, 1)? { 1 } else { 0 };
+        self.write_param(opvalue, 2, M::Value::from_i64(result))?;
+        self.ip = self.after_ip(4)?;
+        Ok(())
+    }
+
+    fn set_relative_base(&mut self, opvalue: &OpValue) -> Result<(), IntcodeError<M::Value>> {
+        let offset = self.load_param(opvalue, 0)?;
+        let relative_base = self.relative(&offset)?;
+        if relative_base.is_negative() {
+            return Err(IntcodeError::RelativeBaseUnderflow { relative_base: self.relative_base, offset });
+        }
+        self.relative_base = to_address(relative_base)?;
+        self.ip = self.after_ip(2)?;
+        Ok(())
+    }
+}
+
+pub(crate) fn to_address<V: Number>(address: V) -> Result<Address, IntcodeError<V>> {
+    match address.to_address() {
+        Some(address) => Ok(address),
+        None if address.is_negative() => Err(IntcodeError::NegativeAddress { address }),
+        None => Err(IntcodeError::AddressTooLarge { address }),
     }
 }
 
@@ -214,95 +505,319 @@ pub enum ParamMode {
 #[derive(Debug, PartialEq)]
 pub struct OpValue {
     opcode: u8,
-    param_modes: Vec<ParamMode>,
+    // The parameter mode digits as they appear in the instruction, i.e. the first parameter's mode is the last digit.
+    param_modes: Value,
 }
 
 impl OpValue {
-    fn new(value: Value) -> Self {
-        let opcode = (value % 100).try_into().unwrap();
-        let mut param_modes: Vec<ParamMode> = (value / 100).to_string().chars().map(|d| match d {
-            '0' => ParamMode::Position,
-            '1' => ParamMode::Immediate,
-            '2' => ParamMode::Relative,
-            _ => unimplemented!(),
-        }).collect();
-        param_modes.reverse();
-        OpValue { opcode, param_modes: param_modes }
-    }
-
-    fn param_mode(&self, pos: usize) -> ParamMode {
-        if pos < self.param_modes.len() {
-            self.param_modes[pos]
-        } else {
-            ParamMode::Position
+    pub fn new(value: Value) -> Result<Self, IntcodeError> {
+        // A negative instruction has a minus sign where the parameter modes should be.
+        if value < 0 {
+            return Err(IntcodeError::InvalidParamMode { instruction: value, digit: '-' });
+        }
+        let mut digits = value / 100;
+        while digits > 0 {
+            let digit = digits % 10;
+            if digit > 2 {
+                return Err(IntcodeError::InvalidParamMode {
+                    instruction: value, digit: std::char::from_digit(digit as u32, 10).unwrap(),
+                });
+            }
+            digits /= 10;
+        }
+        Ok(OpValue { opcode: (value % 100) as u8, param_modes: value / 100 })
+    }
+
+    #[inline]
+    fn from_number<V: Number>(value: &V) -> Result<Self, IntcodeError<V>> {
+        let invalid = |digit| IntcodeError::InvalidParamMode { instruction: value.clone(), digit };
+        match value.to_i64() {
+            Some(instruction) => Self::new(instruction).map_err(|error| match error {
+                IntcodeError::InvalidParamMode { digit, .. } => invalid(digit),
+                _ => unreachable!("decoding only fails because of parameter modes"),
+            }),
+            None if value.is_negative() => Err(invalid('-')),
+            None => {
+                // No instruction has modes for as many parameters as there are digits, so the instruction is
+                // invalid even if all of them are valid modes.
+                let text = value.to_string();
+                let modes = &text[..(text.len() - 2)];
+                Err(invalid(modes.chars().rev().find(|&digit| digit > '2').unwrap_or_else(|| modes.chars().next().unwrap())))
+            },
+        }
+    }
+
+    pub fn opcode(&self) -> u8 {
+        self.opcode
+    }
+
+    pub fn param_mode(&self, pos: usize) -> ParamMode {
+        match self.param_modes / 10_i64.saturating_pow(pos as u32) % 10 {
+            1 => ParamMode::Immediate,
+            2 => ParamMode::Relative,
+            _ => ParamMode::Position,
         }
     }
+
+    /// Whether there are mode digits for parameters at position `count` or later.
+    pub fn has_modes_beyond(&self, count: usize) -> bool {
+        self.param_modes / 10_i64.saturating_pow(count as u32) != 0
+    }
 }
 
 #[test]
 fn test_string_parse() {
-    let machine = IntcodeMachine::from_string("1,2,3");
+    let machine = IntcodeMachine::from_string("1,2,3").unwrap();
     assert_eq!(machine.get_memory_vec(0..3), vec![1,2,3]);
 }
 
+#[test]
+fn test_string_parse_error() {
+    assert_eq!(IntcodeMachine::from_string("1,2,x").err().map(|error| error.index), Some(2));
+}
+
 #[test]
 fn test_opvalue_parse() {
-    assert_eq!(OpValue::new(110199), OpValue {
-        opcode: 99, param_modes: vec![ParamMode::Immediate, ParamMode::Position, ParamMode::Immediate, ParamMode::Immediate]
-    })
+    let opvalue = OpValue::new(110199).unwrap();
+    assert_eq!(opvalue.opcode, 99);
+    assert_eq!((0..4).map(|pos| opvalue.param_mode(pos)).collect::<Vec<ParamMode>>(),
+        vec![ParamMode::Immediate, ParamMode::Position, ParamMode::Immediate, ParamMode::Immediate]);
 }
 
 #[test]
 fn test_opvalue_default_param_mode() {
-    assert_eq!(OpValue::new(110199).param_mode(5), ParamMode::Position);
+    assert_eq!(OpValue::new(110199).unwrap().param_mode(5), ParamMode::Position);
+}
+
+#[test]
+fn test_opvalue_invalid_param_mode() {
+    assert_eq!(OpValue::new(1301), Err(IntcodeError::InvalidParamMode { instruction: 1301, digit: '3' }));
+    assert_eq!(OpValue::new(-1), Err(IntcodeError::InvalidParamMode { instruction: -1, digit: '-' }));
 }
 
 #[test]
 fn test_add() {
-    let mut machine = IntcodeMachine::from_string("1001,1,14,3,99");
-    machine.compute();
+    let mut machine = IntcodeMachine::from_string("1001,1,14,3,99").unwrap();
+    machine.compute().unwrap();
     assert_eq!(machine.get_memory_vec(0..5), vec![1001,1,14,15,99]);
 }
 
 #[test]
 fn test_day2() {
-    let mut machine = IntcodeMachine::from_string("1,9,10,3,2,3,11,0,99,30,40,50");
-    machine.compute();
+    let mut machine = IntcodeMachine::from_string("1,9,10,3,2,3,11,0,99,30,40,50").unwrap();
+    machine.compute().unwrap();
     assert_eq!(machine.get_memory_vec(0..12), vec![3500,9,10,70,2,3,11,0,99,30,40,50]);
 }
 
 #[test]
 fn test_relative_mode() {
-    let mut machine = IntcodeMachine::from_string("109,19,204,-2019,99");
+    let mut machine = IntcodeMachine::from_string("109,19,204,-2019,99").unwrap();
     machine.relative_base = 2000;
-    machine.compute();
-    assert_eq!(machine.get_output(), 109);
+    machine.compute().unwrap();
+    assert_eq!(machine.get_output(), Ok(109));
 }
 
 #[test]
 fn test_day9_quine() {
-    let mut machine = IntcodeMachine::from_string("109,1,204,-1,1001,100,1,100,1008,100,16,101,1006,101,0,99");
-    machine.compute();
+    let mut machine = IntcodeMachine::from_string("109,1,204,-1,1001,100,1,100,1008,100,16,101,1006,101,0,99").unwrap();
+    machine.compute().unwrap();
     assert_eq!(machine.get_outputs(), vec![109,1,204,-1,1001,100,1,100,1008,100,16,101,1006,101,0,99]);
 }
 
 #[test]
 fn test_day9_16digit() {
-    let mut machine = IntcodeMachine::from_string("1102,34915192,34915192,7,4,7,99,0");
-    machine.compute();
-    assert_eq!(machine.get_output().to_string().len(), 16);
+    let mut machine = IntcodeMachine::from_string("1102,34915192,34915192,7,4,7,99,0").unwrap();
+    machine.compute().unwrap();
+    assert_eq!(machine.get_output().unwrap().to_string().len(), 16);
 }
 
 #[test]
 fn test_day9_long_number() {
-    let mut machine = IntcodeMachine::from_string("104,1125899906842624,99");
-    machine.compute();
-    assert_eq!(machine.get_output(), 1125899906842624);
+    let mut machine = IntcodeMachine::from_string("104,1125899906842624,99").unwrap();
+    machine.compute().unwrap();
+    assert_eq!(machine.get_output(), Ok(1125899906842624));
 }
 
 #[test]
 fn test_write_to_relative() {
-    let mut machine = IntcodeMachine::from_string("109,5,21101,5,23,14,99");
-    machine.compute();
+    let mut machine = IntcodeMachine::from_string("109,5,21101,5,23,14,99").unwrap();
+    machine.compute().unwrap();
     assert_eq!(machine.get_memory_vec(0..20), vec![109,5,21101,5,23,14,99,0,0,0,0,0,0,0,0,0,0,0,0,28]);
 }
+
+#[test]
+fn test_sparse_memory_backend() {
+    let program: Program = "109,5,21101,5,23,14,99".parse().unwrap();
+    let mut machine = IntcodeMachine::with_memory(SparseMemory::from_values(&program));
+    machine.compute().unwrap();
+    assert_eq!(machine.get_memory_vec(0..20), vec![109,5,21101,5,23,14,99,0,0,0,0,0,0,0,0,0,0,0,0,28]);
+}
+
+#[test]
+fn test_huge_addresses() {
+    let mut machine = IntcodeMachine::from_string("1101,1,1,9223372036854775807,21101,2,2,1000000000000,99").unwrap();
+    assert_eq!(machine.compute(), Err(IntcodeError::MemoryLimit { address: i64::MAX as Address, limit: DEFAULT_MEMORY_LIMIT }));
+    machine.set_memory_limit(None);
+    machine.compute().unwrap();
+    assert_eq!(machine.get_memory(i64::MAX as Address), 2);
+    assert_eq!(machine.get_memory(1_000_000_000_000), 4);
+}
+
+#[test]
+fn test_compute_with_closures() {
+    let mut machine = IntcodeMachine::from_string("3,9,1002,9,2,9,4,9,99,0").unwrap();
+    let mut outputs = vec![];
+    let state = machine.compute_with(&mut || Some(21), &mut |value| outputs.push(value));
+    assert_eq!(state, Ok(MachineState::Done));
+    assert_eq!(outputs, vec![42]);
+}
+
+#[test]
+fn test_compute_with_channels() {
+    let (input_tx, input_rx) = std::sync::mpsc::channel();
+    let (output_tx, output_rx) = std::sync::mpsc::channel();
+    let mut machine = IntcodeMachine::from_string("3,9,1002,9,2,9,4,9,99,0").unwrap();
+    input_tx.send(5).unwrap();
+    machine.compute_with(&mut { input_rx }, &mut { output_tx }).unwrap();
+    assert_eq!(output_rx.recv(), Ok(10));
+}
+
+#[test]
+fn test_waiting_keeps_memory() {
+    let mut machine = IntcodeMachine::from_string("3,3,99,7").unwrap();
+    assert_eq!(machine.compute(), Ok(MachineState::Waiting));
+    assert_eq!(machine.get_memory(3), 7);
+    machine.push_input(8);
+    assert_eq!(machine.compute(), Ok(MachineState::Done));
+    assert_eq!(machine.get_memory(3), 8);
+}
+
+#[test]
+fn test_unknown_opcode() {
+    let mut machine = IntcodeMachine::from_string("1,0,0,0,42").unwrap();
+    assert_eq!(machine.compute(), Err(IntcodeError::UnknownOpcode { opcode: 42, ip: 4 }));
+}
+
+#[test]
+fn test_immediate_write() {
+    let mut machine = IntcodeMachine::from_string("11101,1,1,0,99").unwrap();
+    assert_eq!(machine.compute(), Err(IntcodeError::ImmediateWrite { ip: 0 }));
+}
+
+#[test]
+fn test_negative_address() {
+    let mut machine = IntcodeMachine::from_string("1,-1,0,0,99").unwrap();
+    assert_eq!(machine.compute(), Err(IntcodeError::NegativeAddress { address: -1 }));
+}
+
+#[test]
+fn test_overflow() {
+    let mut machine = IntcodeMachine::from_string("1101,9223372036854775807,1,0,99").unwrap();
+    assert_eq!(machine.compute(), Err(IntcodeError::Overflow { ip: 0 }));
+    let mut machine = IntcodeMachine::from_string("109,9223372036854775807,209,1,99").unwrap();
+    assert_eq!(machine.compute(), Err(IntcodeError::Overflow { ip: 2 }));
+}
+
+#[test]
+fn test_i128_values() {
+    let program: Program = "1101,9223372036854775807,1,0,99".parse().unwrap();
+    let mut machine = IntcodeMachine::<VecMemory<i128>>::with_program(&program);
+    assert_eq!(machine.compute(), Ok(MachineState::Done));
+    assert_eq!(machine.get_memory(0), 1 << 63);
+    // Writes 2^64 into the output instruction's parameter.
+    let program: Program = "1102,4294967296,4294967296,5,4,0,99".parse().unwrap();
+    let mut machine = IntcodeMachine::<VecMemory<i128>>::with_program(&program);
+    assert_eq!(machine.compute(), Err(IntcodeError::AddressTooLarge { address: 1 << 64 }));
+}
+
+#[test]
+fn test_instructions_at_the_last_address() {
+    // Writes an output instruction to the last address and jumps there, its parameter would be beyond it.
+    let last = Address::MAX as i128;
+    let mut machine = IntcodeMachine::with_memory(VecMemory::from_values(&[1101, 104, 0, last, 1105, 1, last, 99]));
+    machine.set_memory_limit(None);
+    assert_eq!(machine.compute(), Err(IntcodeError::AddressTooLarge { address: 1 << 64 }));
+    assert_eq!(machine.ip(), Address::MAX);
+}
+
+#[test]
+fn test_bignum_values() {
+    // Squares the input twice.
+    let program: Program = "3,13,2,13,13,13,2,13,13,13,4,13,99,0".parse().unwrap();
+    let mut machine = IntcodeMachine::<SparseMemory<num::BigInt>>::with_program(&program);
+    machine.push_input(num::BigInt::from(1_000_000_007));
+    assert_eq!(machine.compute(), Ok(MachineState::Done));
+    assert_eq!(machine.get_output().unwrap().to_string(), "1000000028000000294000001372000002401");
+}
+
+#[test]
+fn test_bignum_instructions() {
+    let mut machine = IntcodeMachine::<VecMemory<num::BigInt>>::with_program(&"99".parse().unwrap());
+    let instruction: num::BigInt = "100000000000000000000000001".parse().unwrap();
+    machine.set_memory(ParamMode::Position, 0.into(), instruction.clone()).unwrap();
+    assert_eq!(machine.compute(), Err(IntcodeError::InvalidParamMode { instruction, digit: '1' }));
+    let instruction: num::BigInt = "-100000000000000000000000001".parse().unwrap();
+    assert_eq!(OpValue::from_number(&instruction), Err(IntcodeError::InvalidParamMode { instruction, digit: '-' }));
+    let instruction: num::BigInt = "100000000000000300000000001".parse().unwrap();
+    assert_eq!(OpValue::from_number(&instruction), Err(IntcodeError::InvalidParamMode { instruction, digit: '3' }));
+}
+
+#[test]
+fn test_relative_base_underflow() {
+    let mut machine = IntcodeMachine::from_string("109,-1,99").unwrap();
+    assert_eq!(machine.compute(), Err(IntcodeError::RelativeBaseUnderflow { relative_base: 0, offset: -1 }));
+}
+
+#[test]
+fn test_output_count() {
+    let mut machine = IntcodeMachine::from_string("104,1,104,2,99").unwrap();
+    machine.compute().unwrap();
+    assert_eq!(machine.get_output(), Err(IntcodeError::OutputCount { count: 2 }));
+}
+
+#[test]
+fn test_step_budget() {
+    // Loops forever.
+    let mut machine = IntcodeMachine::from_string("1105,1,0").unwrap();
+    assert_eq!(machine.compute_with_budget(100), Ok(MachineState::BudgetExhausted));
+    assert_eq!(machine.state(), MachineState::BudgetExhausted);
+    assert_eq!(machine.compute_with_timeout(Duration::from_millis(10)), Ok(MachineState::BudgetExhausted));
+}
+
+#[test]
+fn test_budget_resumes() {
+    // Counts down from 3, outputting every value, in 11 steps.
+    let mut machine = IntcodeMachine::from_string("1101,3,0,14,4,14,1001,14,-1,14,1005,14,4,99,0").unwrap();
+    assert_eq!(machine.compute_with_budget(5), Ok(MachineState::BudgetExhausted));
+    assert_eq!(machine.get_outputs(), vec![3,2]);
+    assert_eq!(machine.compute_with_budget(5), Ok(MachineState::BudgetExhausted));
+    assert_eq!(machine.compute_with_budget(1), Ok(MachineState::Done));
+    assert_eq!(machine.get_outputs(), vec![3,2,1]);
+}
+
+#[test]
+fn test_budget_waiting() {
+    let mut machine = IntcodeMachine::from_string("3,0,99").unwrap();
+    assert_eq!(machine.compute_with_budget(10), Ok(MachineState::Waiting));
+    assert_eq!(machine.compute_with_timeout(Duration::from_secs(10)), Ok(MachineState::Waiting));
+}
+
+#[test]
+fn test_memory_limit() {
+    let mut machine = IntcodeMachine::from_string("1101,1,2,1000,99").unwrap();
+    machine.set_memory_limit(Some(1000));
+    assert_eq!(machine.compute(), Err(IntcodeError::MemoryLimit { address: 1000, limit: 1000 }));
+    assert_eq!(machine.ip(), 0);
+    machine.set_memory_limit(Some(1001));
+    assert_eq!(machine.compute(), Ok(MachineState::Done));
+    assert_eq!(machine.get_memory(1000), 3);
+}
+
+#[test]
+fn test_memory_limit_keeps_input() {
+    let mut machine = IntcodeMachine::from_string("3,9223372036854775807,99").unwrap();
+    machine.set_memory_limit(Some(1 << 20));
+    machine.push_input(1);
+    assert_eq!(machine.compute(), Err(IntcodeError::MemoryLimit { address: i64::MAX as Address, limit: 1 << 20 }));
+    assert_eq!(machine.pending_input().len(), 1);
+}
@@ -0,0 +1,143 @@
+use std::collections::HashMap;
+use itertools::Itertools;
+use crate::{Address, Number, Value};
+
+/// Backing store of an `IntcodeMachine`. Addresses that have never been written to read as zero.
+pub trait Memory: Clone {
+    type Value: Number;
+
+    fn from_values(values: &[Self::Value]) -> Self;
+    fn get(&self, address: Address) -> Self::Value;
+    fn set(&mut self, address: Address, value: Self::Value);
+    /// The stored cells as runs of consecutive addresses, in address order.
+    fn segments(&self) -> Vec<(Address, Vec<Self::Value>)>;
+}
+
+/// Writes at or beyond this address don't grow `VecMemory`'s contiguous part, so that a single write to a huge
+/// address doesn't allocate everything before it.
+const DENSE_LIMIT: Address = 1 << 20;
+
+/// Contiguous memory that grows (and fills the gap with zeros) when writing beyond its end. Cells at very large
+/// addresses are stored sparsely instead.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct VecMemory<V: Number = Value> {
+    dense: Vec<V>,
+    sparse: HashMap<Address, V>,
+}
+
+impl<V: Number> Memory for VecMemory<V> {
+    type Value = V;
+
+    fn from_values(values: &[V]) -> Self {
+        VecMemory { dense: values.to_vec(), sparse: HashMap::new() }
+    }
+
+    #[inline]
+    fn get(&self, address: Address) -> V {
+        match self.dense.get(address as usize) {
+            Some(value) => value.clone(),
+            None => self.sparse.get(&address).cloned().unwrap_or_else(|| V::from_i64(0)),
+        }
+    }
+
+    fn set(&mut self, address: Address, value: V) {
+        let len = self.dense.len() as Address;
+        if address >= len && address >= DENSE_LIMIT {
+            self.sparse.insert(address, value);
+            return;
+        }
+        let idx = address as usize;
+        if address >= len {
+            self.dense.resize(idx + 1, V::from_i64(0));
+        }
+        self.dense[idx] = value;
+    }
+
+    fn segments(&self) -> Vec<(Address, Vec<V>)> {
+        let mut segments = vec![(0, self.dense.clone())];
+        segments.extend(runs(&self.sparse));
+        segments
+    }
+}
+
+/// Groups the cells into runs of consecutive addresses.
+fn runs<V: Number>(cells: &HashMap<Address, V>) -> Vec<(Address, Vec<V>)> {
+    let mut segments: Vec<(Address, Vec<V>)> = vec![];
+    for (&address, value) in cells.iter().sorted_by_key(|&(address, _)| *address) {
+        match segments.last_mut() {
+            Some((start, values)) if *start + values.len() as Address == address => values.push(value.clone()),
+            _ => segments.push((address, vec![value.clone()])),
+        }
+    }
+    segments
+}
+
+/// Memory that only stores addresses that have been written to, for programs that use few, but very large addresses.
+/// Those need a higher memory limit than the default one, see `IntcodeMachine::set_memory_limit`.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct SparseMemory<V: Number = Value>(HashMap<Address, V>);
+
+impl<V: Number> Memory for SparseMemory<V> {
+    type Value = V;
+
+    fn from_values(values: &[V]) -> Self {
+        let mut hashmap = HashMap::new();
+        for (idx, value) in values.iter().enumerate() {
+            hashmap.insert(idx as Address, value.clone());
+        }
+        SparseMemory(hashmap)
+    }
+
+    fn get(&self, address: Address) -> V {
+        match self.0.get(&address) {
+            Some(value) => value.clone(),
+            None => V::from_i64(0),
+        }
+    }
+
+    fn set(&mut self, address: Address, value: V) {
+        self.0.insert(address, value);
+    }
+
+    fn segments(&self) -> Vec<(Address, Vec<V>)> {
+        runs(&self.0)
+    }
+}
+
+#[test]
+fn test_vec_memory_zero_fill() {
+    let mut memory: VecMemory = VecMemory::from_values(&[1,2,3]);
+    memory.set(6, 7);
+    assert_eq!(memory.segments(), vec![(0, vec![1,2,3,0,0,0,7])]);
+    assert_eq!(memory.get(100), 0);
+}
+
+#[test]
+fn test_vec_memory_huge_addresses() {
+    let mut memory: VecMemory = VecMemory::from_values(&[1,2,3]);
+    memory.set(i64::MAX as Address, 4);
+    memory.set(1_000_000_000_000, 5);
+    memory.set(1_000_000_000_001, 6);
+    assert_eq!(memory.get(i64::MAX as Address), 4);
+    assert_eq!(memory.get(1_000_000_000_000), 5);
+    assert_eq!(memory.get(1_000_000), 0);
+    assert_eq!(memory.segments(), vec![(0, vec![1,2,3]), (1_000_000_000_000, vec![5,6]), (i64::MAX as Address, vec![4])]);
+}
+
+#[test]
+fn test_sparse_memory() {
+    let mut memory: SparseMemory = SparseMemory::from_values(&[1,2,3]);
+    memory.set(1_000_000_000_000, 7);
+    assert_eq!(memory.get(1), 2);
+    assert_eq!(memory.get(1_000_000_000_000), 7);
+    assert_eq!(memory.get(100), 0);
+}
+
+#[test]
+fn test_sparse_memory_segments() {
+    let mut memory: SparseMemory = SparseMemory::from_values(&[1,2,3]);
+    memory.set(10, 4);
+    memory.set(11, 5);
+    memory.set(3, 6);
+    assert_eq!(memory.segments(), vec![(0, vec![1,2,3,6]), (10, vec![4,5])]);
+}
@@ -0,0 +1,280 @@
+use std::error::Error;
+use std::fmt;
+use crate::{IntcodeError, IntcodeMachine, MachineState, Memory, Value, VecMemory};
+
+pub type MachineId = usize;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum Link {
+    /// Every output value is sent to the machine.
+    Direct(MachineId),
+    /// Output values are grouped into a destination address followed by `payload_size` values.
+    Addressed { payload_size: usize },
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Packet {
+    pub source: MachineId,
+    pub destination: Value,
+    pub payload: Vec<Value>,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum NetworkState {
+    /// All machines have halted.
+    Done,
+    /// Every machine that hasn't halted waits for input that no other machine is going to send. Unless input
+    /// is pushed from outside, the network is deadlocked.
+    Idle { waiting: Vec<MachineId> },
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct NetworkError {
+    pub machine: MachineId,
+    pub error: IntcodeError,
+}
+
+impl fmt::Display for NetworkError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "machine {}: {}", self.machine, self.error)
+    }
+}
+
+impl Error for NetworkError {}
+
+/// Machines connected by channels, run round-robin. Outputs of machines without outgoing links stay in the
+/// machine.
+#[derive(Clone)]
+pub struct IntcodeNetwork<M: Memory = VecMemory> {
+    machines: Vec<IntcodeMachine<M>>,
+    links: Vec<Vec<Link>>,
+    partial_packets: Vec<Vec<Value>>,
+    undelivered: Vec<Packet>,
+    last_output: Option<(MachineId, Value)>,
+}
+
+impl<M: Memory<Value = Value>> Default for IntcodeNetwork<M> {
+    fn default() -> Self {
+        Self { machines: vec![], links: vec![], partial_packets: vec![], undelivered: vec![], last_output: None }
+    }
+}
+
+impl<M: Memory<Value = Value>> IntcodeNetwork<M> {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Every machine sends its outputs to the next one.
+    pub fn pipeline(machines: Vec<IntcodeMachine<M>>) -> Self {
+        let mut network = Self::new();
+        for machine in machines {
+            let id = network.add_machine(machine);
+            if id > 0 {
+                network.connect(id - 1, id);
+            }
+        }
+        network
+    }
+
+    /// A pipeline where the last machine sends its outputs back to the first one.
+    pub fn ring(machines: Vec<IntcodeMachine<M>>) -> Self {
+        let mut network = Self::pipeline(machines);
+        if network.len() > 1 {
+            network.connect(network.len() - 1, 0);
+        }
+        network
+    }
+
+    pub fn add_machine(&mut self, machine: IntcodeMachine<M>) -> MachineId {
+        self.machines.push(machine);
+        self.links.push(vec![]);
+        self.partial_packets.push(vec![]);
+        self.machines.len() - 1
+    }
+
+    /// Sends every output of `from` to `to`. Connecting a machine to several others broadcasts its outputs.
+    pub fn connect(&mut self, from: MachineId, to: MachineId) {
+        assert!(to < self.machines.len(), "no machine with id {}", to);
+        self.links[from].push(Link::Direct(to));
+    }
+
+    pub fn broadcast(&mut self, from: MachineId, to: &[MachineId]) {
+        for &target in to {
+            self.connect(from, target);
+        }
+    }
+
+    /// Treats the outputs of `from` as packets of a destination machine id followed by `payload_size` values,
+    /// and sends the payload to that machine. Packets for unknown machines end up in `undelivered_packets`.
+    pub fn route_packets(&mut self, from: MachineId, payload_size: usize) {
+        self.links[from].push(Link::Addressed { payload_size });
+    }
+
+    pub fn len(&self) -> usize {
+        self.machines.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.machines.is_empty()
+    }
+
+    pub fn machine(&self, id: MachineId) -> &IntcodeMachine<M> {
+        &self.machines[id]
+    }
+
+    pub fn machine_mut(&mut self, id: MachineId) -> &mut IntcodeMachine<M> {
+        &mut self.machines[id]
+    }
+
+    pub fn push_input(&mut self, id: MachineId, value: Value) {
+        self.machines[id].push_input(value);
+    }
+
+    /// The most recent output of any machine, and which machine produced it.
+    pub fn last_output(&self) -> Option<(MachineId, Value)> {
+        self.last_output
+    }
+
+    pub fn undelivered_packets(&self) -> &[Packet] {
+        &self.undelivered
+    }
+
+    pub fn take_undelivered_packets(&mut self) -> Vec<Packet> {
+        std::mem::take(&mut self.undelivered)
+    }
+
+    /// Runs the machines in turn until all of them are done, or none of them can make progress.
+    pub fn run(&mut self) -> Result<NetworkState, NetworkError> {
+        loop {
+            let mut progressed = false;
+            for id in 0..self.machines.len() {
+                let machine = &mut self.machines[id];
+                let blocked = machine.state() == MachineState::Waiting && machine.pending_input().is_empty();
+                if machine.state() == MachineState::Done || blocked {
+                    continue;
+                }
+                let previous_outputs = machine.get_outputs().len();
+                machine.compute().map_err(|error| NetworkError { machine: id, error })?;
+                progressed = true;
+                if let Some(&value) = machine.get_outputs()[previous_outputs..].last() {
+                    self.last_output = Some((id, value));
+                }
+                if !self.links[id].is_empty() {
+                    let outputs = self.machines[id].get_outputs_and_clear();
+                    self.deliver(id, &outputs);
+                }
+            }
+            if self.machines.iter().all(|machine| machine.state() == MachineState::Done) {
+                return Ok(NetworkState::Done);
+            }
+            if !progressed {
+                let waiting = (0..self.machines.len())
+                    .filter(|&id| self.machines[id].state() != MachineState::Done)
+                    .collect();
+                return Ok(NetworkState::Idle { waiting });
+            }
+        }
+    }
+
+    fn deliver(&mut self, source: MachineId, outputs: &[Value]) {
+        for link in self.links[source].clone() {
+            match link {
+                Link::Direct(target) => for &value in outputs {
+                    self.machines[target].push_input(value);
+                },
+                Link::Addressed { payload_size } => for &value in outputs {
+                    self.partial_packets[source].push(value);
+                    if self.partial_packets[source].len() == payload_size + 1 {
+                        let mut payload = std::mem::take(&mut self.partial_packets[source]);
+                        let destination = payload.remove(0);
+                        self.send_packet(Packet { source, destination, payload });
+                    }
+                },
+            }
+        }
+    }
+
+    fn send_packet(&mut self, packet: Packet) {
+        match self.machines.get_mut(packet.destination as usize) {
+            Some(machine) if packet.destination >= 0 => for &value in &packet.payload {
+                machine.push_input(value);
+            },
+            _ => self.undelivered.push(packet),
+        }
+    }
+}
+
+#[cfg(test)]
+fn machine(program: &str) -> IntcodeMachine {
+    IntcodeMachine::from_string(program).unwrap()
+}
+
+/// Reads a value, outputs twice the value and halts.
+#[cfg(test)]
+const DOUBLER: &str = "3,9,1002,9,2,9,4,9,99,0";
+
+#[test]
+fn test_pipeline() {
+    let mut network = IntcodeNetwork::pipeline(vec![machine(DOUBLER), machine(DOUBLER), machine(DOUBLER)]);
+    network.push_input(0, 5);
+    assert_eq!(network.run(), Ok(NetworkState::Done));
+    assert_eq!(network.last_output(), Some((2, 40)));
+    assert_eq!(network.machine(2).get_outputs(), vec![40]);
+    assert!(network.machine(0).get_outputs().is_empty());
+}
+
+#[test]
+fn test_ring() {
+    // Day 7 feedback loop example.
+    let program = "3,26,1001,26,-4,26,3,27,1002,27,2,27,1,27,26,27,4,27,1001,28,-1,28,1005,28,6,99,0,0,5";
+    let machines = [9,8,7,6,5].iter().map(|&phase| {
+        let mut amplifier = machine(program);
+        amplifier.push_input(phase);
+        amplifier
+    }).collect();
+    let mut network = IntcodeNetwork::ring(machines);
+    network.push_input(0, 0);
+    assert_eq!(network.run(), Ok(NetworkState::Done));
+    assert_eq!(network.last_output(), Some((4, 139629729)));
+}
+
+#[test]
+fn test_broadcast() {
+    let mut network = IntcodeNetwork::new();
+    let source = network.add_machine(machine("104,7,99"));
+    let first = network.add_machine(machine(DOUBLER));
+    let second = network.add_machine(machine(DOUBLER));
+    network.broadcast(source, &[first, second]);
+    assert_eq!(network.run(), Ok(NetworkState::Done));
+    assert_eq!(network.machine(first).get_outputs(), vec![14]);
+    assert_eq!(network.machine(second).get_outputs(), vec![14]);
+}
+
+#[test]
+fn test_packet_routing() {
+    let mut network = IntcodeNetwork::new();
+    let router = network.add_machine(machine("104,2,104,10,104,1,104,20,104,9,104,30,99"));
+    network.add_machine(machine(DOUBLER));
+    network.add_machine(machine(DOUBLER));
+    network.route_packets(router, 1);
+    assert_eq!(network.run(), Ok(NetworkState::Done));
+    assert_eq!(network.machine(1).get_outputs(), vec![40]);
+    assert_eq!(network.machine(2).get_outputs(), vec![20]);
+    assert_eq!(network.undelivered_packets(), &[Packet { source: router, destination: 9, payload: vec![30] }]);
+}
+
+#[test]
+fn test_deadlock() {
+    let echo = "3,5,4,5,99,0";
+    let mut network = IntcodeNetwork::ring(vec![machine(echo), machine(echo)]);
+    assert_eq!(network.run(), Ok(NetworkState::Idle { waiting: vec![0, 1] }));
+    network.push_input(1, 3);
+    assert_eq!(network.run(), Ok(NetworkState::Done));
+    assert_eq!(network.last_output(), Some((0, 3)));
+}
+
+#[test]
+fn test_machine_error() {
+    let mut network = IntcodeNetwork::pipeline(vec![machine("104,1,99"), machine("3,0,42")]);
+    assert_eq!(network.run(), Err(NetworkError { machine: 1, error: IntcodeError::UnknownOpcode { opcode: 42, ip: 2 } }));
+}
@@ -0,0 +1,130 @@
+use std::convert::TryFrom;
+use std::fmt::{Debug, Display};
+use num::{BigInt, ToPrimitive, Zero};
+use crate::Address;
+
+/// The values an `IntcodeMachine` computes with. Arithmetic is checked, so that overflows are reported as
+/// errors instead of wrapping around.
+pub trait Number: Clone + Debug + Display + PartialEq + PartialOrd + Send + Sync + 'static {
+    fn from_i64(value: i64) -> Self;
+    fn from_address(address: Address) -> Self;
+    /// `None` if the value is negative or too large.
+    fn to_address(&self) -> Option<Address>;
+    fn to_i64(&self) -> Option<i64>;
+    fn checked_add(&self, other: &Self) -> Option<Self>;
+    fn checked_mul(&self, other: &Self) -> Option<Self>;
+
+    fn is_zero(&self) -> bool {
+        *self == Self::from_i64(0)
+    }
+
+    fn is_negative(&self) -> bool {
+        *self < Self::from_i64(0)
+    }
+}
+
+impl Number for i64 {
+    #[inline]
+    fn from_i64(value: i64) -> Self {
+        value
+    }
+
+    fn from_address(address: Address) -> Self {
+        address as i64
+    }
+
+    #[inline]
+    fn to_address(&self) -> Option<Address> {
+        Address::try_from(*self).ok()
+    }
+
+    #[inline]
+    fn to_i64(&self) -> Option<i64> {
+        Some(*self)
+    }
+
+    #[inline]
+    fn checked_add(&self, other: &Self) -> Option<Self> {
+        i64::checked_add(*self, *other)
+    }
+
+    #[inline]
+    fn checked_mul(&self, other: &Self) -> Option<Self> {
+        i64::checked_mul(*self, *other)
+    }
+}
+
+impl Number for i128 {
+    fn from_i64(value: i64) -> Self {
+        value.into()
+    }
+
+    fn from_address(address: Address) -> Self {
+        address.into()
+    }
+
+    fn to_address(&self) -> Option<Address> {
+        Address::try_from(*self).ok()
+    }
+
+    fn to_i64(&self) -> Option<i64> {
+        i64::try_from(*self).ok()
+    }
+
+    fn checked_add(&self, other: &Self) -> Option<Self> {
+        i128::checked_add(*self, *other)
+    }
+
+    fn checked_mul(&self, other: &Self) -> Option<Self> {
+        i128::checked_mul(*self, *other)
+    }
+}
+
+/// Never overflows, but is a lot slower than the fixed size integers.
+impl Number for BigInt {
+    fn from_i64(value: i64) -> Self {
+        value.into()
+    }
+
+    fn from_address(address: Address) -> Self {
+        address.into()
+    }
+
+    fn to_address(&self) -> Option<Address> {
+        self.to_u64()
+    }
+
+    fn to_i64(&self) -> Option<i64> {
+        ToPrimitive::to_i64(self)
+    }
+
+    fn checked_add(&self, other: &Self) -> Option<Self> {
+        Some(self + other)
+    }
+
+    fn checked_mul(&self, other: &Self) -> Option<Self> {
+        Some(self * other)
+    }
+
+    fn is_zero(&self) -> bool {
+        Zero::is_zero(self)
+    }
+}
+
+#[test]
+fn test_checked_arithmetic() {
+    assert_eq!(Number::checked_add(&i64::MAX, &1), None);
+    assert_eq!(Number::checked_mul(&(i64::MAX as i128), &2), Some(2 * i64::MAX as i128));
+    let big = BigInt::from_i64(i64::MAX);
+    assert_eq!(Number::checked_mul(&big, &big).unwrap().to_string(), "85070591730234615847396907784232501249");
+}
+
+#[test]
+fn test_conversions() {
+    assert_eq!((-1_i64).to_address(), None);
+    assert_eq!(<i128 as Number>::from_address(u64::MAX).to_address(), Some(u64::MAX));
+    assert_eq!(Number::to_i64(&(1_i128 << 70)), None);
+    assert_eq!(BigInt::from_i64(-3).to_address(), None);
+    assert!(BigInt::from_i64(-3).is_negative());
+    assert!(Number::is_zero(&BigInt::from_i64(0)));
+}
@@ -0,0 +1,218 @@
+use std::collections::{BTreeSet, HashSet};
+use serde::{Deserialize, Serialize};
+use crate::{Address, IntcodeError, IntcodeMachine, Memory};
+
+/// Checks on the memory accesses of instructions. The image, i.e. the addresses the program was loaded into,
+/// counts as initialized; everything after it is scratch memory. Writes from outside of the program, like
+/// `IntcodeMachine::set_memory`, aren't checked, so the host can still patch the image.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Serialize, Deserialize)]
+pub struct MemoryPolicy {
+    /// Reading scratch memory that hasn't been written to is an error, instead of reading zero.
+    pub strict_reads: bool,
+    /// Writing into the image is an error.
+    pub read_only_image: bool,
+    /// Records which instructions access scratch memory, see `IntcodeMachine::audit_log`.
+    pub audit: bool,
+}
+
+#[derive(Clone, Copy, Debug, Eq, Hash, Ord, PartialEq, PartialOrd, Serialize, Deserialize)]
+pub enum AccessKind {
+    Read,
+    Write,
+}
+
+#[derive(Clone, Copy, Debug, Eq, Hash, Ord, PartialEq, PartialOrd, Serialize, Deserialize)]
+pub struct MemoryAccess {
+    pub address: Address,
+    pub kind: AccessKind,
+    /// The instruction that accessed the address.
+    pub ip: Address,
+}
+
+/// A policy together with what it has tracked so far, as stored in snapshots.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct PolicyState {
+    pub policy: MemoryPolicy,
+    pub image_end: Address,
+    /// The scratch addresses written to, in order. Only tracked with `strict_reads`.
+    pub initialized: Vec<Address>,
+    pub audit_log: Vec<MemoryAccess>,
+}
+
+#[derive(Clone, Debug)]
+pub(crate) struct AccessControl {
+    policy: MemoryPolicy,
+    image_end: Address,
+    initialized: HashSet<Address>,
+    audit_log: BTreeSet<MemoryAccess>,
+}
+
+impl AccessControl {
+    pub(crate) fn read<V>(&mut self, ip: Address, address: Address) -> Result<(), IntcodeError<V>> {
+        if address < self.image_end {
+            return Ok(());
+        }
+        if self.policy.strict_reads && !self.initialized.contains(&address) {
+            return Err(IntcodeError::UninitializedRead { address, ip });
+        }
+        self.log(MemoryAccess { address, kind: AccessKind::Read, ip });
+        Ok(())
+    }
+
+    pub(crate) fn write<V>(&mut self, ip: Address, address: Address) -> Result<(), IntcodeError<V>> {
+        if address < self.image_end {
+            return if self.policy.read_only_image { Err(IntcodeError::ReadOnlyWrite { address, ip }) } else { Ok(()) };
+        }
+        self.log(MemoryAccess { address, kind: AccessKind::Write, ip });
+        Ok(())
+    }
+
+    /// Called for every write, also those from outside of the program.
+    pub(crate) fn initialize(&mut self, address: Address) {
+        if self.policy.strict_reads && address >= self.image_end {
+            self.initialized.insert(address);
+        }
+    }
+
+    pub(crate) fn state(&self) -> PolicyState {
+        let mut initialized: Vec<Address> = self.initialized.iter().copied().collect();
+        initialized.sort_unstable();
+        PolicyState {
+            policy: self.policy,
+            image_end: self.image_end,
+            initialized,
+            audit_log: self.audit_log.iter().copied().collect(),
+        }
+    }
+
+    pub(crate) fn from_state(state: PolicyState) -> Self {
+        Self {
+            policy: state.policy,
+            image_end: state.image_end,
+            initialized: state.initialized.into_iter().collect(),
+            audit_log: state.audit_log.into_iter().collect(),
+        }
+    }
+
+    /// Whether reading the address is allowed.
+    pub(crate) fn is_initialized(&self, address: Address) -> bool {
+        !self.policy.strict_reads || address < self.image_end || self.initialized.contains(&address)
+    }
+
+    /// Undoes `initialize`, for stepping back over the first write to an address.
+    pub(crate) fn uninitialize(&mut self, address: Address) {
+        self.initialized.remove(&address);
+    }
+
+    fn log(&mut self, access: MemoryAccess) {
+        if self.policy.audit {
+            self.audit_log.insert(access);
+        }
+    }
+}
+
+impl<M: Memory> IntcodeMachine<M> {
+    /// Applies the policy to the instructions executed from now on, so set it before running the program. The
+    /// image is the program the machine was created with, cells patched in beyond it are scratch memory.
+    pub fn set_memory_policy(&mut self, policy: MemoryPolicy) {
+        self.access = if policy == MemoryPolicy::default() {
+            None
+        } else {
+            Some(Box::new(AccessControl { policy, image_end: self.image_end, initialized: HashSet::new(), audit_log: BTreeSet::new() }))
+        };
+    }
+
+    pub fn memory_policy(&self) -> MemoryPolicy {
+        self.access.as_ref().map_or_else(MemoryPolicy::default, |access| access.policy)
+    }
+
+    /// The accesses to scratch memory so far, ordered by address, if the policy audits them. Every instruction
+    /// is listed once per address and kind of access, however often it executes.
+    pub fn audit_log(&self) -> Vec<MemoryAccess> {
+        self.access.as_ref().map_or_else(Vec::new, |access| access.audit_log.iter().copied().collect())
+    }
+}
+
+#[test]
+fn test_strict_reads() {
+    let mut machine = IntcodeMachine::from_string("1,100,0,0,99").unwrap();
+    machine.set_memory_policy(MemoryPolicy { strict_reads: true, ..MemoryPolicy::default() });
+    assert_eq!(machine.compute(), Err(IntcodeError::UninitializedRead { address: 100, ip: 0 }));
+    assert_eq!(machine.ip(), 0);
+
+    // A patch beyond the program doesn't make the cells before it part of the image.
+    let mut machine = IntcodeMachine::from_string("1,100,0,0,99").unwrap();
+    machine.set_memory(crate::ParamMode::Position, 5000, 0).unwrap();
+    machine.set_memory_policy(MemoryPolicy { strict_reads: true, ..MemoryPolicy::default() });
+    assert_eq!(machine.compute(), Err(IntcodeError::UninitializedRead { address: 100, ip: 0 }));
+
+    // Writes 3 to [100] before reading it twice.
+    let mut machine = IntcodeMachine::from_string("1101,1,2,100,1,100,100,0,99").unwrap();
+    machine.set_memory_policy(MemoryPolicy { strict_reads: true, ..MemoryPolicy::default() });
+    assert_eq!(machine.compute(), Ok(crate::MachineState::Done));
+    assert_eq!(machine.get_memory(0), 6);
+}
+
+#[test]
+fn test_strict_reads_after_external_writes() {
+    let mut machine = IntcodeMachine::from_string("4,50,99").unwrap();
+    machine.set_memory_policy(MemoryPolicy { strict_reads: true, ..MemoryPolicy::default() });
+    machine.set_memory(crate::ParamMode::Position, 50, 7).unwrap();
+    assert_eq!(machine.compute(), Ok(crate::MachineState::Done));
+    assert_eq!(machine.get_outputs(), vec![7]);
+}
+
+#[test]
+fn test_read_only_image() {
+    let policy = MemoryPolicy { read_only_image: true, ..MemoryPolicy::default() };
+    let mut machine = IntcodeMachine::from_string("1101,1,2,7,1101,1,2,0,99").unwrap();
+    machine.set_memory_policy(policy);
+    assert_eq!(machine.compute(), Err(IntcodeError::ReadOnlyWrite { address: 7, ip: 0 }));
+
+    let mut machine = IntcodeMachine::from_string("3,0,99").unwrap();
+    machine.set_memory_policy(policy);
+    machine.push_input(5);
+    assert_eq!(machine.compute(), Err(IntcodeError::ReadOnlyWrite { address: 0, ip: 0 }));
+    assert_eq!(machine.pending_input().len(), 1);
+
+    let mut machine = IntcodeMachine::from_string("1101,1,2,9,99").unwrap();
+    machine.set_memory_policy(policy);
+    assert_eq!(machine.compute(), Ok(crate::MachineState::Done));
+
+    // Writes to [100], beyond the program even though a patch beyond that came first.
+    let mut machine = IntcodeMachine::from_string("1101,1,2,100,4,100,99").unwrap();
+    machine.set_memory(crate::ParamMode::Position, 200, 0).unwrap();
+    machine.set_memory_policy(policy);
+    assert_eq!(machine.compute(), Ok(crate::MachineState::Done));
+    assert_eq!(machine.get_outputs(), vec![3]);
+
+    let mut machine = IntcodeMachine::from_string("4,3,99,0").unwrap();
+    machine.set_memory_policy(policy);
+    machine.set_memory(crate::ParamMode::Position, 3, 7).unwrap();
+    assert_eq!(machine.compute(), Ok(crate::MachineState::Done));
+    assert_eq!(machine.get_outputs(), vec![7]);
+}
+
+#[test]
+fn test_audit_log() {
+    // Sets the relative base to 50, writes to rb+0, but outputs rb+1.
+    let mut machine = IntcodeMachine::from_string("109,50,21101,1,2,0,204,1,99").unwrap();
+    machine.set_memory_policy(MemoryPolicy { audit: true, ..MemoryPolicy::default() });
+    assert_eq!(machine.compute(), Ok(crate::MachineState::Done));
+    assert_eq!(machine.get_outputs(), vec![0]);
+    assert_eq!(machine.audit_log(), vec![
+        MemoryAccess { address: 50, kind: AccessKind::Write, ip: 2 },
+        MemoryAccess { address: 51, kind: AccessKind::Read, ip: 6 },
+    ]);
+    assert_eq!(machine.memory_policy(), MemoryPolicy { audit: true, ..MemoryPolicy::default() });
+}
+
+#[test]
+fn test_audit_log_is_deduplicated() {
+    // Counts [100] up to 10.
+    let mut machine = IntcodeMachine::from_string("1101,0,0,100,1001,100,1,100,1007,100,10,101,1005,101,4,4,100,99").unwrap();
+    machine.set_memory_policy(MemoryPolicy { audit: true, strict_reads: true, ..MemoryPolicy::default() });
+    assert_eq!(machine.compute(), Ok(crate::MachineState::Done));
+    assert_eq!(machine.get_outputs(), vec![10]);
+    assert_eq!(machine.audit_log().len(), 7);
+}
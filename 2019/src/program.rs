@@ -0,0 +1,132 @@
+use std::convert::TryFrom;
+use std::error::Error;
+use std::fmt;
+use std::ops::Deref;
+use std::str::FromStr;
+use itertools::Itertools;
+use crate::Value;
+
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Program(Vec<Value>);
+
+impl Program {
+    pub fn new(values: Vec<Value>) -> Self {
+        Program(values)
+    }
+
+    pub fn into_vec(self) -> Vec<Value> {
+        self.0
+    }
+}
+
+impl Deref for Program {
+    type Target = [Value];
+
+    fn deref(&self) -> &[Value] {
+        &self.0
+    }
+}
+
+impl From<Vec<Value>> for Program {
+    fn from(values: Vec<Value>) -> Self {
+        Program(values)
+    }
+}
+
+impl fmt::Display for Program {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.0.iter().join(","))
+    }
+}
+
+/// Parses comma-separated values. Line breaks separate values as well, `#` starts a comment that runs until
+/// the end of the line, and a trailing comma at the end of a line is ignored.
+impl FromStr for Program {
+    type Err = ParseError;
+
+    fn from_str(input: &str) -> Result<Self, ParseError> {
+        let mut values = vec![];
+        for (line_idx, line) in input.lines().enumerate() {
+            let code = match line.find('#') {
+                Some(pos) => &line[..pos],
+                None => line,
+            };
+            let tokens: Vec<&str> = code.split(',').collect();
+            let mut column = 1;
+            for (token_idx, raw) in tokens.iter().enumerate() {
+                let token = raw.trim();
+                let is_last = token_idx == tokens.len() - 1;
+                if !(token.is_empty() && is_last) {
+                    let leading = raw.len() - raw.trim_start().len();
+                    let value = token.parse::<Value>().map_err(|_| ParseError {
+                        index: values.len(),
+                        token: token.to_string(),
+                        line: line_idx + 1,
+                        column: column + raw[..leading].chars().count(),
+                    })?;
+                    values.push(value);
+                }
+                column += raw.chars().count() + 1;
+            }
+        }
+        Ok(Program(values))
+    }
+}
+
+impl TryFrom<&str> for Program {
+    type Error = ParseError;
+
+    fn try_from(input: &str) -> Result<Self, ParseError> {
+        input.parse()
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct ParseError {
+    pub index: usize,
+    pub token: String,
+    pub line: usize,
+    pub column: usize,
+}
+
+impl fmt::Display for ParseError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "invalid value #{} {:?} at line {}, column {}", self.index, self.token, self.line, self.column)
+    }
+}
+
+impl Error for ParseError {}
+
+#[test]
+fn test_parse_simple() {
+    assert_eq!("1,2,-3".parse(), Ok(Program(vec![1,2,-3])));
+}
+
+#[test]
+fn test_parse_whitespace_and_trailing_comma() {
+    assert_eq!(" 1, 2 ,3,\n\n  4,5,\n".parse(), Ok(Program(vec![1,2,3,4,5])));
+}
+
+#[test]
+fn test_parse_comments() {
+    assert_eq!(Program::try_from("# header\n1,2, # first two\n99 # halt"), Ok(Program(vec![1,2,99])));
+}
+
+#[test]
+fn test_parse_error_position() {
+    assert_eq!("1,2,\n3, x4 ,5".parse::<Program>(), Err(ParseError {
+        index: 3, token: "x4".to_string(), line: 2, column: 4,
+    }));
+}
+
+#[test]
+fn test_parse_empty_token() {
+    assert_eq!("1,,2".parse::<Program>(), Err(ParseError {
+        index: 1, token: "".to_string(), line: 1, column: 3,
+    }));
+}
+
+#[test]
+fn test_display() {
+    assert_eq!(Program(vec![1,0,-1,99]).to_string(), "1,0,-1,99");
+}
@@ -0,0 +1,417 @@
+use std::collections::VecDeque;
+use std::mem;
+use std::panic;
+use std::sync::{Arc, Mutex};
+use std::sync::mpsc::{self, Receiver, RecvTimeoutError, Sender, SyncSender, TrySendError};
+use std::thread::{self, JoinHandle};
+use std::time::Duration;
+use crate::{InputSource, IntcodeError, IntcodeMachine, MachineState, Memory, OutputSink, Value, VecMemory};
+use crate::network::{MachineId, NetworkError};
+
+/// How long a machine waits for input before checking whether the network should stop.
+const POLL_INTERVAL: Duration = Duration::from_millis(10);
+/// How long a machine waits before retrying to send to a full channel.
+const SEND_RETRY_INTERVAL: Duration = Duration::from_micros(50);
+/// How many instructions a machine executes between checking whether the network should stop.
+const STEPS_BETWEEN_STOP_CHECKS: u64 = 100_000;
+const DEFAULT_CHANNEL_CAPACITY: usize = 64;
+
+#[derive(Default)]
+struct Status {
+    alive: bool,
+    waiting: bool,
+    /// Values sent to the machine that it hasn't received yet.
+    queued: usize,
+}
+
+#[derive(Default)]
+struct Shared {
+    /// No more input will be sent from outside the network.
+    closed: bool,
+    stop: bool,
+    error: Option<NetworkError>,
+    machines: Vec<Status>,
+}
+
+impl Shared {
+    fn deadlocked(&self) -> bool {
+        self.closed && self.machines.iter().filter(|status| status.alive).all(|status| status.waiting && status.queued == 0)
+    }
+}
+
+type SharedState = Arc<Mutex<Shared>>;
+
+fn lock(shared: &SharedState) -> std::sync::MutexGuard<'_, Shared> {
+    shared.lock().unwrap_or_else(|poisoned| poisoned.into_inner())
+}
+
+/// Sends a value to a machine's channel. Returns whether the machine is still there to receive it.
+fn send(shared: &SharedState, id: MachineId, sender: &SyncSender<Value>, mut value: Value) -> Result<bool, IntcodeError> {
+    lock(shared).machines[id].queued += 1;
+    loop {
+        match sender.try_send(value) {
+            Ok(()) => return Ok(true),
+            Err(TrySendError::Full(unsent)) => {
+                if lock(shared).stop {
+                    lock(shared).machines[id].queued -= 1;
+                    return Err(IntcodeError::OutputClosed);
+                }
+                value = unsent;
+                thread::sleep(SEND_RETRY_INTERVAL);
+            },
+            Err(TrySendError::Disconnected(_)) => {
+                lock(shared).machines[id].queued -= 1;
+                return Ok(false);
+            },
+        }
+    }
+}
+
+struct ChannelInput {
+    id: MachineId,
+    /// Input that was pushed to the machine before the network started.
+    pending: VecDeque<Value>,
+    receiver: Receiver<Value>,
+    shared: SharedState,
+}
+
+impl InputSource for ChannelInput {
+    fn read_input(&mut self) -> Option<Value> {
+        if let Some(value) = self.pending.pop_front() {
+            return Some(value);
+        }
+        lock(&self.shared).machines[self.id].waiting = true;
+        loop {
+            let result = self.receiver.recv_timeout(POLL_INTERVAL);
+            let mut shared = lock(&self.shared);
+            match result {
+                Ok(value) => {
+                    shared.machines[self.id].waiting = false;
+                    shared.machines[self.id].queued -= 1;
+                    return Some(value);
+                },
+                Err(RecvTimeoutError::Timeout) => {
+                    if shared.deadlocked() {
+                        shared.stop = true;
+                    }
+                    if shared.stop {
+                        return None;
+                    }
+                },
+                Err(RecvTimeoutError::Disconnected) => return None,
+            }
+        }
+    }
+}
+
+struct ChannelOutput {
+    targets: Vec<(MachineId, SyncSender<Value>)>,
+    taps: Vec<Sender<Value>>,
+    outputs: Vec<Value>,
+    shared: SharedState,
+}
+
+impl OutputSink for ChannelOutput {
+    fn write_output(&mut self, value: Value) -> Result<(), IntcodeError> {
+        self.outputs.push(value);
+        self.taps.retain(|tap| tap.send(value).is_ok());
+        let mut connected = vec![];
+        for (id, sender) in mem::take(&mut self.targets) {
+            if send(&self.shared, id, &sender, value)? {
+                connected.push((id, sender));
+            }
+        }
+        // Machines that are done don't need any more values.
+        self.targets = connected;
+        Ok(())
+    }
+}
+
+fn run_machine<M: Memory<Value = Value>>(mut machine: IntcodeMachine<M>, mut input: ChannelInput, mut output: ChannelOutput) -> IntcodeMachine<M> {
+    let result = loop {
+        match machine.compute_with_budget_with(&mut input, &mut output, STEPS_BETWEEN_STOP_CHECKS) {
+            Ok(MachineState::BudgetExhausted) if !lock(&input.shared).stop => continue,
+            result => break result,
+        }
+    };
+    machine.input = mem::take(&mut input.pending);
+    machine.output.append(&mut output.outputs);
+    let mut shared = lock(&input.shared);
+    shared.machines[input.id].alive = false;
+    match result {
+        // Only happens when the network is stopping anyway.
+        Err(IntcodeError::OutputClosed) => (),
+        Err(error) => {
+            shared.stop = true;
+            shared.error.get_or_insert(NetworkError { machine: input.id, error });
+        },
+        Ok(_) => (),
+    }
+    machine
+}
+
+/// Machines that each run on their own thread, connected by bounded channels.
+pub struct ThreadedNetwork<M: Memory = VecMemory> {
+    machines: Vec<IntcodeMachine<M>>,
+    links: Vec<Vec<MachineId>>,
+    taps: Vec<Vec<Sender<Value>>>,
+    channel_capacity: usize,
+}
+
+impl<M: Memory<Value = Value>> Default for ThreadedNetwork<M> {
+    fn default() -> Self {
+        Self { machines: vec![], links: vec![], taps: vec![], channel_capacity: DEFAULT_CHANNEL_CAPACITY }
+    }
+}
+
+impl<M: Memory<Value = Value> + Send + 'static> ThreadedNetwork<M> {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn pipeline(machines: Vec<IntcodeMachine<M>>) -> Self {
+        let mut network = Self::new();
+        for machine in machines {
+            let id = network.add_machine(machine);
+            if id > 0 {
+                network.connect(id - 1, id);
+            }
+        }
+        network
+    }
+
+    pub fn ring(machines: Vec<IntcodeMachine<M>>) -> Self {
+        let mut network = Self::pipeline(machines);
+        if network.machines.len() > 1 {
+            network.connect(network.machines.len() - 1, 0);
+        }
+        network
+    }
+
+    /// How many values a channel holds before the sending machine blocks.
+    pub fn set_channel_capacity(&mut self, capacity: usize) {
+        self.channel_capacity = capacity;
+    }
+
+    pub fn add_machine(&mut self, machine: IntcodeMachine<M>) -> MachineId {
+        self.machines.push(machine);
+        self.links.push(vec![]);
+        self.taps.push(vec![]);
+        self.machines.len() - 1
+    }
+
+    /// Sends every output of `from` to `to`. Connecting a machine to several others broadcasts its outputs.
+    pub fn connect(&mut self, from: MachineId, to: MachineId) {
+        assert!(to < self.machines.len(), "no machine with id {}", to);
+        self.links[from].push(to);
+    }
+
+    pub fn broadcast(&mut self, from: MachineId, to: &[MachineId]) {
+        for &target in to {
+            self.connect(from, target);
+        }
+    }
+
+    /// Returns a receiver for the outputs of a machine while the network is running. Unlike the channels between
+    /// machines, it never blocks the machine.
+    pub fn tap(&mut self, id: MachineId) -> Receiver<Value> {
+        let (sender, receiver) = mpsc::channel();
+        self.taps[id].push(sender);
+        receiver
+    }
+
+    pub fn start(self) -> RunningNetwork<M> {
+        let count = self.machines.len();
+        let shared: SharedState = Arc::new(Mutex::new(Shared::default()));
+        lock(&shared).machines = (0..count).map(|_| Status { alive: true, ..Status::default() }).collect();
+        let (senders, receivers): (Vec<SyncSender<Value>>, Vec<Receiver<Value>>) =
+            (0..count).map(|_| mpsc::sync_channel(self.channel_capacity)).unzip();
+
+        let mut handles = vec![];
+        let parts = self.machines.into_iter().zip(self.links).zip(self.taps).zip(receivers);
+        for (id, (((mut machine, links), taps), receiver)) in parts.enumerate() {
+            let input = ChannelInput { id, pending: mem::take(&mut machine.input), receiver, shared: shared.clone() };
+            let targets = links.into_iter().map(|target| (target, senders[target].clone())).collect();
+            let output = ChannelOutput { targets, taps, outputs: vec![], shared: shared.clone() };
+            handles.push(thread::spawn(move || run_machine(machine, input, output)));
+        }
+        RunningNetwork { senders, handles, shared }
+    }
+}
+
+pub struct RunningNetwork<M: Memory = VecMemory> {
+    senders: Vec<SyncSender<Value>>,
+    handles: Vec<JoinHandle<IntcodeMachine<M>>>,
+    shared: SharedState,
+}
+
+impl<M: Memory<Value = Value>> RunningNetwork<M> {
+    /// Sends input to a machine, blocking while its channel is full. Fails if the machine has stopped.
+    pub fn send(&self, id: MachineId, value: Value) -> Result<(), IntcodeError> {
+        match send(&self.shared, id, &self.senders[id], value)? {
+            true => Ok(()),
+            false => Err(IntcodeError::OutputClosed),
+        }
+    }
+
+    /// Makes all machines stop as soon as they wait for input or have executed a few more instructions.
+    pub fn shutdown(&self) {
+        lock(&self.shared).stop = true;
+    }
+
+    /// Waits until every machine has halted, or the network is stuck because all remaining machines wait for
+    /// input. Stuck machines are left in the `Waiting` state. If any machine fails, the others are stopped and
+    /// the first error is returned.
+    pub fn join(mut self) -> Result<Vec<IntcodeMachine<M>>, NetworkError> {
+        self.senders.clear();
+        lock(&self.shared).closed = true;
+        let machines = mem::take(&mut self.handles).into_iter()
+            .map(|handle| handle.join().unwrap_or_else(|payload| panic::resume_unwind(payload)))
+            .collect();
+        match lock(&self.shared).error.take() {
+            Some(error) => Err(error),
+            None => Ok(machines),
+        }
+    }
+}
+
+/// Dropping a network without joining it stops the machines and waits for their threads to finish.
+impl<M: Memory> Drop for RunningNetwork<M> {
+    fn drop(&mut self) {
+        if self.handles.is_empty() {
+            return;
+        }
+        self.senders.clear();
+        {
+            let mut shared = lock(&self.shared);
+            shared.closed = true;
+            shared.stop = true;
+        }
+        for handle in self.handles.drain(..) {
+            // A machine's panic can't be propagated from here, the network is discarded anyway.
+            let _ = handle.join();
+        }
+    }
+}
+
+#[cfg(test)]
+fn machine(source: &str) -> IntcodeMachine {
+    IntcodeMachine::from_program(&crate::asm::assemble(source).unwrap())
+}
+
+#[cfg(test)]
+const DOUBLER: &str = "
+        in [value]
+        mul [value], #2, [value]
+        out [value]
+        hlt
+value:  data 0
+";
+
+#[test]
+fn test_pipeline() {
+    let mut first = machine(DOUBLER);
+    first.push_input(5);
+    let machines = ThreadedNetwork::pipeline(vec![first, machine(DOUBLER), machine(DOUBLER)]).start().join().unwrap();
+    assert_eq!(machines[2].get_outputs(), vec![40]);
+    assert!(machines.iter().all(|machine| machine.state() == MachineState::Done));
+}
+
+#[test]
+fn test_ring() {
+    // Day 7 feedback loop example.
+    let program = "3,26,1001,26,-4,26,3,27,1002,27,2,27,1,27,26,27,4,27,1001,28,-1,28,1005,28,6,99,0,0,5";
+    let machines = [9,8,7,6,5].iter().map(|&phase| {
+        let mut amplifier = IntcodeMachine::from_string(program).unwrap();
+        amplifier.push_input(phase);
+        amplifier
+    }).collect();
+    let network = ThreadedNetwork::ring(machines).start();
+    network.send(0, 0).unwrap();
+    let machines = network.join().unwrap();
+    assert_eq!(machines[4].get_outputs().last(), Some(&139629729));
+}
+
+#[test]
+fn test_backpressure() {
+    let producer = machine("
+        loop:   add [count], #1, [count]
+                out [count]
+                lt [count], #100, [more]
+                jt [more], #loop
+                hlt
+        count:  data 0
+        more:   data 0
+    ");
+    let consumer = machine("
+        loop:   in [value]
+                add [sum], [value], [sum]
+                add [count], #1, [count]
+                lt [count], #100, [more]
+                jt [more], #loop
+                out [sum]
+                hlt
+        value:  data 0
+        sum:    data 0
+        count:  data 0
+        more:   data 0
+    ");
+    let mut network = ThreadedNetwork::pipeline(vec![producer, consumer]);
+    network.set_channel_capacity(1);
+    let sums = network.tap(1);
+    let machines = network.start().join().unwrap();
+    assert_eq!(machines[0].get_outputs().len(), 100);
+    assert_eq!(sums.recv(), Ok(5050));
+}
+
+#[test]
+fn test_external_input_and_tap() {
+    let mut network = ThreadedNetwork::new();
+    let id = network.add_machine(machine(DOUBLER));
+    let outputs = network.tap(id);
+    let network = network.start();
+    network.send(id, 21).unwrap();
+    assert_eq!(outputs.recv(), Ok(42));
+    network.join().unwrap();
+}
+
+#[test]
+fn test_deadlock_stops() {
+    let echo = "
+        in [value]
+        out [value]
+        hlt
+value:  data 0
+";
+    let machines = ThreadedNetwork::ring(vec![machine(echo), machine(echo)]).start().join().unwrap();
+    assert!(machines.iter().all(|machine| machine.state() == MachineState::Waiting));
+}
+
+#[test]
+fn test_error_stops_network() {
+    let mut network = ThreadedNetwork::new();
+    network.add_machine(machine("loop: jt #1, #loop"));
+    network.add_machine(IntcodeMachine::from_string("1,0,0,0,42").unwrap());
+    assert_eq!(network.start().join().err(), Some(NetworkError {
+        machine: 1, error: IntcodeError::UnknownOpcode { opcode: 42, ip: 4 },
+    }));
+}
+
+#[test]
+fn test_shutdown() {
+    let network = ThreadedNetwork::pipeline(vec![machine("loop: jt #1, #loop")]).start();
+    network.shutdown();
+    let machines = network.join().unwrap();
+    assert_eq!(machines[0].state(), MachineState::BudgetExhausted);
+}
+
+#[test]
+fn test_drop_stops_machines() {
+    let mut network = ThreadedNetwork::new();
+    network.add_machine(machine("loop: jt #1, #loop"));
+    let id = network.add_machine(machine(DOUBLER));
+    let outputs = network.tap(id);
+    drop(network.start());
+    // The machine's thread has finished, so its end of the tap is gone.
+    assert_eq!(outputs.recv_timeout(Duration::from_secs(10)), Err(RecvTimeoutError::Disconnected));
+}
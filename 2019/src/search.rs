@@ -0,0 +1,90 @@
+use std::ops::Range;
+use std::sync::Mutex;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::thread;
+use itertools::Itertools;
+use crate::{Address, DEFAULT_MEMORY_LIMIT, IntcodeMachine, MachineState, Memory, Value};
+
+/// How many candidates a worker takes at once.
+const BATCH_SIZE: usize = 64;
+
+/// Patches every combination of the given values into the memory of copies of `template`, runs them, and returns
+/// the first combination (in the order of the ranges, the first cell varying slowest) that leaves `target` at
+/// `address`. Combinations that fail, wait for input or run for more than `max_steps` instructions don't match.
+/// Without a memory limit on `template`, the copies get `DEFAULT_MEMORY_LIMIT`, so that runaway candidates fail
+/// instead of exhausting memory.
+pub fn search_inputs<M: Memory<Value = Value> + Send + Sync>(template: &IntcodeMachine<M>, cells: &[(Address, Range<Value>)], address: Address, target: Value, max_steps: u64) -> Option<Vec<Value>> {
+    let matches = |values: &[Value]| {
+        let mut machine = template.clone();
+        machine.memory_limit = machine.memory_limit.or(Some(DEFAULT_MEMORY_LIMIT));
+        for ((cell, _), value) in cells.iter().zip(values) {
+            machine.memory.set(*cell, *value);
+        }
+        machine.compute_with_budget(max_steps) == Ok(MachineState::Done) && machine.get_memory(address) == target
+    };
+    if cells.is_empty() {
+        return if matches(&[]) { Some(vec![]) } else { None };
+    }
+
+    let candidates = Mutex::new(cells.iter().map(|(_, range)| range.clone()).multi_cartesian_product().fuse().enumerate());
+    let first_match = AtomicUsize::new(usize::MAX);
+    let found = Mutex::new(None);
+    let threads = thread::available_parallelism().map_or(1, |threads| threads.get());
+    thread::scope(|scope| {
+        for _ in 0..threads {
+            scope.spawn(|| loop {
+                let batch: Vec<(usize, Vec<Value>)> = candidates.lock().unwrap().by_ref().take(BATCH_SIZE).collect();
+                // Later candidates can't beat a match that has already been found.
+                match batch.first() {
+                    Some((index, _)) if *index < first_match.load(Ordering::SeqCst) => (),
+                    _ => break,
+                }
+                if let Some((index, values)) = batch.into_iter().find(|(_, values)| matches(values)) {
+                    let mut found = found.lock().unwrap();
+                    if index < first_match.load(Ordering::SeqCst) {
+                        first_match.store(index, Ordering::SeqCst);
+                        *found = Some(values);
+                    }
+                }
+            });
+        }
+    });
+    found.into_inner().unwrap()
+}
+
+#[test]
+fn test_first_match_wins() {
+    // Adds the values at the two patched addresses.
+    let template = IntcodeMachine::from_string("1,0,0,0,99").unwrap();
+    assert_eq!(search_inputs(&template, &[(1, 0..5), (2, 0..5)], 0, 100, 1000), Some(vec![0, 4]));
+    assert_eq!(search_inputs(&template, &[(1, 0..5), (2, 0..5)], 0, 1000, 1000), None);
+}
+
+#[test]
+fn test_failing_candidates_dont_match() {
+    // Opcode 0 is invalid, 3 waits for input and 5 jumps back to the start forever.
+    let template = IntcodeMachine::from_string("0,0,0,0,99").unwrap();
+    assert_eq!(search_inputs(&template, &[(0, 0..2)], 0, 0, 1000), None);
+    assert_eq!(search_inputs(&template, &[(0, 0..9)], 0, 2, 1000), Some(vec![1]));
+    let template = IntcodeMachine::from_string("1105,1,0").unwrap();
+    assert_eq!(search_inputs(&template, &[], 0, 1105, 1000), None);
+    // Writes 1 to the patched address. Without a limit of its own, the search applies the default one.
+    let mut template = IntcodeMachine::from_string("1101,0,1,0,99").unwrap();
+    template.set_memory_limit(None);
+    assert_eq!(search_inputs(&template, &[(3, (1 << 40)..(1 << 40) + 2)], 1 << 40, 1, 1000), None);
+    assert_eq!(search_inputs(&template, &[(3, 0..2)], 1, 1, 1000), Some(vec![1]));
+}
+
+#[test]
+fn test_matches_serial_search() {
+    let template = IntcodeMachine::from_string("1,0,0,3,1,1,2,3,1,3,4,3,1,5,0,3,2,1,10,19,1,19,5,23,99").unwrap();
+    let run = |noun, verb| {
+        let mut machine = template.clone();
+        machine.memory.set(1, noun);
+        machine.memory.set(2, verb);
+        machine.compute().ok().map(|_| machine.get_memory(0))
+    };
+    let target = run(17, 13).unwrap();
+    let serial = (0..20).cartesian_product(0..20).find(|&(noun, verb)| run(noun, verb) == Some(target));
+    assert_eq!(search_inputs(&template, &[(1, 0..20), (2, 0..20)], 0, target, crate::DEFAULT_STEP_BUDGET), serial.map(|(noun, verb)| vec![noun, verb]));
+}
@@ -0,0 +1,451 @@
+use std::convert::TryInto;
+use std::error::Error;
+use std::fmt;
+use serde::{Deserialize, Serialize};
+use crate::{AccessKind, Address, IntcodeMachine, MachineState, MemoryAccess, MemoryPolicy, Memory, PolicyState, Value};
+use crate::policy::AccessControl;
+
+const MAGIC: &[u8; 4] = b"ICSN";
+const FORMAT: &str = "intcode-snapshot";
+const VERSION: u32 = 2;
+/// Version 1 snapshots lack the disabled opcodes and the memory policy.
+const OLDEST_VERSION: u32 = 1;
+
+/// Everything needed to resume an `IntcodeMachine`, possibly in another process. Custom opcodes aren't
+/// included, machines using them have to be restored with the same extensions.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct Snapshot {
+    pub ip: Address,
+    pub relative_base: Address,
+    pub state: MachineState,
+    pub memory_limit: Option<Address>,
+    pub input: Vec<Value>,
+    pub output: Vec<Value>,
+    /// Runs of consecutive memory cells, as `(start address, values)`.
+    pub memory: Vec<(Address, Vec<Value>)>,
+    /// The built-in opcodes that are disabled, in ascending order.
+    #[serde(default)]
+    pub disabled_opcodes: Vec<u8>,
+    #[serde(default)]
+    pub memory_policy: Option<PolicyState>,
+}
+
+#[derive(Serialize, Deserialize)]
+struct JsonSnapshot {
+    format: String,
+    version: u32,
+    machine: Snapshot,
+}
+
+#[derive(Debug)]
+pub enum SnapshotError {
+    InvalidHeader,
+    UnsupportedVersion(u32),
+    Truncated,
+    InvalidState(u8),
+    /// A run of memory cells overlaps the previous one, or extends beyond the largest address.
+    InvalidSegment { start: Address, len: usize },
+    InvalidOpcode(u8),
+    /// An unknown memory policy or kind of memory access.
+    InvalidPolicy(u8),
+    TrailingData,
+    Json(serde_json::Error),
+}
+
+impl fmt::Display for SnapshotError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Self::InvalidHeader => write!(f, "not an Intcode snapshot"),
+            Self::UnsupportedVersion(version) => write!(f, "unsupported snapshot version {}", version),
+            Self::Truncated => write!(f, "snapshot ends unexpectedly"),
+            Self::InvalidState(state) => write!(f, "invalid machine state {}", state),
+            Self::InvalidSegment { start, len } => write!(f, "invalid memory segment of {} cells at {}", len, start),
+            Self::InvalidOpcode(opcode) => write!(f, "invalid disabled opcode {}", opcode),
+            Self::InvalidPolicy(byte) => write!(f, "invalid memory policy {}", byte),
+            Self::TrailingData => write!(f, "unexpected data after the end of the snapshot"),
+            Self::Json(error) => write!(f, "invalid JSON snapshot: {}", error),
+        }
+    }
+}
+
+impl Error for SnapshotError {}
+
+impl From<serde_json::Error> for SnapshotError {
+    fn from(error: serde_json::Error) -> Self {
+        Self::Json(error)
+    }
+}
+
+impl<M: Memory<Value = Value>> IntcodeMachine<M> {
+    pub fn snapshot(&self) -> Snapshot {
+        Snapshot {
+            ip: self.ip,
+            relative_base: self.relative_base,
+            state: self.state,
+            memory_limit: self.memory_limit,
+            input: self.input.iter().copied().collect(),
+            output: self.output.clone(),
+            memory: self.memory.segments(),
+            disabled_opcodes: (0..100).filter(|opcode| self.disabled_opcodes & (1 << opcode) != 0).collect(),
+            memory_policy: self.access.as_ref().map(|access| access.state()),
+        }
+    }
+
+    /// Fails if the memory segments aren't in address order, overlap, or run past the largest address, or if a
+    /// disabled opcode isn't a valid opcode.
+    pub fn from_snapshot(snapshot: Snapshot) -> Result<Self, SnapshotError> {
+        let mut end = 0;
+        for (start, values) in &snapshot.memory {
+            let len = values.len();
+            match start.checked_add(len as Address) {
+                Some(segment_end) if *start >= end => end = segment_end,
+                _ => return Err(SnapshotError::InvalidSegment { start: *start, len }),
+            }
+        }
+        let mut disabled_opcodes = 0;
+        for &opcode in &snapshot.disabled_opcodes {
+            if opcode >= 100 {
+                return Err(SnapshotError::InvalidOpcode(opcode));
+            }
+            disabled_opcodes |= 1 << opcode;
+        }
+        let mut segments = snapshot.memory.into_iter().peekable();
+        let memory = match segments.peek() {
+            Some((0, _)) => M::from_values(&segments.next().unwrap().1),
+            _ => M::from_values(&[]),
+        };
+        let mut machine = Self::with_memory(memory);
+        for (start, values) in segments {
+            for (offset, value) in values.into_iter().enumerate() {
+                machine.memory.set(start + offset as Address, value);
+            }
+        }
+        machine.ip = snapshot.ip;
+        machine.relative_base = snapshot.relative_base;
+        machine.state = snapshot.state;
+        machine.memory_limit = snapshot.memory_limit;
+        machine.input = snapshot.input.into();
+        machine.output = snapshot.output;
+        machine.disabled_opcodes = disabled_opcodes;
+        if let Some(state) = &snapshot.memory_policy {
+            machine.image_end = state.image_end;
+        }
+        machine.access = snapshot.memory_policy.map(|state| Box::new(AccessControl::from_state(state)));
+        Ok(machine)
+    }
+}
+
+impl Snapshot {
+    pub fn to_json(&self) -> String {
+        let json = JsonSnapshot { format: FORMAT.to_string(), version: VERSION, machine: self.clone() };
+        serde_json::to_string(&json).expect("snapshots can always be serialized")
+    }
+
+    pub fn from_json(json: &str) -> Result<Self, SnapshotError> {
+        // Look at the header first, so that other versions are reported as such instead of as invalid JSON.
+        #[derive(Deserialize)]
+        struct Header {
+            format: String,
+            version: u32,
+        }
+        let header: Header = serde_json::from_str(json).map_err(|_| SnapshotError::InvalidHeader)?;
+        if header.format != FORMAT {
+            return Err(SnapshotError::InvalidHeader);
+        }
+        if !(OLDEST_VERSION..=VERSION).contains(&header.version) {
+            return Err(SnapshotError::UnsupportedVersion(header.version));
+        }
+        Ok(serde_json::from_str::<JsonSnapshot>(json)?.machine)
+    }
+
+    /// Encodes the snapshot as a magic number and version, followed by LEB128 varints (zigzag encoded for
+    /// signed values).
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut bytes = MAGIC.to_vec();
+        write_unsigned(&mut bytes, VERSION.into());
+        write_unsigned(&mut bytes, self.ip);
+        write_unsigned(&mut bytes, self.relative_base);
+        bytes.push(state_to_byte(self.state));
+        write_unsigned(&mut bytes, self.memory_limit.map_or(0, |limit| limit + 1));
+        write_values(&mut bytes, &self.input);
+        write_values(&mut bytes, &self.output);
+        write_unsigned(&mut bytes, self.memory.len() as u64);
+        for (start, values) in &self.memory {
+            write_unsigned(&mut bytes, *start);
+            write_values(&mut bytes, values);
+        }
+        write_unsigned(&mut bytes, self.disabled_opcodes.len() as u64);
+        bytes.extend(&self.disabled_opcodes);
+        match &self.memory_policy {
+            None => bytes.push(0),
+            Some(state) => {
+                bytes.push(policy_to_byte(state.policy));
+                write_unsigned(&mut bytes, state.image_end);
+                write_unsigned(&mut bytes, state.initialized.len() as u64);
+                for &address in &state.initialized {
+                    write_unsigned(&mut bytes, address);
+                }
+                write_unsigned(&mut bytes, state.audit_log.len() as u64);
+                for access in &state.audit_log {
+                    write_unsigned(&mut bytes, access.address);
+                    bytes.push(match access.kind { AccessKind::Read => 0, AccessKind::Write => 1 });
+                    write_unsigned(&mut bytes, access.ip);
+                }
+            },
+        }
+        bytes
+    }
+
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self, SnapshotError> {
+        if !bytes.starts_with(MAGIC) {
+            return Err(SnapshotError::InvalidHeader);
+        }
+        let mut reader = Reader(&bytes[MAGIC.len()..]);
+        let version = reader.unsigned()?;
+        if version < u64::from(OLDEST_VERSION) || version > u64::from(VERSION) {
+            return Err(SnapshotError::UnsupportedVersion(version.try_into().unwrap_or(u32::MAX)));
+        }
+        let ip = reader.unsigned()?;
+        let relative_base = reader.unsigned()?;
+        let state = state_from_byte(reader.byte()?)?;
+        let memory_limit = reader.unsigned()?.checked_sub(1);
+        let input = reader.values()?;
+        let output = reader.values()?;
+        let mut memory = vec![];
+        for _ in 0..reader.unsigned()? {
+            let start = reader.unsigned()?;
+            memory.push((start, reader.values()?));
+        }
+        let mut disabled_opcodes = vec![];
+        let mut memory_policy = None;
+        if version >= 2 {
+            for _ in 0..reader.unsigned()? {
+                disabled_opcodes.push(reader.byte()?);
+            }
+            let byte = reader.byte()?;
+            if byte != 0 {
+                let policy = policy_from_byte(byte)?;
+                let image_end = reader.unsigned()?;
+                let mut initialized = vec![];
+                for _ in 0..reader.unsigned()? {
+                    initialized.push(reader.unsigned()?);
+                }
+                let mut audit_log = vec![];
+                for _ in 0..reader.unsigned()? {
+                    let address = reader.unsigned()?;
+                    let kind = match reader.byte()? {
+                        0 => AccessKind::Read,
+                        1 => AccessKind::Write,
+                        byte => return Err(SnapshotError::InvalidPolicy(byte)),
+                    };
+                    audit_log.push(MemoryAccess { address, kind, ip: reader.unsigned()? });
+                }
+                memory_policy = Some(PolicyState { policy, image_end, initialized, audit_log });
+            }
+        }
+        if !reader.0.is_empty() {
+            return Err(SnapshotError::TrailingData);
+        }
+        Ok(Snapshot { ip, relative_base, state, memory_limit, input, output, memory, disabled_opcodes, memory_policy })
+    }
+}
+
+fn state_to_byte(state: MachineState) -> u8 {
+    match state {
+        MachineState::Ready => 0,
+        MachineState::Running => 1,
+        MachineState::Done => 2,
+        MachineState::Waiting => 3,
+        MachineState::BudgetExhausted => 4,
+    }
+}
+
+fn state_from_byte(byte: u8) -> Result<MachineState, SnapshotError> {
+    match byte {
+        0 => Ok(MachineState::Ready),
+        1 => Ok(MachineState::Running),
+        2 => Ok(MachineState::Done),
+        3 => Ok(MachineState::Waiting),
+        4 => Ok(MachineState::BudgetExhausted),
+        _ => Err(SnapshotError::InvalidState(byte)),
+    }
+}
+
+/// Sets the lowest bit so that a policy can be told apart from no policy at all.
+fn policy_to_byte(policy: MemoryPolicy) -> u8 {
+    1 | (policy.strict_reads as u8) << 1 | (policy.read_only_image as u8) << 2 | (policy.audit as u8) << 3
+}
+
+fn policy_from_byte(byte: u8) -> Result<MemoryPolicy, SnapshotError> {
+    if byte & !0xf != 0 || byte & 1 == 0 {
+        return Err(SnapshotError::InvalidPolicy(byte));
+    }
+    Ok(MemoryPolicy { strict_reads: byte & 2 != 0, read_only_image: byte & 4 != 0, audit: byte & 8 != 0 })
+}
+
+fn write_unsigned(bytes: &mut Vec<u8>, mut value: u64) {
+    while value >= 0x80 {
+        bytes.push((value as u8) | 0x80);
+        value >>= 7;
+    }
+    bytes.push(value as u8);
+}
+
+fn write_values(bytes: &mut Vec<u8>, values: &[Value]) {
+    write_unsigned(bytes, values.len() as u64);
+    for &value in values {
+        write_unsigned(bytes, ((value << 1) ^ (value >> 63)) as u64);
+    }
+}
+
+struct Reader<'a>(&'a [u8]);
+
+impl Reader<'_> {
+    fn byte(&mut self) -> Result<u8, SnapshotError> {
+        let (&byte, rest) = self.0.split_first().ok_or(SnapshotError::Truncated)?;
+        self.0 = rest;
+        Ok(byte)
+    }
+
+    fn unsigned(&mut self) -> Result<u64, SnapshotError> {
+        let mut value = 0;
+        for shift in (0..64).step_by(7) {
+            let byte = self.byte()?;
+            value |= u64::from(byte & 0x7f) << shift;
+            if byte & 0x80 == 0 {
+                return Ok(value);
+            }
+        }
+        Err(SnapshotError::InvalidHeader)
+    }
+
+    fn values(&mut self) -> Result<Vec<Value>, SnapshotError> {
+        let count = self.unsigned()?;
+        // Don't trust the count for the allocation, every value takes at least one byte.
+        let mut values = Vec::with_capacity((count as usize).min(self.0.len()));
+        for _ in 0..count {
+            let zigzag = self.unsigned()?;
+            values.push(((zigzag >> 1) as Value) ^ -((zigzag & 1) as Value));
+        }
+        Ok(values)
+    }
+}
+
+#[cfg(test)]
+fn paused_machine() -> IntcodeMachine {
+    // Outputs 3, then waits for a value to add to it before halting, after writing way beyond the program.
+    let mut machine = IntcodeMachine::from_string("1101,3,0,100,4,100,3,101,1,100,101,1000000,4,1000000,99").unwrap();
+    machine.set_memory_limit(Some(1 << 24));
+    assert_eq!(machine.compute(), Ok(MachineState::Waiting));
+    machine.push_input(-5);
+    machine.push_input(i64::MIN);
+    machine
+}
+
+#[cfg(test)]
+fn assert_resumes(mut machine: IntcodeMachine) {
+    assert_eq!(machine.compute(), Ok(MachineState::Done));
+    assert_eq!(machine.get_outputs(), vec![3, -2]);
+    assert_eq!(machine.pending_input().iter().copied().collect::<Vec<Value>>(), vec![i64::MIN]);
+}
+
+#[test]
+fn test_binary_round_trip() {
+    let machine = paused_machine();
+    let snapshot = Snapshot::from_bytes(&machine.snapshot().to_bytes()).unwrap();
+    assert_eq!(snapshot, machine.snapshot());
+    assert_resumes(IntcodeMachine::from_snapshot(snapshot).unwrap());
+}
+
+#[test]
+fn test_json_round_trip() {
+    let machine = paused_machine();
+    let json = machine.snapshot().to_json();
+    assert!(json.starts_with(r#"{"format":"intcode-snapshot","version":2,"machine":{"ip":6,"#));
+    let snapshot = Snapshot::from_json(&json).unwrap();
+    assert_eq!(snapshot, machine.snapshot());
+    assert_resumes(IntcodeMachine::from_snapshot(snapshot).unwrap());
+}
+
+#[test]
+fn test_sparse_round_trip() {
+    let mut machine = IntcodeMachine::with_memory(crate::SparseMemory::from_values(&[1101,1,2,1000000000000,99]));
+    machine.set_memory_limit(None);
+    machine.compute().unwrap();
+    let restored: IntcodeMachine<crate::SparseMemory> = IntcodeMachine::from_snapshot(machine.snapshot()).unwrap();
+    assert_eq!(restored.get_memory(1_000_000_000_000), 3);
+    assert_eq!(restored.state(), MachineState::Done);
+    assert!(machine.snapshot().to_bytes().len() < 64);
+}
+
+#[test]
+fn test_version_check() {
+    let mut bytes = paused_machine().snapshot().to_bytes();
+    bytes[4] = 3;
+    assert!(matches!(Snapshot::from_bytes(&bytes), Err(SnapshotError::UnsupportedVersion(3))));
+    let json = paused_machine().snapshot().to_json().replace(r#""version":2"#, r#""version":3"#);
+    assert!(matches!(Snapshot::from_json(&json), Err(SnapshotError::UnsupportedVersion(3))));
+}
+
+#[test]
+fn test_invalid_snapshots() {
+    let bytes = paused_machine().snapshot().to_bytes();
+    assert!(matches!(Snapshot::from_bytes(b"PNG"), Err(SnapshotError::InvalidHeader)));
+    assert!(matches!(Snapshot::from_bytes(&bytes[..bytes.len() - 1]), Err(SnapshotError::Truncated)));
+    assert!(matches!(Snapshot::from_bytes(&[&bytes[..], &[0]].concat()), Err(SnapshotError::TrailingData)));
+    assert!(matches!(Snapshot::from_json("{}"), Err(SnapshotError::InvalidHeader)));
+    assert!(matches!(Snapshot::from_json(r#"{"format":"intcode-snapshot","version":1}"#), Err(SnapshotError::Json(_))));
+}
+
+#[test]
+fn test_invalid_segments() {
+    let mut snapshot = paused_machine().snapshot();
+    snapshot.memory.push((u64::MAX - 1, vec![1, 2]));
+    assert!(matches!(IntcodeMachine::<crate::VecMemory>::from_snapshot(snapshot),
+        Err(SnapshotError::InvalidSegment { start, len: 2 }) if start == u64::MAX - 1));
+    let mut snapshot = paused_machine().snapshot();
+    snapshot.memory.push((5, vec![1]));
+    assert!(matches!(IntcodeMachine::<crate::VecMemory>::from_snapshot(snapshot), Err(SnapshotError::InvalidSegment { start: 5, len: 1 })));
+    // Huge, but valid addresses.
+    let mut snapshot = paused_machine().snapshot();
+    snapshot.memory.push((u64::MAX - 1, vec![7]));
+    assert_eq!(IntcodeMachine::<crate::VecMemory>::from_snapshot(snapshot).unwrap().get_memory(u64::MAX - 1), 7);
+}
+
+#[test]
+fn test_version_1() {
+    let snapshot = paused_machine().snapshot();
+    let mut bytes = snapshot.to_bytes();
+    bytes[4] = 1;
+    bytes.truncate(bytes.len() - 2);
+    assert_eq!(Snapshot::from_bytes(&bytes).unwrap(), snapshot);
+    let json = snapshot.to_json().replace(r#""version":2"#, r#""version":1"#).replace(r#","disabled_opcodes":[],"memory_policy":null"#, "");
+    assert!(!json.contains("disabled_opcodes"));
+    assert_eq!(Snapshot::from_json(&json).unwrap(), snapshot);
+}
+
+#[test]
+fn test_disabled_opcodes_and_policy() {
+    // Writes to [20] and reads it back, then tries to write into the image with the disabled multiply.
+    let program: crate::Program = "1101,1,2,20,4,20,1102,2,3,0,99".parse().unwrap();
+    let mut machine = crate::extension::MachineBuilder::new(&program).disable(crate::Opcode::Mul).build();
+    machine.set_memory_policy(MemoryPolicy { strict_reads: true, read_only_image: true, audit: true });
+    machine.step().unwrap();
+    let snapshot = machine.snapshot();
+    assert_eq!(snapshot.disabled_opcodes, vec![2]);
+    assert_eq!(snapshot.memory_policy.as_ref().unwrap().initialized, vec![20]);
+    for snapshot in [Snapshot::from_bytes(&snapshot.to_bytes()).unwrap(), Snapshot::from_json(&snapshot.to_json()).unwrap()] {
+        let mut restored: IntcodeMachine = IntcodeMachine::from_snapshot(snapshot).unwrap();
+        restored.step().unwrap();
+        assert_eq!(restored.get_outputs(), vec![3]);
+        assert_eq!(restored.compute(), Err(crate::IntcodeError::UnknownOpcode { opcode: 2, ip: 6 }));
+        assert_eq!(restored.audit_log(), vec![
+            MemoryAccess { address: 20, kind: AccessKind::Read, ip: 4 },
+            MemoryAccess { address: 20, kind: AccessKind::Write, ip: 0 },
+        ]);
+    }
+
+    let mut snapshot = machine.snapshot();
+    snapshot.disabled_opcodes.push(200);
+    assert!(matches!(IntcodeMachine::<crate::VecMemory>::from_snapshot(snapshot), Err(SnapshotError::InvalidOpcode(200))));
+}
@@ -0,0 +1,536 @@
+use std::collections::{BTreeMap, BTreeSet, HashMap, VecDeque};
+use std::error::Error;
+use std::fmt;
+use std::ops::{Add, Mul, Range};
+use crate::{Address, DEFAULT_STEP_BUDGET, IntcodeError, OpValue, ParamMode, Program, Value, to_address};
+
+/// The value of a memory cell in terms of the unknown initial values of other cells.
+#[derive(Clone, Debug, PartialEq)]
+pub enum Expr {
+    Const(Value),
+    /// The initial value of the cell at this address.
+    Symbol(Address),
+    Add(Box<Expr>, Box<Expr>),
+    Mul(Box<Expr>, Box<Expr>),
+    LessThan(Box<Expr>, Box<Expr>),
+    Equals(Box<Expr>, Box<Expr>),
+    /// The value read from a symbolic address. What it is depends on the memory at the time of the read, so
+    /// it can't be evaluated or solved for.
+    Load(Box<Expr>),
+}
+
+impl Add for Expr {
+    type Output = Expr;
+
+    fn add(self, other: Expr) -> Expr {
+        match (self, other) {
+            (Expr::Const(a), Expr::Const(b)) if a.checked_add(b).is_some() => Expr::Const(a + b),
+            (Expr::Const(0), other) | (other, Expr::Const(0)) => other,
+            (a, b) => Expr::Add(Box::new(a), Box::new(b)),
+        }
+    }
+}
+
+impl Mul for Expr {
+    type Output = Expr;
+
+    fn mul(self, other: Expr) -> Expr {
+        match (self, other) {
+            (Expr::Const(a), Expr::Const(b)) if a.checked_mul(b).is_some() => Expr::Const(a * b),
+            (Expr::Const(0), _) | (_, Expr::Const(0)) => Expr::Const(0),
+            (Expr::Const(1), other) | (other, Expr::Const(1)) => other,
+            (a, b) => Expr::Mul(Box::new(a), Box::new(b)),
+        }
+    }
+}
+
+impl Expr {
+    /// Like `a + b`, but `None` where the interpreter would fail: if both are constants whose sum overflows.
+    pub fn checked_add(a: Expr, b: Expr) -> Option<Expr> {
+        match (&a, &b) {
+            (Expr::Const(a), Expr::Const(b)) => a.checked_add(*b).map(Expr::Const),
+            _ => Some(a + b),
+        }
+    }
+
+    pub fn checked_mul(a: Expr, b: Expr) -> Option<Expr> {
+        match (&a, &b) {
+            (Expr::Const(a), Expr::Const(b)) => a.checked_mul(*b).map(Expr::Const),
+            _ => Some(a * b),
+        }
+    }
+
+    pub fn less_than(a: Expr, b: Expr) -> Expr {
+        match (a, b) {
+            (Expr::Const(a), Expr::Const(b)) => Expr::Const((a < b) as Value),
+            (a, b) if a == b && !a.has_load() => Expr::Const(0),
+            (a, b) => Expr::LessThan(Box::new(a), Box::new(b)),
+        }
+    }
+
+    pub fn equals(a: Expr, b: Expr) -> Expr {
+        match (a, b) {
+            (Expr::Const(a), Expr::Const(b)) => Expr::Const((a == b) as Value),
+            (a, b) if a == b && !a.has_load() => Expr::Const(1),
+            (a, b) => Expr::Equals(Box::new(a), Box::new(b)),
+        }
+    }
+
+    pub fn as_const(&self) -> Option<Value> {
+        match self {
+            Expr::Const(value) => Some(*value),
+            _ => None,
+        }
+    }
+
+    /// Whether the expression reads memory. Two equal loads can still differ if memory changes between them.
+    pub fn has_load(&self) -> bool {
+        match self {
+            Expr::Const(_) | Expr::Symbol(_) => false,
+            Expr::Add(a, b) | Expr::Mul(a, b) | Expr::LessThan(a, b) | Expr::Equals(a, b) => a.has_load() || b.has_load(),
+            Expr::Load(_) => true,
+        }
+    }
+
+    /// The addresses of the cells the expression depends on.
+    pub fn symbols(&self) -> BTreeSet<Address> {
+        let mut symbols = BTreeSet::new();
+        self.collect_symbols(&mut symbols);
+        symbols
+    }
+
+    fn collect_symbols(&self, symbols: &mut BTreeSet<Address>) {
+        match self {
+            Expr::Const(_) => (),
+            Expr::Symbol(address) => {
+                symbols.insert(*address);
+            },
+            Expr::Add(a, b) | Expr::Mul(a, b) | Expr::LessThan(a, b) | Expr::Equals(a, b) => {
+                a.collect_symbols(symbols);
+                b.collect_symbols(symbols);
+            },
+            Expr::Load(address) => address.collect_symbols(symbols),
+        }
+    }
+
+    /// Evaluates the expression with the given initial values for the symbolic cells. Returns `None` if it
+    /// contains a `Load` or overflows.
+    pub fn eval(&self, symbol: &impl Fn(Address) -> Value) -> Option<Value> {
+        Some(match self {
+            Expr::Const(value) => *value,
+            Expr::Symbol(address) => symbol(*address),
+            Expr::Add(a, b) => a.eval(symbol)?.checked_add(b.eval(symbol)?)?,
+            Expr::Mul(a, b) => a.eval(symbol)?.checked_mul(b.eval(symbol)?)?,
+            Expr::LessThan(a, b) => (a.eval(symbol)? < b.eval(symbol)?) as Value,
+            Expr::Equals(a, b) => (a.eval(symbol)? == b.eval(symbol)?) as Value,
+            Expr::Load(_) => return None,
+        })
+    }
+}
+
+impl fmt::Display for Expr {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Expr::Const(value) => write!(f, "{}", value),
+            Expr::Symbol(address) => write!(f, "[{}]", address),
+            Expr::Add(a, b) => write!(f, "({} + {})", a, b),
+            Expr::Mul(a, b) => write!(f, "({} * {})", a, b),
+            Expr::LessThan(a, b) => write!(f, "({} < {})", a, b),
+            Expr::Equals(a, b) => write!(f, "({} == {})", a, b),
+            Expr::Load(address) => write!(f, "load({})", address),
+        }
+    }
+}
+
+/// A sum of symbols with constant factors, plus a constant.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Linear {
+    pub constant: Value,
+    /// Factors by symbol address. Symbols with a factor of 0 are left out.
+    pub terms: BTreeMap<Address, Value>,
+}
+
+impl Linear {
+    /// Returns `None` if the expression multiplies symbols with each other, compares them, or its factors overflow.
+    pub fn from_expr(expr: &Expr) -> Option<Linear> {
+        match expr {
+            Expr::Const(value) => Some(Linear { constant: *value, terms: BTreeMap::new() }),
+            Expr::Symbol(address) => Some(Linear { constant: 0, terms: vec![(*address, 1)].into_iter().collect() }),
+            Expr::Add(a, b) => {
+                let (mut sum, other) = (Linear::from_expr(a)?, Linear::from_expr(b)?);
+                sum.constant = sum.constant.checked_add(other.constant)?;
+                for (address, factor) in other.terms {
+                    let term = sum.terms.entry(address).or_insert(0);
+                    *term = term.checked_add(factor)?;
+                    if *term == 0 {
+                        sum.terms.remove(&address);
+                    }
+                }
+                Some(sum)
+            },
+            Expr::Mul(a, b) => {
+                let (a, b) = (Linear::from_expr(a)?, Linear::from_expr(b)?);
+                let (factor, mut product) = match (a.terms.is_empty(), b.terms.is_empty()) {
+                    (true, _) => (a.constant, b),
+                    (_, true) => (b.constant, a),
+                    _ => return None,
+                };
+                product.constant = product.constant.checked_mul(factor)?;
+                for term in product.terms.values_mut() {
+                    *term = term.checked_mul(factor)?;
+                }
+                product.terms.retain(|_, term| *term != 0);
+                Some(product)
+            },
+            Expr::LessThan(_, _) | Expr::Equals(_, _) | Expr::Load(_) => None,
+        }
+    }
+
+    /// Finds values for the cells, each within its range, that make the sum equal `target`. Like
+    /// `search::search_inputs`, returns the first solution with the first cell varying slowest. Symbols that
+    /// don't appear in `cells` make the equation unsolvable.
+    pub fn solve(&self, target: Value, cells: &[(Address, Range<Value>)]) -> Option<Vec<Value>> {
+        if self.terms.keys().any(|address| cells.iter().all(|(cell, _)| cell != address)) {
+            return None;
+        }
+        let factors: Vec<(i128, Range<Value>)> = cells.iter()
+            .map(|(address, range)| (self.terms.get(address).copied().unwrap_or(0).into(), range.clone()))
+            .collect();
+        let mut values = vec![];
+        if solve_from(&factors, i128::from(target) - i128::from(self.constant), &mut values) {
+            Some(values)
+        } else {
+            None
+        }
+    }
+}
+
+/// The smallest and largest value `factor * x` can take for `x` in `range`.
+fn bounds(factor: i128, range: &Range<Value>) -> (i128, i128) {
+    let (low, high) = (factor * i128::from(range.start), factor * (i128::from(range.end) - 1));
+    (low.min(high), low.max(high))
+}
+
+fn solve_from(factors: &[(i128, Range<Value>)], remaining: i128, values: &mut Vec<Value>) -> bool {
+    let ((factor, range), rest) = match factors.split_first() {
+        Some(first) => first,
+        None => return remaining == 0,
+    };
+    if range.start >= range.end {
+        return false;
+    }
+    if rest.is_empty() && *factor != 0 {
+        // The last value is determined by the others.
+        let value = remaining / factor;
+        if value * factor != remaining || value < range.start.into() || value >= range.end.into() {
+            return false;
+        }
+        values.push(value as Value);
+        return true;
+    }
+    let (rest_min, rest_max) = rest.iter()
+        .filter(|(_, range)| range.start < range.end)
+        .map(|(factor, range)| bounds(*factor, range))
+        .fold((0, 0), |(min, max), (low, high)| (min + low, max + high));
+    for value in range.clone() {
+        let left = remaining - factor * i128::from(value);
+        if left < rest_min || left > rest_max {
+            continue;
+        }
+        values.push(value);
+        if solve_from(rest, left, values) {
+            return true;
+        }
+        values.pop();
+    }
+    false
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum SymbolicError {
+    Machine(IntcodeError),
+    /// The instruction at `ip` depends on a symbol.
+    SymbolicInstruction { ip: Address },
+    /// The address an instruction writes to or jumps to depends on a symbol.
+    SymbolicAddress { ip: Address },
+    /// Whether a jump is taken depends on a symbol.
+    SymbolicBranch { ip: Address, condition: Expr },
+    WaitingForInput { ip: Address },
+    StepLimit,
+}
+
+impl fmt::Display for SymbolicError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Self::Machine(error) => write!(f, "{}", error),
+            Self::SymbolicInstruction { ip } => write!(f, "instruction at position {} is symbolic", ip),
+            Self::SymbolicAddress { ip } => write!(f, "instruction at position {} accesses a symbolic address", ip),
+            Self::SymbolicBranch { ip, condition } => write!(f, "jump at position {} depends on {}", ip, condition),
+            Self::WaitingForInput { ip } => write!(f, "instruction at position {} waits for input", ip),
+            Self::StepLimit => write!(f, "program did not halt within {} steps", DEFAULT_STEP_BUDGET),
+        }
+    }
+}
+
+impl Error for SymbolicError {}
+
+impl From<IntcodeError> for SymbolicError {
+    fn from(error: IntcodeError) -> Self {
+        Self::Machine(error)
+    }
+}
+
+/// Runs a program with the initial values of some memory cells unknown, tracking what every cell is computed
+/// from. Instructions, jump conditions and the addresses written to have to stay concrete, reading from a
+/// symbolic address gives an opaque `Expr::Load`.
+#[derive(Clone, Debug)]
+pub struct SymbolicMachine {
+    memory: HashMap<Address, Expr>,
+    ip: Address,
+    relative_base: Address,
+    input: VecDeque<Value>,
+    output: Vec<Expr>,
+}
+
+impl SymbolicMachine {
+    pub fn new(program: &Program) -> Self {
+        let memory = program.iter().enumerate()
+            .filter(|(_, value)| **value != 0)
+            .map(|(address, value)| (address as Address, Expr::Const(*value)))
+            .collect();
+        Self { memory, ip: 0, relative_base: 0, input: VecDeque::new(), output: vec![] }
+    }
+
+    /// Makes the initial value of the cell at `address` unknown.
+    pub fn unknown(&mut self, address: Address) {
+        self.memory.insert(address, Expr::Symbol(address));
+    }
+
+    pub fn set_memory(&mut self, address: Address, value: Value) {
+        self.memory.insert(address, Expr::Const(value));
+    }
+
+    pub fn push_input(&mut self, value: Value) {
+        self.input.push_back(value);
+    }
+
+    pub fn get_memory(&self, address: Address) -> Expr {
+        self.memory.get(&address).cloned().unwrap_or(Expr::Const(0))
+    }
+
+    pub fn outputs(&self) -> &[Expr] {
+        &self.output
+    }
+
+    /// Runs the program until it halts.
+    pub fn run(&mut self) -> Result<(), SymbolicError> {
+        for _ in 0..DEFAULT_STEP_BUDGET {
+            if !self.step()? {
+                return Ok(());
+            }
+        }
+        Err(SymbolicError::StepLimit)
+    }
+
+    /// Executes a single instruction. Returns `false` once the program has halted.
+    fn step(&mut self) -> Result<bool, SymbolicError> {
+        let ip = self.ip;
+        let instruction = self.get_memory(ip).as_const().ok_or(SymbolicError::SymbolicInstruction { ip })?;
+        let opvalue = OpValue::new(instruction)?;
+        match opvalue.opcode() {
+            1 => self.binary(&opvalue, Expr::checked_add)?,
+            2 => self.binary(&opvalue, Expr::checked_mul)?,
+            3 => {
+                let value = self.input.pop_front().ok_or(SymbolicError::WaitingForInput { ip })?;
+                self.write_param(&opvalue, 0, Expr::Const(value))?;
+                self.ip += 2;
+            },
+            4 => {
+                let value = self.read_param(&opvalue, 0)?;
+                self.output.push(value);
+                self.ip += 2;
+            },
+            opcode @ 5 | opcode @ 6 => {
+                let condition = self.read_param(&opvalue, 0)?;
+                let value = condition.as_const().ok_or(SymbolicError::SymbolicBranch { ip, condition })?;
+                self.ip = if (value != 0) == (opcode == 5) { self.concrete_address(&opvalue, 1)? } else { ip + 3 };
+            },
+            7 => self.binary(&opvalue, |a, b| Some(Expr::less_than(a, b)))?,
+            8 => self.binary(&opvalue, |a, b| Some(Expr::equals(a, b)))?,
+            9 => {
+                let offset = self.read_param(&opvalue, 0)?.as_const().ok_or(SymbolicError::SymbolicAddress { ip })?;
+                let relative_base = (self.relative_base as Value).checked_add(offset).ok_or(IntcodeError::Overflow { ip })?;
+                self.relative_base = to_address(relative_base).map_err(|_| IntcodeError::RelativeBaseUnderflow {
+                    relative_base: self.relative_base, offset,
+                })?;
+                self.ip += 2;
+            },
+            99 => return Ok(false),
+            _ => return Err(IntcodeError::UnknownOpcode { opcode: opvalue.opcode().into(), ip }.into()),
+        }
+        Ok(true)
+    }
+
+    /// `op` returns `None` if the operation overflows.
+    fn binary(&mut self, opvalue: &OpValue, op: impl Fn(Expr, Expr) -> Option<Expr>) -> Result<(), SymbolicError> {
+        let result = op(self.read_param(opvalue, 0)?, self.read_param(opvalue, 1)?).ok_or(IntcodeError::Overflow { ip: self.ip })?;
+        self.write_param(opvalue, 2, result)?;
+        self.ip += 4;
+        Ok(())
+    }
+
+    fn param(&self, pos: usize) -> Expr {
+        self.get_memory(self.ip + 1 + pos as Address)
+    }
+
+    /// The address a parameter refers to, or the expression for it if it isn't concrete.
+    fn param_address(&self, opvalue: &OpValue, pos: usize) -> Result<Result<Address, Expr>, IntcodeError> {
+        let param = self.param(pos);
+        let address = match opvalue.param_mode(pos) {
+            ParamMode::Position => param,
+            ParamMode::Relative => Expr::checked_add(param, Expr::Const(self.relative_base as Value)).ok_or(IntcodeError::Overflow { ip: self.ip })?,
+            ParamMode::Immediate => return Err(IntcodeError::ImmediateWrite { ip: self.ip }),
+        };
+        match address.as_const() {
+            Some(address) => Ok(Ok(to_address(address)?)),
+            None => Ok(Err(address)),
+        }
+    }
+
+    fn read_param(&self, opvalue: &OpValue, pos: usize) -> Result<Expr, SymbolicError> {
+        if opvalue.param_mode(pos) == ParamMode::Immediate {
+            return Ok(self.param(pos));
+        }
+        Ok(match self.param_address(opvalue, pos)? {
+            Ok(address) => self.get_memory(address),
+            Err(address) => Expr::Load(Box::new(address)),
+        })
+    }
+
+    fn concrete_address(&self, opvalue: &OpValue, pos: usize) -> Result<Address, SymbolicError> {
+        let target = self.read_param(opvalue, pos)?.as_const().ok_or(SymbolicError::SymbolicAddress { ip: self.ip })?;
+        Ok(to_address(target)?)
+    }
+
+    fn write_param(&mut self, opvalue: &OpValue, pos: usize, value: Expr) -> Result<(), SymbolicError> {
+        let address = self.param_address(opvalue, pos)?.map_err(|_| SymbolicError::SymbolicAddress { ip: self.ip })?;
+        self.memory.insert(address, value);
+        Ok(())
+    }
+}
+
+/// Runs `program` with the given cells unknown and solves for values within their ranges that leave `target`
+/// at `address`. Returns `None` if the cell doesn't depend linearly on the unknown cells, or if there is no
+/// solution.
+pub fn solve_linear(program: &Program, cells: &[(Address, Range<Value>)], address: Address, target: Value) -> Result<Option<Vec<Value>>, SymbolicError> {
+    let mut machine = SymbolicMachine::new(program);
+    for (cell, _) in cells {
+        machine.unknown(*cell);
+    }
+    machine.run()?;
+    Ok(Linear::from_expr(&machine.get_memory(address)).and_then(|linear| linear.solve(target, cells)))
+}
+
+#[cfg(test)]
+fn program(source: &str) -> Program {
+    source.parse().unwrap()
+}
+
+#[test]
+fn test_expression_tree() {
+    let mut machine = SymbolicMachine::new(&program("1,13,14,0,2,0,15,0,1102,3,4,16,99,0,0,0,0"));
+    machine.unknown(13);
+    machine.unknown(14);
+    machine.unknown(15);
+    machine.run().unwrap();
+    assert_eq!(machine.get_memory(0).to_string(), "(([13] + [14]) * [15])");
+    assert_eq!(machine.get_memory(16), Expr::Const(12));
+    assert_eq!(machine.get_memory(0).symbols(), vec![13, 14, 15].into_iter().collect());
+    assert_eq!(machine.get_memory(0).eval(&|address| address as Value), Some(405));
+}
+
+#[test]
+fn test_symbolic_reads() {
+    let mut machine = SymbolicMachine::new(&program("4,1,99"));
+    machine.unknown(1);
+    machine.run().unwrap();
+    assert_eq!(machine.outputs()[0].to_string(), "load([1])");
+    assert_eq!(machine.outputs()[0].eval(&|_| 0), None);
+}
+
+#[test]
+fn test_comparisons_and_outputs() {
+    let mut machine = SymbolicMachine::new(&program("7,11,12,13,1008,11,3,14,4,13,99,0,0,0,0"));
+    machine.unknown(11);
+    machine.unknown(12);
+    machine.run().unwrap();
+    assert_eq!(machine.outputs(), &[Expr::less_than(Expr::Symbol(11), Expr::Symbol(12))]);
+    assert_eq!(machine.get_memory(14).to_string(), "([11] == 3)");
+    assert_eq!(Linear::from_expr(&machine.get_memory(14)), None);
+}
+
+#[test]
+fn test_comparing_loads() {
+    // Loads from the address in [40] twice, with a write to [41] in between, and compares the results.
+    let source = "1001,40,0,5,1001,0,0,30,1101,7,0,41,1001,40,0,17,1001,0,0,31,8,30,31,32,99";
+    let mut machine = SymbolicMachine::new(&program(source));
+    machine.unknown(40);
+    machine.run().unwrap();
+    assert_eq!(machine.get_memory(32).to_string(), "(load([40]) == load([40]))");
+    let mut machine = crate::IntcodeMachine::from_string(source).unwrap();
+    machine.set_memory(ParamMode::Position, 40, 41).unwrap();
+    machine.compute().unwrap();
+    assert_eq!(machine.get_memory(32), 0);
+    assert_eq!(Expr::equals(Expr::Symbol(1), Expr::Symbol(1)), Expr::Const(1));
+}
+
+#[test]
+fn test_symbolic_errors() {
+    let mut machine = SymbolicMachine::new(&program("1005,3,0,99"));
+    machine.unknown(3);
+    assert_eq!(machine.run(), Err(SymbolicError::SymbolicBranch { ip: 0, condition: Expr::Symbol(3) }));
+    let mut machine = SymbolicMachine::new(&program("1,5,5,0,99,7"));
+    machine.unknown(3);
+    assert_eq!(machine.run(), Err(SymbolicError::SymbolicAddress { ip: 0 }));
+    let mut machine = SymbolicMachine::new(&program("1,6,6,4,0,99,0"));
+    machine.unknown(6);
+    assert_eq!(machine.run(), Err(SymbolicError::SymbolicInstruction { ip: 4 }));
+}
+
+#[test]
+fn test_linear_solve() {
+    // 3 * [1] - 2 * [2] + 5 = 20 has the solutions (5, 0), (7, 3), (9, 6), ...
+    let expr = Expr::Const(3) * Expr::Symbol(1) + Expr::Symbol(2) * Expr::Const(-2) + Expr::Const(5);
+    let linear = Linear::from_expr(&expr).unwrap();
+    assert_eq!(linear.terms, vec![(1, 3), (2, -2)].into_iter().collect());
+    assert_eq!(linear.solve(20, &[(1, 0..100), (2, 0..100)]), Some(vec![5, 0]));
+    assert_eq!(linear.solve(20, &[(1, 6..100), (2, 0..100)]), Some(vec![7, 3]));
+    assert_eq!(linear.solve(21, &[(1, 0..100), (2, 0..1)]), None);
+    assert_eq!(linear.solve(20, &[(1, 0..100)]), None);
+}
+
+#[test]
+fn test_matches_search() {
+    // Like day 2 programs, starts by adding the values at the addresses noun and verb, and then overwrites
+    // that result with 7 * noun + verb + 3.
+    let source = "1,0,0,3,1002,1,7,3,1,3,2,3,1001,3,3,0,99";
+    let cells = [(1, 0..100), (2, 0..100)];
+    let template = crate::IntcodeMachine::from_string(source).unwrap();
+    for target in [3, 50, 400, 795, 796, -1].iter() {
+        let expected = crate::search::search_inputs(&template, &cells, 0, *target, DEFAULT_STEP_BUDGET);
+        assert_eq!(solve_linear(&program(source), &cells, 0, *target), Ok(expected), "target {}", target);
+    }
+}
+
+#[test]
+fn test_overflow() {
+    for (source, ip) in [("1101,9223372036854775807,1,0,99", 0), ("1102,4611686018427387904,2,0,99", 0),
+        ("109,9223372036854775807,109,1,99", 2), ("109,9223372036854775807,204,1,99", 2)].iter() {
+        let mut machine = SymbolicMachine::new(&program(source));
+        assert_eq!(machine.run(), Err(SymbolicError::Machine(IntcodeError::Overflow { ip: *ip })), "{}", source);
+    }
+    let sum = Expr::Const(i64::MAX) + Expr::Const(1);
+    assert_eq!(sum.to_string(), "(9223372036854775807 + 1)");
+    assert_eq!(sum.eval(&|_| 0), None);
+    assert_eq!((Expr::Symbol(0) * Expr::Const(2)).eval(&|_| i64::MAX), None);
+}
@@ -0,0 +1,173 @@
+use std::collections::HashMap;
+use std::fmt;
+use std::io::Write;
+use itertools::Itertools;
+use crate::{Address, Opcode, Value};
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct TraceEvent<V = Value> {
+    pub ip: Address,
+    pub relative_base: Address,
+    pub opcode: Opcode,
+    pub(crate) params: [V; 3],
+    pub write: Option<(Address, V)>,
+}
+
+impl<V> TraceEvent<V> {
+    /// The parameters as the instruction used them: values for parameters that are read, and the address for
+    /// the parameter that is written to.
+    pub fn params(&self) -> &[V] {
+        &self.params[..self.opcode.arity()]
+    }
+}
+
+impl<V: fmt::Display> fmt::Display for TraceEvent<V> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{:5}: {}", self.ip, self.opcode.mnemonic())?;
+        if !self.params().is_empty() {
+            write!(f, " {}", self.params().iter().join(", "))?;
+        }
+        if let Some((address, value)) = &self.write {
+            write!(f, " ; [{}] <- {}", address, value)?;
+        }
+        Ok(())
+    }
+}
+
+/// Receives every instruction an `IntcodeMachine` executes.
+pub trait Tracer<V = Value> {
+    /// When this is `false`, the machine doesn't even collect the information for a `TraceEvent`.
+    const ENABLED: bool = true;
+
+    fn trace(&mut self, event: &TraceEvent<V>);
+}
+
+pub struct NoTracer;
+
+impl<V> Tracer<V> for NoTracer {
+    const ENABLED: bool = false;
+
+    fn trace(&mut self, _event: &TraceEvent<V>) {}
+}
+
+impl<V: Clone> Tracer<V> for Vec<TraceEvent<V>> {
+    fn trace(&mut self, event: &TraceEvent<V>) {
+        self.push(event.clone());
+    }
+}
+
+impl<V, F: FnMut(&TraceEvent<V>)> Tracer<V> for F {
+    fn trace(&mut self, event: &TraceEvent<V>) {
+        self(event)
+    }
+}
+
+/// Writes one line per instruction, e.g. to stderr or a file.
+pub struct WriteTracer<W: Write>(pub W);
+
+impl<V: fmt::Display, W: Write> Tracer<V> for WriteTracer<W> {
+    fn trace(&mut self, event: &TraceEvent<V>) {
+        // A broken trace shouldn't stop the machine.
+        let _ = writeln!(self.0, "{}", event);
+    }
+}
+
+#[derive(Clone, Debug, Default)]
+pub struct Profiler {
+    total: u64,
+    by_address: HashMap<Address, (Opcode, u64)>,
+    by_opcode: HashMap<Opcode, u64>,
+}
+
+impl Profiler {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn total(&self) -> u64 {
+        self.total
+    }
+
+    pub fn count_at(&self, address: Address) -> u64 {
+        self.by_address.get(&address).map_or(0, |&(_, count)| count)
+    }
+
+    pub fn count_of(&self, opcode: Opcode) -> u64 {
+        self.by_opcode.get(&opcode).copied().unwrap_or(0)
+    }
+
+    /// The `count` most executed instruction addresses, most executed first.
+    pub fn hot_spots(&self, count: usize) -> Vec<(Address, Opcode, u64)> {
+        self.by_address.iter()
+            .map(|(&address, &(opcode, executed))| (address, opcode, executed))
+            .sorted_by_key(|&(address, _, executed)| (std::cmp::Reverse(executed), address))
+            .take(count)
+            .collect()
+    }
+
+    pub fn report(&self, hot_spots: usize) -> String {
+        let percent = |count: u64| 100.0 * count as f64 / self.total.max(1) as f64;
+        let mut report = format!("{} instructions executed\n\nBy opcode:\n", self.total);
+        for (opcode, count) in self.by_opcode.iter().sorted_by_key(|&(opcode, count)| (std::cmp::Reverse(*count), opcode.code())) {
+            report += &format!("  {:<4} {:>12} {:>6.2}%\n", opcode.mnemonic(), count, percent(*count));
+        }
+        report += "\nHot spots:\n";
+        for (address, opcode, count) in self.hot_spots(hot_spots) {
+            report += &format!("  {:5}: {:<4} {:>12} {:>6.2}%\n", address, opcode.mnemonic(), count, percent(count));
+        }
+        report
+    }
+}
+
+impl<V> Tracer<V> for Profiler {
+    fn trace(&mut self, event: &TraceEvent<V>) {
+        self.total += 1;
+        self.by_address.entry(event.ip).or_insert((event.opcode, 0)).1 += 1;
+        *self.by_opcode.entry(event.opcode).or_insert(0) += 1;
+    }
+}
+
+#[test]
+fn test_trace_events() {
+    let mut machine = crate::IntcodeMachine::from_string("1,9,10,3,2,3,11,0,99,30,40,50").unwrap();
+    let mut events = vec![];
+    machine.compute_traced(&mut events).unwrap();
+    assert_eq!(events.iter().map(|event| event.to_string()).collect::<Vec<String>>(), vec![
+        "    0: add 30, 40, 3 ; [3] <- 70",
+        "    4: mul 70, 50, 0 ; [0] <- 3500",
+        "    8: hlt",
+    ]);
+}
+
+#[test]
+fn test_trace_relative_and_input() {
+    let mut machine = crate::IntcodeMachine::from_string("109,10,203,-3,204,-3,99").unwrap();
+    machine.push_input(42);
+    let mut events = vec![];
+    machine.compute_traced(&mut events).unwrap();
+    assert_eq!(events[1].params(), &[7]);
+    assert_eq!(events[1].write, Some((7, 42)));
+    assert_eq!(events[2].params(), &[42]);
+    assert_eq!(events[2].relative_base, 10);
+}
+
+#[test]
+fn test_trace_skips_waiting_input() {
+    let mut machine = crate::IntcodeMachine::from_string("3,0,99").unwrap();
+    let mut count = 0;
+    machine.compute_traced(&mut |_: &TraceEvent| count += 1).unwrap();
+    assert_eq!(count, 0);
+}
+
+#[test]
+fn test_profiler() {
+    // Counts down from 3, outputting every value.
+    let mut machine = crate::IntcodeMachine::from_string("1101,3,0,14,4,14,1001,14,-1,14,1005,14,4,99,0").unwrap();
+    let mut profiler = Profiler::new();
+    machine.compute_traced(&mut profiler).unwrap();
+    assert_eq!(profiler.total(), 11);
+    assert_eq!(profiler.count_at(4), 3);
+    assert_eq!(profiler.count_of(Opcode::Add), 4);
+    assert_eq!(profiler.hot_spots(2), vec![(4, Opcode::Output, 3), (6, Opcode::Add, 3)]);
+    assert!(profiler.report(3).contains("   10: jt              3  27.27%"));
+}